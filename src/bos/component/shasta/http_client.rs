@@ -0,0 +1,119 @@
+pub mod v2 {
+    use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    use crate::error::Error;
+
+    #[derive(Serialize, Deserialize, Debug, Clone, Default)]
+    pub struct ComponentStatus {
+        /// powering_on, powering_off, configuring or empty if BOS is not working on the node
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub phase: Option<String>,
+        /// eg power_on_pending, power_off_gracefully_called, configuring, stable, failed, on_hold
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub status: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub status_override: Option<String>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, Default)]
+    pub struct LastAction {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub action: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub last_updated: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub failed: Option<bool>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, Default)]
+    pub struct Component {
+        pub id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub enabled: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub error: Option<String>,
+        /// BOS session currently managing the node, empty if none
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub session: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub status: Option<ComponentStatus>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub last_action: Option<LastAction>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub retry_policy: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub actual_state: Option<Value>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub desired_state: Option<Value>,
+    }
+
+    impl Component {
+        pub fn get_phase(&self) -> &str {
+            self.status
+                .as_ref()
+                .and_then(|status| status.phase.as_deref())
+                .unwrap_or_default()
+        }
+
+        /// Status override takes precedence over status (eg 'on_hold')
+        pub fn get_status(&self) -> &str {
+            self.status
+                .as_ref()
+                .and_then(|status| {
+                    status
+                        .status_override
+                        .as_deref()
+                        .filter(|status_override| !status_override.is_empty())
+                        .or(status.status.as_deref())
+                })
+                .unwrap_or_default()
+        }
+
+        pub fn get_error(&self) -> Option<&str> {
+            self.error.as_deref().filter(|error| !error.is_empty())
+        }
+    }
+
+    /// Get BOS components. Ref --> https://github.com/Cray-HPE/docs-csm/blob/release/1.5/api/bos.md#get__v2_components
+    pub async fn get(
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+        ids_opt: Option<&str>,
+        session_opt: Option<&str>,
+    ) -> Result<Vec<Component>, Error> {
+        let client_builder = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(shasta_root_cert)?);
+
+        // Build client
+        let client = if let Ok(socks5_env) = std::env::var("SOCKS5") {
+            // socks5 proxy
+            log::debug!("SOCKS5 enabled");
+            let socks5proxy = reqwest::Proxy::all(socks5_env)?;
+
+            // rest client to authenticate
+            client_builder.proxy(socks5proxy).build()?
+        } else {
+            client_builder.build()?
+        };
+
+        let api_url = shasta_base_url.to_string() + "/bos/v2/components";
+
+        let response = client
+            .get(api_url)
+            .query(&[("ids", ids_opt), ("session", session_opt)])
+            .bearer_auth(shasta_token)
+            .send()
+            .await
+            .map_err(Error::NetError)?;
+
+        if response.status().is_success() {
+            response.json().await.map_err(Error::NetError)
+        } else {
+            let payload = response.json::<Value>().await.map_err(Error::NetError)?;
+
+            Err(Error::CsmError(payload))
+        }
+    }
+}
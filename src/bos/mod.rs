@@ -1,3 +1,4 @@
 pub mod common;
+pub mod component;
 pub mod session;
 pub mod template;
@@ -1 +1,2 @@
+pub mod mesa;
 pub mod shasta;
@@ -0,0 +1,515 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::{
+    bos,
+    bos::{
+        component::shasta::http_client::v2::Component,
+        session::shasta::http_client::v2::{Status, StatusLabel},
+    },
+    error::Error,
+};
+
+/// Max number of xnames per request when fetching BOS components
+const NUM_XNAMES_PER_REQUEST: usize = 60;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum NodeOutcome {
+    /// BOS finished with the node and reported no error
+    Succeeded,
+    Failed {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    /// Node did not change phase/status for longer than the stuck threshold
+    Stuck { phase: String, status: String },
+    /// BOS was still working on the node when the tracker stopped
+    Incomplete { phase: String, status: String },
+    /// BOS session completed but BOS never acted on the node (node not managed by the BOS
+    /// session and no BOS action since the BOS session started)
+    NotTargeted,
+}
+
+/// Snapshot of a BOS session progress
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct BosSessionProgress {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_status: Option<String>,
+    /// phase (eg powering_on, configuring, "" if BOS is done with the node) --> number of nodes
+    pub phase_count_map: BTreeMap<String, usize>,
+    /// Nodes which phase/status did not change for longer than the stuck threshold
+    pub stuck_xname_vec: Vec<String>,
+    pub failed_xname_vec: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BosSessionReport {
+    pub session_name: String,
+    /// True if the BOS session completed before the timeout
+    pub is_session_complete: bool,
+    pub node_outcome_map: BTreeMap<String, NodeOutcome>,
+    pub progress: BosSessionProgress,
+}
+
+impl BosSessionReport {
+    /// True if the BOS session completed and all nodes succeeded. A BOS session without nodes is
+    /// not a success since there is nothing to tell it did anything
+    pub fn is_success(&self) -> bool {
+        self.is_session_complete
+            && !self.node_outcome_map.is_empty()
+            && self
+                .node_outcome_map
+                .values()
+                .all(|outcome| *outcome == NodeOutcome::Succeeded)
+    }
+}
+
+/// Follows a BOS v2 session (boot, reboot or shutdown) till it completes, aggregating the BOS
+/// components phases. Works like `pcs::transitions::http_client::wait_to_complete` but for BOS
+/// sessions
+///
+/// eg:
+///
+/// let report = BosSessionTracker::new(shasta_token, shasta_base_url, shasta_root_cert, &bos_session_name)
+///     .stuck_threshold(Duration::from_secs(900))
+///     .wait_with_progress(|progress| println!("{:?}", progress.phase_count_map))
+///     .await?;
+pub struct BosSessionTracker {
+    shasta_token: String,
+    shasta_base_url: String,
+    shasta_root_cert: Vec<u8>,
+    session_name: String,
+    xname_vec: Vec<String>,
+    poll_interval: Duration,
+    timeout: Duration,
+    stuck_threshold: Duration,
+}
+
+/// Last phase/status seen for a node and since when
+struct NodeState {
+    phase: String,
+    status: String,
+    since: Instant,
+}
+
+impl BosSessionTracker {
+    pub fn new(
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+        session_name: &str,
+    ) -> Self {
+        Self {
+            shasta_token: shasta_token.to_string(),
+            shasta_base_url: shasta_base_url.to_string(),
+            shasta_root_cert: shasta_root_cert.to_vec(),
+            session_name: session_name.to_string(),
+            xname_vec: Vec::new(),
+            poll_interval: Duration::from_secs(10),
+            timeout: Duration::from_secs(3600),
+            stuck_threshold: Duration::from_secs(1200),
+        }
+    }
+
+    /// Nodes targeted by the BOS session. If not provided, the nodes BOS assigns to the session
+    /// while it runs are tracked
+    pub fn xname_vec(mut self, xname_vec: &[String]) -> Self {
+        self.xname_vec = xname_vec.to_vec();
+        self
+    }
+
+    /// Defaults to 10 seconds
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Max time to wait for the BOS session to complete. Defaults to 1 hour
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Time a node can stay in the same phase/status before it is considered stuck. Defaults
+    /// to 20 minutes
+    pub fn stuck_threshold(mut self, stuck_threshold: Duration) -> Self {
+        self.stuck_threshold = stuck_threshold;
+        self
+    }
+
+    pub async fn wait(self) -> Result<BosSessionReport, Error> {
+        self.wait_with_progress(|progress| {
+            log::info!(
+                "BOS session status: {:?}; phases: {:?}",
+                progress.session_status,
+                progress.phase_count_map
+            )
+        })
+        .await
+    }
+
+    /// Waits till the BOS session completes or the timeout is reached. `on_progress` is called
+    /// after each poll
+    pub async fn wait_with_progress<F>(self, mut on_progress: F) -> Result<BosSessionReport, Error>
+    where
+        F: FnMut(&BosSessionProgress),
+    {
+        let deadline = Instant::now() + self.timeout;
+
+        let mut node_state_map: HashMap<String, NodeState> = HashMap::new();
+
+        // Nodes seen managed by the BOS session
+        let mut xname_in_session_set: HashSet<String> = HashSet::new();
+
+        loop {
+            let session_status_opt = self.get_session_status().await?;
+
+            let is_session_complete = matches!(
+                session_status_opt,
+                Some(Status {
+                    status: StatusLabel::Complete,
+                    ..
+                })
+            );
+
+            let component_vec = self.get_component_vec(&node_state_map).await?;
+
+            let now = Instant::now();
+
+            for component in &component_vec {
+                if component.session.as_deref() == Some(self.session_name.as_str()) {
+                    xname_in_session_set.insert(component.id.clone());
+                }
+
+                let phase = component.get_phase().to_string();
+                let status = component.get_status().to_string();
+
+                match node_state_map.get_mut(&component.id) {
+                    Some(node_state)
+                        if node_state.phase == phase && node_state.status == status => {}
+                    Some(node_state) => {
+                        node_state.phase = phase;
+                        node_state.status = status;
+                        node_state.since = now;
+                    }
+                    None => {
+                        node_state_map.insert(
+                            component.id.clone(),
+                            NodeState {
+                                phase,
+                                status,
+                                since: now,
+                            },
+                        );
+                    }
+                }
+            }
+
+            let progress = self.get_progress(
+                session_status_opt.as_ref().map(|status| &status.status),
+                &component_vec,
+                &node_state_map,
+                now,
+            );
+
+            on_progress(&progress);
+
+            if is_session_complete || now + self.poll_interval > deadline {
+                if !is_session_complete {
+                    log::warn!(
+                        "BOS session '{}' did not complete before the timeout",
+                        self.session_name
+                    );
+                }
+
+                let node_outcome_map = component_vec
+                    .iter()
+                    .map(|component| {
+                        let is_acted_on = xname_in_session_set.contains(&component.id)
+                            || session_status_opt.as_ref().is_some_and(|status| {
+                                is_last_action_since(component, &status.start_time)
+                            });
+
+                        (
+                            component.id.clone(),
+                            get_node_outcome(
+                                component,
+                                is_session_complete,
+                                progress.stuck_xname_vec.contains(&component.id),
+                                is_acted_on,
+                            ),
+                        )
+                    })
+                    .collect();
+
+                return Ok(BosSessionReport {
+                    session_name: self.session_name.clone(),
+                    is_session_complete,
+                    node_outcome_map,
+                    progress,
+                });
+            }
+
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    async fn get_session_status(&self) -> Result<Option<Status>, Error> {
+        Ok(bos::session::shasta::http_client::v2::get(
+            &self.shasta_token,
+            &self.shasta_base_url,
+            &self.shasta_root_cert,
+            Some(&self.session_name),
+        )
+        .await?
+        .pop()
+        .and_then(|bos_session| bos_session.status))
+    }
+
+    /// BOS components of the nodes targeted, or the ones managed by the BOS session and the
+    /// ones seen in previous polls if the target nodes were not provided
+    async fn get_component_vec(
+        &self,
+        node_state_map: &HashMap<String, NodeState>,
+    ) -> Result<Vec<Component>, Error> {
+        let mut xname_vec = self.xname_vec.clone();
+
+        if xname_vec.is_empty() {
+            let component_in_session_vec = bos::component::shasta::http_client::v2::get(
+                &self.shasta_token,
+                &self.shasta_base_url,
+                &self.shasta_root_cert,
+                None,
+                Some(&self.session_name),
+            )
+            .await?;
+
+            xname_vec = component_in_session_vec
+                .into_iter()
+                .map(|component| component.id)
+                .chain(node_state_map.keys().cloned())
+                .collect();
+
+            xname_vec.sort();
+            xname_vec.dedup();
+        }
+
+        let mut component_vec = Vec::new();
+
+        for xname_batch in xname_vec.chunks(NUM_XNAMES_PER_REQUEST) {
+            component_vec.extend(
+                bos::component::shasta::http_client::v2::get(
+                    &self.shasta_token,
+                    &self.shasta_base_url,
+                    &self.shasta_root_cert,
+                    Some(&xname_batch.join(",")),
+                    None,
+                )
+                .await?,
+            );
+        }
+
+        Ok(component_vec)
+    }
+
+    fn get_progress(
+        &self,
+        session_status_opt: Option<&StatusLabel>,
+        component_vec: &[Component],
+        node_state_map: &HashMap<String, NodeState>,
+        now: Instant,
+    ) -> BosSessionProgress {
+        let mut progress = BosSessionProgress {
+            session_status: session_status_opt.map(|status| {
+                match status {
+                    StatusLabel::Pending => "pending",
+                    StatusLabel::Running => "running",
+                    StatusLabel::Complete => "complete",
+                }
+                .to_string()
+            }),
+            ..Default::default()
+        };
+
+        for component in component_vec {
+            *progress
+                .phase_count_map
+                .entry(component.get_phase().to_string())
+                .or_default() += 1;
+
+            if is_failed(component) {
+                progress.failed_xname_vec.push(component.id.clone());
+            } else if !component.get_phase().is_empty()
+                && node_state_map.get(&component.id).is_some_and(|node_state| {
+                    now.duration_since(node_state.since) > self.stuck_threshold
+                })
+            {
+                progress.stuck_xname_vec.push(component.id.clone());
+            }
+        }
+
+        progress
+    }
+}
+
+fn is_failed(component: &Component) -> bool {
+    component.get_status() == "failed"
+}
+
+/// True if BOS acted on the node (eg powered it on) after `start_time`
+fn is_last_action_since(component: &Component, start_time: &str) -> bool {
+    let parse = |date_time: &str| {
+        chrono::DateTime::parse_from_rfc3339(date_time)
+            .map(|date_time| date_time.naive_utc())
+            .or_else(|_| chrono::NaiveDateTime::parse_from_str(date_time, "%Y-%m-%dT%H:%M:%S%.f"))
+            .ok()
+    };
+
+    match (
+        component
+            .last_action
+            .as_ref()
+            .and_then(|last_action| last_action.last_updated.as_deref())
+            .and_then(parse),
+        parse(start_time),
+    ) {
+        (Some(last_updated), Some(start_time)) => last_updated >= start_time,
+        _ => false,
+    }
+}
+
+/// `is_acted_on` tells whether BOS worked on the node in this BOS session, nodes BOS never
+/// touched also end up with an empty phase and no error
+fn get_node_outcome(
+    component: &Component,
+    is_session_complete: bool,
+    is_stuck: bool,
+    is_acted_on: bool,
+) -> NodeOutcome {
+    let phase = component.get_phase().to_string();
+    let status = component.get_status().to_string();
+
+    if is_failed(component) {
+        NodeOutcome::Failed {
+            error: component.get_error().map(str::to_string),
+        }
+    } else if is_session_complete && phase.is_empty() {
+        match component.get_error() {
+            Some(error) => NodeOutcome::Failed {
+                error: Some(error.to_string()),
+            },
+            None if is_acted_on => NodeOutcome::Succeeded,
+            None => NodeOutcome::NotTargeted,
+        }
+    } else if is_stuck {
+        NodeOutcome::Stuck { phase, status }
+    } else {
+        NodeOutcome::Incomplete { phase, status }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::test_fixtures;
+
+    fn component(phase: &str, status: &str, error: &str) -> Component {
+        test_fixtures::bos_component(
+            "x1000c1s7b0n0",
+            phase,
+            status,
+            error,
+            "",
+            Some("2024-05-02T10:00:00"),
+        )
+    }
+
+    #[test]
+    fn test_get_node_outcome() {
+        assert_eq!(
+            get_node_outcome(&component("", "stable", ""), true, false, true),
+            NodeOutcome::Succeeded
+        );
+        assert_eq!(
+            get_node_outcome(&component("", "stable", ""), true, false, false),
+            NodeOutcome::NotTargeted
+        );
+        assert_eq!(
+            get_node_outcome(
+                &component("", "failed", "power on failed"),
+                true,
+                false,
+                false
+            ),
+            NodeOutcome::Failed {
+                error: Some("power on failed".to_string())
+            }
+        );
+        assert_eq!(
+            get_node_outcome(
+                &component("powering_on", "power_on_called", ""),
+                false,
+                true,
+                true
+            ),
+            NodeOutcome::Stuck {
+                phase: "powering_on".to_string(),
+                status: "power_on_called".to_string()
+            }
+        );
+        assert_eq!(
+            get_node_outcome(
+                &component("configuring", "configuring", ""),
+                false,
+                false,
+                true
+            ),
+            NodeOutcome::Incomplete {
+                phase: "configuring".to_string(),
+                status: "configuring".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_is_success() {
+        let mut report = BosSessionReport {
+            session_name: "zinal-reboot".to_string(),
+            is_session_complete: true,
+            node_outcome_map: BTreeMap::new(),
+            progress: BosSessionProgress::default(),
+        };
+
+        assert!(!report.is_success());
+
+        report
+            .node_outcome_map
+            .insert("x1000c1s7b0n0".to_string(), NodeOutcome::Succeeded);
+
+        assert!(report.is_success());
+
+        report.node_outcome_map.insert(
+            "x1000c1s7b0n1".to_string(),
+            NodeOutcome::Failed { error: None },
+        );
+
+        assert!(!report.is_success());
+    }
+
+    #[test]
+    fn test_is_last_action_since() {
+        let component = component("", "stable", "");
+
+        assert!(is_last_action_since(&component, "2024-05-02T09:00:00Z"));
+        assert!(!is_last_action_since(&component, "2024-05-02T11:00:00"));
+        assert!(!is_last_action_since(
+            &test_fixtures::bos_component("x1000c1s7b0n0", "", "stable", "", "", None),
+            "2024-05-02T09:00:00"
+        ));
+    }
+}
@@ -1,3 +1,6 @@
+pub mod builder;
+pub mod converter;
 pub mod http_client;
 pub mod r#struct;
 pub mod utils;
+pub mod validator;
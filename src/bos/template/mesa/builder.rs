@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+
+use crate::ims::image::r#struct::Image;
+
+use super::{
+    r#struct::v2::{BootSet, BosSessionTemplate, Cfs},
+    validator::{self, BosSessionTemplateValidationError},
+};
+
+/// Same rootfs provider as `BosSessionTemplate::new_for_hsm_group`
+pub const DEFAULT_ROOTFS_PROVIDER: &str = "cpss3";
+pub const DEFAULT_ROOTFS_PROVIDER_PASSTHROUGH: &str = "dvs:api-gw-service-nmn.local:300:nmn0";
+
+/// Builder for a BOS v2 boot set
+#[derive(Debug, Clone)]
+pub struct BootSetBuilder {
+    boot_set: BootSet,
+}
+
+impl BootSetBuilder {
+    /// Boot set booting the IMS image which manifest is in `path` (eg
+    /// 's3://boot-images/<image id>/manifest.json')
+    pub fn new(path: &str) -> Self {
+        Self {
+            boot_set: BootSet {
+                name: None,
+                path: Some(path.to_string()),
+                cfs: None,
+                r#type: Some("s3".to_string()),
+                etag: None,
+                kernel_parameters: None,
+                node_list: None,
+                node_roles_groups: None,
+                node_groups: None,
+                arch: None,
+                rootfs_provider: Some(DEFAULT_ROOTFS_PROVIDER.to_string()),
+                rootfs_provider_passthrough: Some(DEFAULT_ROOTFS_PROVIDER_PASSTHROUGH.to_string()),
+            },
+        }
+    }
+
+    /// Boot set booting an IMS image. Takes path, etag and arch from the IMS image
+    pub fn from_image(image: &Image) -> Self {
+        let link = image.link.clone().unwrap_or_default();
+
+        let mut boot_set_builder = Self::new(&link.path);
+
+        boot_set_builder.boot_set.name = Some(image.name.clone());
+        boot_set_builder.boot_set.etag = link.etag;
+
+        if !link.r#type.is_empty() {
+            boot_set_builder.boot_set.r#type = Some(link.r#type);
+        }
+
+        if let Some(arch) = image.arch.as_deref() {
+            boot_set_builder.boot_set.arch = Some(get_bos_arch(arch));
+        }
+
+        boot_set_builder
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.boot_set.name = Some(name.to_string());
+        self
+    }
+
+    pub fn etag(mut self, etag: &str) -> Self {
+        self.boot_set.etag = Some(etag.to_string());
+        self
+    }
+
+    pub fn kernel_parameters(mut self, kernel_parameters: &str) -> Self {
+        self.boot_set.kernel_parameters = Some(kernel_parameters.to_string());
+        self
+    }
+
+    pub fn node_list(mut self, xname_vec: &[String]) -> Self {
+        self.boot_set
+            .node_list
+            .get_or_insert_with(Vec::new)
+            .extend_from_slice(xname_vec);
+        self
+    }
+
+    pub fn node_groups(mut self, group_vec: &[String]) -> Self {
+        self.boot_set
+            .node_groups
+            .get_or_insert_with(Vec::new)
+            .extend_from_slice(group_vec);
+        self
+    }
+
+    pub fn node_roles_groups(mut self, role_vec: &[String]) -> Self {
+        self.boot_set
+            .node_roles_groups
+            .get_or_insert_with(Vec::new)
+            .extend_from_slice(role_vec);
+        self
+    }
+
+    /// BOS arch (X86, ARM, Other)
+    pub fn arch(mut self, arch: &str) -> Self {
+        self.boot_set.arch = Some(arch.to_string());
+        self
+    }
+
+    pub fn rootfs_provider(mut self, rootfs_provider: &str, passthrough: &str) -> Self {
+        self.boot_set.rootfs_provider = Some(rootfs_provider.to_string());
+        self.boot_set.rootfs_provider_passthrough = Some(passthrough.to_string());
+        self
+    }
+
+    /// CFS configuration for the nodes in this boot set, overrides the session template one
+    pub fn configuration(mut self, configuration_name: &str) -> Self {
+        self.boot_set.cfs = Some(Cfs {
+            configuration: Some(configuration_name.to_string()),
+        });
+        self
+    }
+
+    pub fn build(self) -> BootSet {
+        self.boot_set
+    }
+}
+
+/// Builder for BOS v2 session templates. Templates are validated when built, `build_unchecked`
+/// only runs the checks which do not need CSM, `build` also checks the IMS manifests, CFS
+/// configurations and HSM entities the template refers to exist
+///
+/// eg:
+///
+/// let bos_session_template = BosSessionTemplateBuilder::new("compute-template")
+///     .configuration("compute-config")
+///     .boot_set(
+///         "compute",
+///         BootSetBuilder::from_image(&image)
+///             .kernel_parameters(kernel_params)
+///             .node_groups(&["compute".to_string()]),
+///     )
+///     .build(shasta_token, shasta_base_url, shasta_root_cert)
+///     .await?;
+#[derive(Debug, Clone)]
+pub struct BosSessionTemplateBuilder {
+    name: String,
+    tenant: Option<String>,
+    description: Option<String>,
+    enable_cfs: bool,
+    configuration_name: Option<String>,
+    boot_set_map: HashMap<String, BootSet>,
+}
+
+impl BosSessionTemplateBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            tenant: None,
+            description: None,
+            enable_cfs: true,
+            configuration_name: None,
+            boot_set_map: HashMap::new(),
+        }
+    }
+
+    pub fn tenant(mut self, tenant: &str) -> Self {
+        self.tenant = Some(tenant.to_string());
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// CFS configuration applied to the nodes after booting
+    pub fn configuration(mut self, configuration_name: &str) -> Self {
+        self.configuration_name = Some(configuration_name.to_string());
+        self
+    }
+
+    /// Defaults to true
+    pub fn enable_cfs(mut self, enable_cfs: bool) -> Self {
+        self.enable_cfs = enable_cfs;
+        self
+    }
+
+    /// Adds a boot set, replacing any boot set with the same name
+    pub fn boot_set(mut self, boot_set_name: &str, boot_set_builder: BootSetBuilder) -> Self {
+        self.boot_set_map
+            .insert(boot_set_name.to_string(), boot_set_builder.build());
+        self
+    }
+
+    fn to_template(&self) -> BosSessionTemplate {
+        BosSessionTemplate {
+            name: Some(self.name.clone()),
+            tenant: self.tenant.clone(),
+            description: self.description.clone(),
+            enable_cfs: Some(self.enable_cfs),
+            cfs: self
+                .configuration_name
+                .as_ref()
+                .map(|configuration_name| Cfs {
+                    configuration: Some(configuration_name.clone()),
+                }),
+            boot_sets: Some(self.boot_set_map.clone()),
+            links: None,
+        }
+    }
+
+    /// Returns the BOS session template without calling CSM to validate it
+    pub fn build_unchecked(
+        &self,
+    ) -> Result<BosSessionTemplate, Vec<BosSessionTemplateValidationError>> {
+        let bos_session_template = self.to_template();
+
+        validator::validate_unchecked(&bos_session_template)?;
+
+        Ok(bos_session_template)
+    }
+
+    /// Returns the BOS session template after validating it against CSM. All problems found are
+    /// returned at once
+    pub async fn build(
+        &self,
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+    ) -> Result<BosSessionTemplate, Vec<BosSessionTemplateValidationError>> {
+        let bos_session_template = self.build_unchecked()?;
+
+        validator::validate(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &bos_session_template,
+        )
+        .await?;
+
+        Ok(bos_session_template)
+    }
+}
+
+/// Converts IMS arch (x86_64, aarch64) to BOS arch (X86, ARM, Other)
+pub fn get_bos_arch(ims_arch: &str) -> String {
+    match ims_arch {
+        "x86_64" | "X86" => "X86",
+        "aarch64" | "ARM" => "ARM",
+        _ => "Other",
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bos_session_template_builder() {
+        let bos_session_template = BosSessionTemplateBuilder::new("compute-template")
+            .configuration("compute-config")
+            .boot_set(
+                "compute",
+                BootSetBuilder::new("s3://boot-images/1234/manifest.json")
+                    .node_groups(&["compute".to_string()]),
+            )
+            .boot_set(
+                "compute_arm",
+                BootSetBuilder::new("s3://boot-images/5678/manifest.json")
+                    .arch("ARM")
+                    .node_groups(&["compute".to_string()]),
+            )
+            .build_unchecked()
+            .unwrap();
+
+        assert_eq!(
+            bos_session_template.get_confguration().as_deref(),
+            Some("compute-config")
+        );
+        assert_eq!(bos_session_template.boot_sets.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_bos_session_template_builder_errors() {
+        let error_vec = BosSessionTemplateBuilder::new("compute-template")
+            .boot_set(
+                "compute",
+                BootSetBuilder::new("boot-images/1234/manifest.json")
+                    .node_list(&["x1000c1s7b0n0".to_string(), "nid000001".to_string()]),
+            )
+            .boot_set(
+                "compute_2",
+                BootSetBuilder::new("s3://boot-images/5678/manifest.json")
+                    .node_list(&["x1000c1s7b0n0".to_string()]),
+            )
+            .boot_set(
+                "uan",
+                BootSetBuilder::new("s3://boot-images/5678/manifest.json"),
+            )
+            .build_unchecked()
+            .unwrap_err();
+
+        assert!(matches!(
+            error_vec[0],
+            BosSessionTemplateValidationError::MissingConfiguration
+        ));
+        assert!(matches!(
+            error_vec[1],
+            BosSessionTemplateValidationError::InvalidPath { .. }
+        ));
+        assert!(matches!(
+            error_vec[2],
+            BosSessionTemplateValidationError::InvalidXname { .. }
+        ));
+        assert!(matches!(
+            error_vec[3],
+            BosSessionTemplateValidationError::MissingTarget(_)
+        ));
+        assert!(matches!(
+            &error_vec[4],
+            BosSessionTemplateValidationError::OverlappingBootSets { target_vec, .. } if target_vec == &vec!["x1000c1s7b0n0".to_string()]
+        ));
+        assert_eq!(error_vec.len(), 5);
+    }
+}
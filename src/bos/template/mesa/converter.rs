@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{bos, error::Error, ims};
+
+use super::{
+    builder::get_bos_arch,
+    r#struct::{v1, v2},
+    validator,
+};
+
+/// v1 field which could not be carried over to v2
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LossyField {
+    /// None if the field belongs to the session template, not to a boot set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boot_set: Option<String>,
+    pub field: String,
+    pub value: Value,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BosSessionTemplateConversion {
+    pub v1_name: String,
+    pub v2_name: String,
+    pub bos_session_template: v2::BosSessionTemplate,
+    pub lossy_field_vec: Vec<LossyField>,
+    /// Problems which make the v2 session template invalid, eg CFS enabled without a CFS
+    /// configuration
+    pub validation_error_vec: Vec<String>,
+    /// True if the v2 session template was created in CSM
+    pub created: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub put_error: Option<String>,
+}
+
+impl BosSessionTemplateConversion {
+    pub fn is_lossless(&self) -> bool {
+        self.lossy_field_vec.is_empty()
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.validation_error_vec.is_empty()
+    }
+}
+
+/// Converts a BOS v1 session template to v2 and collects the v1 fields v2 does not support.
+/// `arch_map` maps IMS image ids to their IMS arch (x86_64, aarch64), boot sets booting images
+/// not in the map get the BOS default arch
+pub fn convert(
+    v1_bos_session_template: &v1::BosSessionTemplate,
+    v2_name: &str,
+    arch_map: &HashMap<String, String>,
+) -> BosSessionTemplateConversion {
+    let mut lossy_field_vec = Vec::new();
+
+    let mut lossy = |boot_set: Option<&str>, field: &str, value: Value, reason: &str| {
+        lossy_field_vec.push(LossyField {
+            boot_set: boot_set.map(str::to_string),
+            field: field.to_string(),
+            value,
+            reason: reason.to_string(),
+        })
+    };
+
+    for (field, value) in [
+        ("templateUrl", &v1_bos_session_template.template_url),
+        ("cfs_url", &v1_bos_session_template.cfs_url),
+        ("cfs_branch", &v1_bos_session_template.cfs_branch),
+        ("partition", &v1_bos_session_template.partition),
+    ] {
+        if let Some(value) = value {
+            lossy(
+                None,
+                field,
+                Value::from(value.as_str()),
+                "not supported by BOS v2",
+            );
+        }
+    }
+
+    let cfs = v1_bos_session_template.cfs.as_ref().map(|cfs| {
+        for (field, value) in [
+            ("cfs.clone_url", &cfs.clone_url),
+            ("cfs.branch", &cfs.branch),
+            ("cfs.commit", &cfs.commit),
+            ("cfs.playbook", &cfs.playbook),
+        ] {
+            if let Some(value) = value {
+                lossy(
+                    None,
+                    field,
+                    Value::from(value.as_str()),
+                    "BOS v2 only supports CFS configurations, create a CFS configuration with this git details instead",
+                );
+            }
+        }
+
+        v2::Cfs {
+            configuration: cfs.configuration.clone(),
+        }
+    });
+
+    let boot_set_map: HashMap<String, v2::BootSet> = v1_bos_session_template
+        .boot_sets
+        .iter()
+        .flatten()
+        .map(|(boot_set_name, boot_set)| {
+            for (field, value) in [
+                ("boot_ordinal", boot_set.boot_ordinal.map(Value::from)),
+                (
+                    "shutdown_ordinal",
+                    boot_set.shutdown_ordinal.map(Value::from),
+                ),
+                ("network", boot_set.network.as_deref().map(Value::from)),
+            ] {
+                if let Some(value) = value {
+                    lossy(Some(boot_set_name), field, value, "not supported by BOS v2");
+                }
+            }
+
+            let arch = boot_set
+                .path
+                .as_deref()
+                .map(get_image_id_from_path)
+                .and_then(|image_id| arch_map.get(image_id))
+                .map(|ims_arch| get_bos_arch(ims_arch));
+
+            (
+                boot_set_name.clone(),
+                v2::BootSet {
+                    name: boot_set.name.clone(),
+                    path: boot_set.path.clone(),
+                    cfs: None,
+                    r#type: boot_set.r#type.clone(),
+                    etag: boot_set.etag.clone(),
+                    kernel_parameters: boot_set.kernel_parameters.clone(),
+                    node_list: boot_set.node_list.clone(),
+                    node_roles_groups: boot_set.node_roles_groups.clone(),
+                    node_groups: boot_set.node_groups.clone(),
+                    arch,
+                    rootfs_provider: boot_set.rootfs_provider.clone(),
+                    rootfs_provider_passthrough: boot_set.rootfs_provider_passthrough.clone(),
+                },
+            )
+        })
+        .collect();
+
+    let bos_session_template = v2::BosSessionTemplate {
+        name: Some(v2_name.to_string()),
+        tenant: None,
+        description: v1_bos_session_template.description.clone(),
+        enable_cfs: v1_bos_session_template.enable_cfs,
+        cfs,
+        boot_sets: Some(boot_set_map),
+        // Links are generated by BOS
+        links: None,
+    };
+
+    let validation_error_vec = match validator::validate_unchecked(&bos_session_template) {
+        Ok(_) => Vec::new(),
+        Err(error_vec) => error_vec.iter().map(|error| error.to_string()).collect(),
+    };
+
+    BosSessionTemplateConversion {
+        v1_name: v1_bos_session_template.name.clone(),
+        v2_name: v2_name.to_string(),
+        bos_session_template,
+        lossy_field_vec,
+        validation_error_vec,
+        created: false,
+        put_error: None,
+    }
+}
+
+/// Converts BOS v1 session templates to v2. The v2 session templates are named after the v1 ones
+/// plus `name_suffix` (eg '-v2'). If `put` is true, valid v2 session templates are created in CSM,
+/// existing v2 session templates are never overwritten
+///
+/// # Arguments
+/// * `v1_name_vec_opt` - v1 session templates to convert, all if None
+pub async fn convert_all(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    v1_name_vec_opt: Option<&[String]>,
+    name_suffix: &str,
+    put: bool,
+) -> Result<Vec<BosSessionTemplateConversion>, Error> {
+    let mut v1_bos_session_template_vec = bos::template::shasta::http_client::v1::get(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        None,
+    )
+    .await
+    .map_err(Error::NetError)?;
+
+    if let Some(v1_name_vec) = v1_name_vec_opt {
+        v1_bos_session_template_vec
+            .retain(|bos_session_template| v1_name_vec.contains(&bos_session_template.name));
+    }
+
+    let arch_map: HashMap<String, String> =
+        ims::image::shasta::http_client::get_all(shasta_token, shasta_base_url, shasta_root_cert)
+            .await
+            .map_err(Error::NetError)?
+            .into_iter()
+            .filter_map(|image| {
+                Some((
+                    image["id"].as_str()?.to_string(),
+                    image["arch"].as_str()?.to_string(),
+                ))
+            })
+            .collect();
+
+    let v2_name_vec: Vec<String> = bos::template::shasta::http_client::v2::get(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        None,
+    )
+    .await?
+    .into_iter()
+    .filter_map(|bos_session_template| bos_session_template.name)
+    .collect();
+
+    let mut conversion_vec = Vec::new();
+
+    for v1_bos_session_template in &v1_bos_session_template_vec {
+        let v2_name = format!("{}{}", v1_bos_session_template.name, name_suffix);
+
+        let mut conversion = convert(v1_bos_session_template, &v2_name, &arch_map);
+
+        if !conversion.is_lossless() {
+            log::warn!(
+                "BOS sessiontemplate '{}' conversion to v2 drops fields: {:?}",
+                conversion.v1_name,
+                conversion
+                    .lossy_field_vec
+                    .iter()
+                    .map(|lossy_field| lossy_field.field.as_str())
+                    .collect::<Vec<&str>>()
+            );
+        }
+
+        if put {
+            if v2_name_vec.contains(&v2_name) {
+                conversion.put_error = Some(format!(
+                    "BOS v2 sessiontemplate '{}' already exists",
+                    v2_name
+                ));
+            } else if !conversion.is_valid() {
+                conversion.put_error = Some("v2 sessiontemplate is not valid".to_string());
+            } else {
+                match bos::template::shasta::http_client::v2::put(
+                    shasta_token,
+                    shasta_base_url,
+                    shasta_root_cert,
+                    &conversion.bos_session_template,
+                    &v2_name,
+                )
+                .await
+                {
+                    Ok(_) => conversion.created = true,
+                    Err(error) => conversion.put_error = Some(error.to_string()),
+                }
+            }
+        }
+
+        conversion_vec.push(conversion);
+    }
+
+    Ok(conversion_vec)
+}
+
+/// Returns the IMS image id from a boot set path like 's3://boot-images/<image id>/manifest.json'
+fn get_image_id_from_path(path: &str) -> &str {
+    path.trim_start_matches("s3://boot-images/")
+        .trim_end_matches("/manifest.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_v1_to_v2() {
+        let v1_bos_session_template: v1::BosSessionTemplate =
+            serde_json::from_value(serde_json::json!({
+                "name": "compute",
+                "cfs_url": "https://api-gw-service-nmn.local/vcs/cray/config-management.git",
+                "enable_cfs": true,
+                "cfs": {
+                    "configuration": "compute-config",
+                    "playbook": "site.yml"
+                },
+                "boot_sets": {
+                    "compute": {
+                        "path": "s3://boot-images/1234/manifest.json",
+                        "type": "s3",
+                        "boot_ordinal": 2,
+                        "network": "nmn",
+                        "kernel_parameters": "ip=dhcp quiet",
+                        "node_groups": ["compute"],
+                        "rootfs_provider": "cpss3"
+                    }
+                }
+            }))
+            .unwrap();
+
+        let arch_map = HashMap::from([("1234".to_string(), "aarch64".to_string())]);
+
+        let conversion = convert(&v1_bos_session_template, "compute-v2", &arch_map);
+
+        assert!(conversion.is_valid());
+        assert_eq!(
+            conversion
+                .lossy_field_vec
+                .iter()
+                .map(|lossy_field| lossy_field.field.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["cfs_url", "cfs.playbook", "boot_ordinal", "network"]
+        );
+
+        let bos_session_template = conversion.bos_session_template;
+        let boot_set = &bos_session_template.boot_sets.as_ref().unwrap()["compute"];
+
+        assert_eq!(bos_session_template.name.as_deref(), Some("compute-v2"));
+        assert_eq!(
+            bos_session_template.get_confguration().as_deref(),
+            Some("compute-config")
+        );
+        assert_eq!(boot_set.arch.as_deref(), Some("ARM"));
+        assert_eq!(boot_set.kernel_parameters.as_deref(), Some("ip=dhcp quiet"));
+    }
+}
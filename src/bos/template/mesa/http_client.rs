@@ -1,20 +1,66 @@
-use crate::error::Error;
+use std::collections::HashMap;
 
-use super::r#struct::v2::BosSessionTemplate;
+use crate::{
+    common::site_profile::{self, BosApiVersion},
+    error::Error,
+};
 
+use super::{converter, r#struct::v2::BosSessionTemplate};
+
+/// Get BOS session templates using the BOS API version supported by the site. BOS v1 session
+/// templates are converted to v2 (see `converter::convert`), v1 fields not supported by v2 are
+/// dropped
 pub async fn get(
     shasta_token: &str,
     shasta_base_url: &str,
     shasta_root_cert: &[u8],
     bos_session_template_id_opt: Option<&str>,
 ) -> Result<Vec<BosSessionTemplate>, Error> {
-    crate::bos::template::shasta::http_client::v2::get(
-        shasta_token,
-        shasta_base_url,
-        shasta_root_cert,
-        bos_session_template_id_opt,
-    )
-    .await
+    match site_profile::get(shasta_token, shasta_base_url, shasta_root_cert)
+        .await?
+        .get_bos_api_version()?
+    {
+        BosApiVersion::V2 => {
+            crate::bos::template::shasta::http_client::v2::get(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                bos_session_template_id_opt,
+            )
+            .await
+        }
+        BosApiVersion::V1 => {
+            let v1_bos_session_template_vec = crate::bos::template::shasta::http_client::v1::get(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                bos_session_template_id_opt.map(str::to_string).as_ref(),
+            )
+            .await
+            .map_err(Error::NetError)?;
+
+            Ok(v1_bos_session_template_vec
+                .iter()
+                .map(|v1_bos_session_template| {
+                    let conversion = converter::convert(
+                        v1_bos_session_template,
+                        &v1_bos_session_template.name,
+                        &HashMap::new(),
+                    );
+
+                    if !conversion.is_lossless() {
+                        log::debug!(
+                            "BOS v1 session template '{}' fields not supported by v2: {:?}",
+                            conversion.v1_name,
+                            conversion.lossy_field_vec
+                        );
+                    }
+
+                    conversion.bos_session_template
+                })
+                .collect())
+        }
+    }
 }
 
 pub async fn get_all(
@@ -0,0 +1,474 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::OnceLock,
+};
+
+use regex::Regex;
+
+use crate::{cfs, error::Error, hsm, ims, ims::s3::parse_s3_path, node};
+
+use super::r#struct::v2::{BootSet, BosSessionTemplate};
+
+/// BOS v2 limit for session template names
+pub const BOS_SESSION_TEMPLATE_NAME_MAX_LENGTH: usize = 127;
+
+/// Arch BOS assumes when a boot set does not define one
+const DEFAULT_BOOT_SET_ARCH: &str = "X86";
+
+#[derive(thiserror::Error, Debug)]
+pub enum BosSessionTemplateValidationError {
+    #[error("BOS session template name '{name}' is not valid. {reason}")]
+    InvalidName { name: String, reason: String },
+    #[error("BOS session template has no boot sets")]
+    MissingBootSets,
+    #[error("boot set '{0}': missing path")]
+    MissingPath(String),
+    #[error("boot set '{boot_set}': path '{path}' is not a valid S3 path (s3://<bucket>/<key>)")]
+    InvalidPath { boot_set: String, path: String },
+    #[error("boot set '{boot_set}': IMS manifest '{path}' not found in S3")]
+    ManifestNotFound { boot_set: String, path: String },
+    #[error("boot set '{0}': no node_list, node_groups or node_roles_groups provided")]
+    MissingTarget(String),
+    #[error("boot set '{boot_set}': xname '{xname}' is not valid")]
+    InvalidXname { boot_set: String, xname: String },
+    #[error("boot set '{boot_set}': node '{xname}' not found in HSM")]
+    XnameNotFound { boot_set: String, xname: String },
+    #[error("boot set '{boot_set}': HSM group '{group}' not found")]
+    GroupNotFound { boot_set: String, group: String },
+    #[error("boot set '{boot_set}': no node in HSM with role '{role}'")]
+    RoleNotFound { boot_set: String, role: String },
+    #[error(
+        "boot sets '{boot_set}' and '{other_boot_set}' have the same arch and overlap on: {}",
+        target_vec.join(", ")
+    )]
+    OverlappingBootSets {
+        boot_set: String,
+        other_boot_set: String,
+        target_vec: Vec<String>,
+    },
+    #[error("CFS enabled but no CFS configuration provided")]
+    MissingConfiguration,
+    #[error("CFS configuration '{0}' not found")]
+    ConfigurationNotFound(String),
+    #[error("Could not validate BOS session template against S3. Reason: {0}")]
+    S3(String),
+    #[error("Could not validate BOS session template. Reason: {0}")]
+    Csm(#[from] Error),
+}
+
+/// Validations which do not need to call CSM:
+///  - name follows BOS rules
+///  - there is at least one boot set, each boot set has a S3 path and at least one target
+///  - xnames in node lists are valid
+///  - a CFS configuration is provided if CFS is enabled
+///  - boot sets with the same arch do not share targets
+///
+/// All problems found are returned at once
+pub fn validate_unchecked(
+    bos_session_template: &BosSessionTemplate,
+) -> Result<(), Vec<BosSessionTemplateValidationError>> {
+    let mut error_vec = validate_structure(bos_session_template);
+
+    // Overlaps between nodes in HSM groups and roles are only known after asking HSM, here only
+    // the targets as written in the boot sets can be checked
+    let boot_set_target_vec: Vec<(&String, &str, BTreeSet<String>)> =
+        get_boot_set_vec(bos_session_template)
+            .into_iter()
+            .map(|(boot_set_name, boot_set)| {
+                let target_set = boot_set
+                    .node_list
+                    .iter()
+                    .flatten()
+                    .cloned()
+                    .chain(
+                        boot_set
+                            .node_groups
+                            .iter()
+                            .flatten()
+                            .map(|group| format!("group '{}'", group)),
+                    )
+                    .chain(
+                        boot_set
+                            .node_roles_groups
+                            .iter()
+                            .flatten()
+                            .map(|role| format!("role '{}'", role)),
+                    )
+                    .collect();
+
+                (boot_set_name, get_arch(boot_set), target_set)
+            })
+            .collect();
+
+    error_vec.extend(get_overlapping_boot_sets(&boot_set_target_vec));
+
+    if error_vec.is_empty() {
+        Ok(())
+    } else {
+        Err(error_vec)
+    }
+}
+
+/// Validates a BOS session template against CSM. On top of `validate_unchecked`, checks:
+///  - IMS manifests in boot set paths exist in S3
+///  - CFS configurations exist
+///  - node lists, HSM groups and roles resolve to nodes in HSM
+///  - boot sets with the same arch do not target the same nodes once HSM groups and roles are
+///    resolved
+///
+/// All problems found are returned at once
+pub async fn validate(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    bos_session_template: &BosSessionTemplate,
+) -> Result<(), Vec<BosSessionTemplateValidationError>> {
+    let mut error_vec = validate_structure(bos_session_template);
+
+    let boot_set_vec = get_boot_set_vec(bos_session_template);
+
+    // CFS configurations
+    let mut configuration_name_vec: Vec<&String> = bos_session_template
+        .cfs
+        .iter()
+        .chain(
+            boot_set_vec
+                .iter()
+                .filter_map(|(_, boot_set)| boot_set.cfs.as_ref()),
+        )
+        .filter_map(|cfs| cfs.configuration.as_ref())
+        .collect();
+
+    configuration_name_vec.sort();
+    configuration_name_vec.dedup();
+
+    for configuration_name in configuration_name_vec {
+        if let Err(error) = validate_configuration(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            configuration_name,
+        )
+        .await
+        {
+            error_vec.push(error);
+        }
+    }
+
+    // IMS manifests
+    error_vec.extend(
+        validate_manifests(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &boot_set_vec,
+        )
+        .await,
+    );
+
+    // HSM entities
+    let node_vec = match hsm::component::http_client::get_all_nodes(
+        shasta_base_url,
+        shasta_token,
+        shasta_root_cert,
+        None,
+    )
+    .await
+    {
+        Ok(component_array) => component_array.components.unwrap_or_default(),
+        Err(error) => {
+            error_vec.push(BosSessionTemplateValidationError::Csm(error));
+            return Err(error_vec);
+        }
+    };
+
+    let xname_role_map: HashMap<String, Option<String>> = node_vec
+        .into_iter()
+        .filter_map(|node| node.id.map(|xname| (xname, node.role)))
+        .collect();
+
+    let mut group_member_map: HashMap<&String, Vec<String>> = HashMap::new();
+
+    let mut boot_set_target_vec = Vec::new();
+
+    for (boot_set_name, boot_set) in &boot_set_vec {
+        let mut xname_set = BTreeSet::new();
+
+        for xname in boot_set.node_list.iter().flatten() {
+            if xname_role_map.contains_key(xname) {
+                xname_set.insert(xname.clone());
+            } else if node::utils::validate_xname_format(xname) {
+                error_vec.push(BosSessionTemplateValidationError::XnameNotFound {
+                    boot_set: boot_set_name.to_string(),
+                    xname: xname.clone(),
+                });
+            }
+        }
+
+        for group in boot_set.node_groups.iter().flatten() {
+            if !group_member_map.contains_key(group) {
+                match hsm::group::http_client::get(
+                    shasta_token,
+                    shasta_base_url,
+                    shasta_root_cert,
+                    Some(group),
+                )
+                .await
+                {
+                    Ok(hsm_group_vec) if !hsm_group_vec.is_empty() => {
+                        group_member_map.insert(group, hsm_group_vec[0].get_members());
+                    }
+                    Ok(_) => {}
+                    Err(error) if error.is_not_found() => {}
+                    Err(error) => {
+                        error_vec.push(BosSessionTemplateValidationError::Csm(error));
+                        continue;
+                    }
+                }
+            }
+
+            match group_member_map.get(group) {
+                Some(member_vec) => xname_set.extend(member_vec.iter().cloned()),
+                None => error_vec.push(BosSessionTemplateValidationError::GroupNotFound {
+                    boot_set: boot_set_name.to_string(),
+                    group: group.clone(),
+                }),
+            }
+        }
+
+        for role in boot_set.node_roles_groups.iter().flatten() {
+            let member_vec: Vec<&String> = xname_role_map
+                .iter()
+                .filter(|(_, node_role)| node_role.as_ref() == Some(role))
+                .map(|(xname, _)| xname)
+                .collect();
+
+            if member_vec.is_empty() {
+                error_vec.push(BosSessionTemplateValidationError::RoleNotFound {
+                    boot_set: boot_set_name.to_string(),
+                    role: role.clone(),
+                });
+            }
+
+            xname_set.extend(member_vec.into_iter().cloned());
+        }
+
+        boot_set_target_vec.push((*boot_set_name, get_arch(boot_set), xname_set));
+    }
+
+    error_vec.extend(get_overlapping_boot_sets(&boot_set_target_vec));
+
+    if error_vec.is_empty() {
+        Ok(())
+    } else {
+        Err(error_vec)
+    }
+}
+
+/// Name regex, compiled once
+fn name_regex() -> &'static Regex {
+    static NAME_REGEX: OnceLock<Regex> = OnceLock::new();
+    NAME_REGEX.get_or_init(|| Regex::new(r"^[a-zA-Z0-9]([-._a-zA-Z0-9]*[a-zA-Z0-9])?$").unwrap())
+}
+
+/// Checks BOS session template name: alphanumeric characters, '-', '.' or '_', must start and
+/// end with an alphanumeric character and no longer than 127 characters
+pub fn validate_name(name: &str) -> Result<(), BosSessionTemplateValidationError> {
+    let invalid_name = |reason: String| BosSessionTemplateValidationError::InvalidName {
+        name: name.to_string(),
+        reason,
+    };
+
+    if name.is_empty() {
+        return Err(invalid_name("Name can't be empty".to_string()));
+    }
+
+    if name.len() > BOS_SESSION_TEMPLATE_NAME_MAX_LENGTH {
+        return Err(invalid_name(format!(
+            "Name must be {} characters or less",
+            BOS_SESSION_TEMPLATE_NAME_MAX_LENGTH
+        )));
+    }
+
+    if !name_regex().is_match(name) {
+        return Err(invalid_name(
+            "Name must contain only alphanumeric characters, '-', '.' or '_' and must start and end with an alphanumeric character".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Boot sets sorted by name so validation errors are returned in a stable order
+fn get_boot_set_vec(bos_session_template: &BosSessionTemplate) -> Vec<(&String, &BootSet)> {
+    let mut boot_set_vec: Vec<(&String, &BootSet)> =
+        bos_session_template.boot_sets.iter().flatten().collect();
+
+    boot_set_vec.sort_by_key(|(boot_set_name, _)| *boot_set_name);
+
+    boot_set_vec
+}
+
+fn get_arch(boot_set: &BootSet) -> &str {
+    boot_set.arch.as_deref().unwrap_or(DEFAULT_BOOT_SET_ARCH)
+}
+
+fn validate_structure(
+    bos_session_template: &BosSessionTemplate,
+) -> Vec<BosSessionTemplateValidationError> {
+    let mut error_vec = Vec::new();
+
+    if let Err(error) = validate_name(bos_session_template.name.as_deref().unwrap_or_default()) {
+        error_vec.push(error);
+    }
+
+    if bos_session_template.enable_cfs.unwrap_or(true)
+        && bos_session_template.get_confguration().is_none()
+    {
+        error_vec.push(BosSessionTemplateValidationError::MissingConfiguration);
+    }
+
+    let boot_set_vec = get_boot_set_vec(bos_session_template);
+
+    if boot_set_vec.is_empty() {
+        error_vec.push(BosSessionTemplateValidationError::MissingBootSets);
+    }
+
+    for (boot_set_name, boot_set) in boot_set_vec {
+        match boot_set.path.as_ref() {
+            None => error_vec.push(BosSessionTemplateValidationError::MissingPath(
+                boot_set_name.to_string(),
+            )),
+            Some(path) if parse_s3_path(path).is_none() => {
+                error_vec.push(BosSessionTemplateValidationError::InvalidPath {
+                    boot_set: boot_set_name.to_string(),
+                    path: path.clone(),
+                })
+            }
+            Some(_) => {}
+        }
+
+        let has_target = [
+            &boot_set.node_list,
+            &boot_set.node_groups,
+            &boot_set.node_roles_groups,
+        ]
+        .iter()
+        .any(|target_vec_opt| target_vec_opt.as_ref().is_some_and(|vec| !vec.is_empty()));
+
+        if !has_target {
+            error_vec.push(BosSessionTemplateValidationError::MissingTarget(
+                boot_set_name.to_string(),
+            ));
+        }
+
+        for xname in boot_set.node_list.iter().flatten() {
+            if !node::utils::validate_xname_format(xname) {
+                error_vec.push(BosSessionTemplateValidationError::InvalidXname {
+                    boot_set: boot_set_name.to_string(),
+                    xname: xname.clone(),
+                });
+            }
+        }
+    }
+
+    error_vec
+}
+
+/// BOS boots each node with a single boot set, boot sets with the same arch can't share targets
+fn get_overlapping_boot_sets(
+    boot_set_target_vec: &[(&String, &str, BTreeSet<String>)],
+) -> Vec<BosSessionTemplateValidationError> {
+    let mut error_vec = Vec::new();
+
+    for (index, (boot_set_name, arch, target_set)) in boot_set_target_vec.iter().enumerate() {
+        for (other_boot_set_name, other_arch, other_target_set) in &boot_set_target_vec[index + 1..]
+        {
+            if arch != other_arch {
+                continue;
+            }
+
+            let target_vec: Vec<String> =
+                target_set.intersection(other_target_set).cloned().collect();
+
+            if !target_vec.is_empty() {
+                error_vec.push(BosSessionTemplateValidationError::OverlappingBootSets {
+                    boot_set: boot_set_name.to_string(),
+                    other_boot_set: other_boot_set_name.to_string(),
+                    target_vec,
+                });
+            }
+        }
+    }
+
+    error_vec
+}
+
+async fn validate_configuration(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    configuration_name: &str,
+) -> Result<(), BosSessionTemplateValidationError> {
+    match cfs::configuration::mesa::http_client::get(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        Some(configuration_name),
+    )
+    .await
+    {
+        Ok(configuration_vec) if !configuration_vec.is_empty() => Ok(()),
+        Ok(_) => Err(BosSessionTemplateValidationError::ConfigurationNotFound(
+            configuration_name.to_string(),
+        )),
+        Err(error) if error.is_not_found() => {
+            Err(BosSessionTemplateValidationError::ConfigurationNotFound(
+                configuration_name.to_string(),
+            ))
+        }
+        Err(error) => Err(BosSessionTemplateValidationError::Csm(error)),
+    }
+}
+
+/// Checks the IMS manifest each boot set points to exists in S3
+async fn validate_manifests(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    boot_set_vec: &[(&String, &BootSet)],
+) -> Vec<BosSessionTemplateValidationError> {
+    let path_vec: Vec<(&String, &String, (&str, &str))> = boot_set_vec
+        .iter()
+        .filter_map(|(boot_set_name, boot_set)| {
+            let path = boot_set.path.as_ref()?;
+            parse_s3_path(path).map(|bucket_key| (*boot_set_name, path, bucket_key))
+        })
+        .collect();
+
+    if path_vec.is_empty() {
+        return Vec::new();
+    }
+
+    let sts_value = match ims::s3::s3_auth(shasta_token, shasta_base_url, shasta_root_cert).await {
+        Ok(sts_value) => sts_value,
+        Err(error) => {
+            return vec![BosSessionTemplateValidationError::S3(error.to_string())];
+        }
+    };
+
+    let mut error_vec = Vec::new();
+
+    for (boot_set_name, path, (bucket, key)) in path_vec {
+        match ims::s3::s3_object_exists(&sts_value, key, bucket).await {
+            Ok(true) => {}
+            Ok(false) => error_vec.push(BosSessionTemplateValidationError::ManifestNotFound {
+                boot_set: boot_set_name.to_string(),
+                path: path.clone(),
+            }),
+            Err(error) => error_vec.push(BosSessionTemplateValidationError::S3(error.to_string())),
+        }
+    }
+
+    error_vec
+}
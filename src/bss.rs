@@ -1,13 +1,43 @@
 #[cfg(feature = "ochami")]
-pub mod bootparameters {
-    use std::collections::HashMap;
+pub mod boot_image;
+
+#[cfg(feature = "ochami")]
+pub mod bootscript;
+
+#[cfg(feature = "ochami")]
+pub mod cloud_init;
+
+#[cfg(feature = "ochami")]
+pub mod dumpstate;
 
+#[cfg(feature = "ochami")]
+pub mod endpoint_history;
+
+#[cfg(feature = "ochami")]
+pub mod fetcher;
+
+#[cfg(feature = "ochami")]
+pub mod hosts;
+
+#[cfg(feature = "ochami")]
+pub mod kernel_cmdline;
+
+#[cfg(feature = "ochami")]
+pub mod kernel_param_policy;
+
+#[cfg(feature = "ochami")]
+pub mod snapshot;
+
+#[cfg(feature = "ochami")]
+pub mod bootparameters {
     use serde::{Deserialize, Serialize};
     use serde_json::Value;
     use utils::get_image_id_from_s3_path;
 
     use crate::error::Error;
 
+    use super::{cloud_init::CloudInit, kernel_cmdline::KernelCmdline};
+
     #[derive(Debug, Serialize, Deserialize, Default, Clone)]
     pub struct BootParameters {
         #[serde(default)]
@@ -17,13 +47,13 @@ pub mod bootparameters {
         #[serde(skip_serializing_if = "Option::is_none")]
         pub nids: Option<Vec<u32>>,
         #[serde(default)]
-        pub params: String, // FIXME: change type to HashMap<String, String> by using function
-        // bss::utils::convert_kernel_params_to_map AND create new method
-        // bss::BootParameters::num_kernel_params which returns the list of kernel parameters
+        /// Kernel command line, use `get_kernel_cmdline` to read or modify kernel parameters
+        pub params: String,
         #[serde(default)]
         pub kernel: String,
         #[serde(default)]
         pub initrd: String,
+        /// cloud-init data, use `get_cloud_init` to read or modify it
         #[serde(rename = "cloud-init")]
         #[serde(skip_serializing_if = "Option::is_none")]
         pub cloud_init: Option<Value>,
@@ -66,51 +96,43 @@ pub mod bootparameters {
             }
         }
 
+        pub fn get_kernel_cmdline(&self) -> KernelCmdline {
+            KernelCmdline::parse(&self.params)
+        }
+
+        pub fn set_kernel_cmdline(&mut self, kernel_cmdline: &KernelCmdline) {
+            self.params = kernel_cmdline.to_string();
+        }
+
+        pub fn get_cloud_init(&self) -> Result<CloudInit, Error> {
+            match &self.cloud_init {
+                Some(cloud_init) => Ok(serde_json::from_value(cloud_init.clone())?),
+                None => Ok(CloudInit::default()),
+            }
+        }
+
+        pub fn set_cloud_init(&mut self, cloud_init: &CloudInit) -> Result<(), Error> {
+            self.cloud_init = Some(serde_json::to_value(cloud_init)?);
+            Ok(())
+        }
+
         /// Returns the image id. This function may fail since it assumes kernel path has the following
         // FIXME: Change function signature so it returns a Result<String, Error> instead of String
         pub fn get_boot_image(&self) -> String {
-            let params: HashMap<&str, &str> = self
-                .params
-                .split_whitespace()
-                .map(|kernel_param| {
-                    kernel_param
-                        .split_once('=')
-                        .map(|(key, value)| (key.trim(), value.trim()))
-                        .unwrap_or((kernel_param, ""))
-                })
-                .collect();
+            let kernel_cmdline = self.get_kernel_cmdline();
 
             // NOTE: CN nodes have UIID image id in 'root' kernel parameter
-            // Get `root` kernel parameter and split it by '/'
-            let root_kernel_param_opt = params.get("root");
-            // NOTE: CN nodes have UIID image id in 'metal.server' kernel parameter
-            // Get `root` kernel parameter and split it by '/'
-            let metal_server_kernel_param_opt = params.get("metal.server");
-
+            // NOTE: NCN nodes have UIID image id in 'metal.server' kernel parameter
             let boot_image_id_opt: Option<&str> =
-                if let Some(root_kernel_param) = root_kernel_param_opt {
+                if let Some(root_kernel_param) = kernel_cmdline.get("root") {
                     get_image_id_from_s3_path(root_kernel_param)
-                } else if let Some(metal_server_kernel_param) = metal_server_kernel_param_opt {
+                } else if let Some(metal_server_kernel_param) = kernel_cmdline.get("metal.server") {
                     get_image_id_from_s3_path(metal_server_kernel_param)
                 } else {
                     None
                 };
 
             boot_image_id_opt.unwrap_or("").to_string()
-
-            /* let mut path_elem_vec = self.kernel.split("/").skip(3);
-
-            let mut image_id: String = path_elem_vec.next().unwrap_or_default().to_string();
-
-            for path_elem in path_elem_vec {
-                if !path_elem.eq("kernel") {
-                    image_id = format!("{}/{}", image_id, path_elem);
-                } else {
-                    break;
-                }
-            }
-
-            image_id */
         }
 
         /// Update boot image in kernel boot parameters and also in kernel and initrd fields if
@@ -120,114 +142,43 @@ pub mod bootparameters {
         /// - kernel parameter value changed
         ///  - number of kernel parameters have changed
         pub fn update_boot_image(&mut self, new_image_id: &str) -> Result<bool, Error> {
-            let mut changed = false;
-            // replace image id in 'root' kernel param
+            let mut kernel_cmdline = self.get_kernel_cmdline();
 
-            // convert kernel params to a hashmap
-            let mut params: HashMap<&str, &str> = self
-                .params
-                .split_whitespace()
-                .map(|kernel_param| kernel_param.split_once('=').unwrap_or((kernel_param, "")))
-                .collect();
-
-            // NOTE: CN nodes have UIID image id in 'root' kernel parameter
-            // Get `root` kernel parameter and split it by '/'
-            let root_kernel_param_rslt = params.get("root");
-
-            let mut root_kernel_param: Vec<&str> = match root_kernel_param_rslt {
-                Some(root_kernel_param) => root_kernel_param.split("/").collect::<Vec<&str>>(),
-                None => {
-                    return Err(Error::Message(
-                        "ERROR - The 'root' kernel param is missing from user input".to_string(),
-                    ));
-                }
-            };
-
-            // Replace image id in root kernel param with new image id
-            for current_image_id in &mut root_kernel_param {
-                // Look for any substring between '/' that matches an UUID formant and take it as
-                // the image id
-                if uuid::Uuid::try_parse(current_image_id).is_ok() {
-                    if *current_image_id != new_image_id {
-                        changed = true;
-                    }
-                    // Replace image id in `root` kernel parameter with new value
-                    *current_image_id = new_image_id;
-                }
+            if !kernel_cmdline.contains("root") {
+                return Err(Error::Message(
+                    "ERROR - The 'root' kernel param is missing from user input".to_string(),
+                ));
             }
 
-            // Create new `root` kernel param string
-            let new_root_kernel_param = root_kernel_param.join("/");
-
-            // Create new kernel parameters
-            params
-                .entry("root")
-                .and_modify(|root_param| *root_param = &new_root_kernel_param);
-
-            self.update_kernel_param("root", &new_root_kernel_param);
-
-            // replace image id in 'nmd_data' kernel param
-
-            // convert kernel params to a hashmap
-            let mut params: HashMap<&str, &str> = self
-                .params
-                .split_whitespace()
-                .map(|kernel_param| kernel_param.split_once('=').unwrap_or((kernel_param, "")))
-                .map(|(key, value)| (key.trim(), value.trim()))
-                .collect();
+            let mut changed = false;
 
+            // NOTE: CN nodes have UUID image id in 'root' and 'nmd_data' kernel parameters
             // NOTE: NCN nodes have UUID image id in 'metal.server' kernel parameter
-            let mut metal_server_kernel_param: Vec<&str>;
-            if let Some(metal_server_data) = params.get("metal.server") {
-                metal_server_kernel_param = metal_server_data.split("/").collect();
-
-                for substring in &mut metal_server_kernel_param {
-                    if uuid::Uuid::try_parse(substring).is_ok() {
-                        *substring = new_image_id;
-                        changed = true;
-                    }
-                }
-
-                let new_metal_server_kernel_param = metal_server_kernel_param.join("/");
-
-                params
-                    .entry("metal.server")
-                    .and_modify(|metal_server_param| {
-                        *metal_server_param = &new_metal_server_kernel_param
-                    });
-
-                self.update_kernel_param("metal.server", &new_metal_server_kernel_param);
-
-                // convert kernel params to a hashmap
-                params = self
-                    .params
-                    .split_whitespace()
-                    .map(|kernel_param| kernel_param.split_once('=').unwrap_or((kernel_param, "")))
-                    .collect();
-            } else {
-            };
+            for key in ["root", "metal.server", "nmd_data"] {
+                let Some(value) = kernel_cmdline.get(key) else {
+                    continue;
+                };
 
-            // NOTE: NCN nodes have UUID image id 'nmd_data' kernel parameter
-            let mut nmd_kernel_param: Vec<&str>;
-            if let Some(nmd_data) = params.get("nmd_data") {
-                nmd_kernel_param = nmd_data.split("/").collect();
+                // Look for any substring between '/' that matches an UUID formant and take it as
+                // the image id
+                let new_value = value
+                    .split('/')
+                    .map(|substring| {
+                        if uuid::Uuid::try_parse(substring).is_ok() {
+                            new_image_id
+                        } else {
+                            substring
+                        }
+                    })
+                    .collect::<Vec<&str>>()
+                    .join("/");
 
-                for substring in &mut nmd_kernel_param {
-                    if uuid::Uuid::try_parse(substring).is_ok() {
-                        *substring = new_image_id;
-                        changed = true;
-                    }
+                if new_value != value {
+                    changed |= kernel_cmdline.set(key, Some(&new_value));
                 }
+            }
 
-                let new_nmd_kernel_param = nmd_kernel_param.join("/");
-
-                params
-                    .entry("nmd_data")
-                    .and_modify(|nmd_param| *nmd_param = &new_nmd_kernel_param);
-
-                self.update_kernel_param("nmd_data", &new_nmd_kernel_param);
-            } else {
-            };
+            self.set_kernel_cmdline(&kernel_cmdline);
 
             self.kernel = format!("s3://boot-images/{}/kernel", new_image_id);
 
@@ -236,280 +187,160 @@ pub mod bootparameters {
             Ok(changed)
         }
 
+        /// Returns the value of a kernel parameter, empty string if the kernel parameter has no
+        /// value (eg 'quiet'). If the kernel parameter is duplicated, the last value is returned
         pub fn get_kernel_param_value(&self, key: &str) -> Option<String> {
-            let params: HashMap<&str, &str> = self
-                .params
-                .split_whitespace()
-                .map(|kernel_param| kernel_param.split_once('=').unwrap_or((kernel_param, "")))
-                .map(|(key, value)| (key.trim(), value.trim()))
-                .collect();
-
-            params.get(key).map(|value| value.to_string())
+            self.get_kernel_cmdline().get(key).map(str::to_string)
         }
 
         pub fn get_num_kernel_params(&self) -> usize {
-            let params: HashMap<&str, &str> = self
-                .params
-                .split_whitespace()
-                .map(|kernel_param| kernel_param.split_once('=').unwrap_or((kernel_param, "")))
-                .map(|(key, value)| (key.trim(), value.trim()))
-                .collect();
-
-            params.len()
+            self.get_kernel_cmdline().len()
         }
 
         /// Apply a str of kernel parameters:
         ///  - current kernel params will be ignored/removed and replaced by the new ones
+        ///
         /// Returns true if kernel params have change
         pub fn apply_kernel_params(&mut self, new_params: &str) -> bool {
-            let mut change = false;
+            let new_kernel_cmdline = KernelCmdline::parse(new_params);
 
-            let new_params: Vec<(&str, &str)> = new_params
-                .split_whitespace()
-                .map(|kernel_param| kernel_param.split_once('=').unwrap_or((kernel_param, "")))
-                .map(|(key, value)| (key.trim(), value.trim()))
-                .collect();
+            let change = !new_kernel_cmdline.is_equivalent(&self.get_kernel_cmdline());
 
-            let mut params: HashMap<&str, &str> = HashMap::new();
-
-            for (new_key, new_value) in &new_params {
-                for (key, value) in params.iter_mut() {
-                    if *key == *new_key {
-                        log::debug!("key '{}' found", key);
-                        if value != new_value {
-                            log::info!("changing key {} from {} to {}", key, value, new_value);
-
-                            *value = new_value;
-                            change = true
-                        } else {
-                            log::debug!("key '{}' value does not change ({})", key, value);
-                        }
-                    }
-                }
-            }
-
-            if change == false {
-                log::debug!("No value change in kernel params. Checking is either new params have been added or removed");
-                if new_params.len() != params.len() {
-                    log::info!("num kernel parameters have changed");
-                    change = true;
-                }
+            if change {
+                log::info!("kernel parameters have changed");
             }
 
-            self.params = new_params
-                .iter()
-                .map(|(key, value)| {
-                    if !value.is_empty() {
-                        format!("{key}={value}")
-                    } else {
-                        key.to_string()
-                    }
-                })
-                .collect::<Vec<String>>()
-                .join(" ");
+            self.set_kernel_cmdline(&new_kernel_cmdline);
 
             change
         }
 
         /// Set a str of kernel parameters:
-        ///  - if kernel parameter already exists, then it will be updated
-        ///  - if kernel parameter does not exists, then it will be added
+        ///  - if kernel parameter already exists, then it will be updated. If the kernel parameter
+        ///    is duplicated (eg several `console`), all occurrences are updated, unless several
+        ///    values are provided, in which case they replace the occurrences as a group
+        ///  - if kernel parameter does not exists, then it won't be added
+        ///
         /// Returns true if kernel params have change
+        /// NOTE: for backwards compatibility, it also returns true if the number of kernel
+        /// parameters provided is different than the number of current kernel parameters
         pub fn update_kernel_params(&mut self, new_params: &str) -> bool {
+            let new_kernel_cmdline = KernelCmdline::parse(new_params);
+
+            let mut kernel_cmdline = self.get_kernel_cmdline();
+
             let mut change = false;
 
-            let new_params: Vec<(&str, &str)> = new_params
-                .split_whitespace()
-                .map(|kernel_param| kernel_param.split_once('=').unwrap_or((kernel_param, "")))
-                .map(|(key, value)| (key.trim(), value.trim()))
-                .collect();
+            // Keys in order of first occurrence, duplicated keys are updated as a group
+            let mut key_vec: Vec<&str> = Vec::new();
+            for new_param in new_kernel_cmdline.iter() {
+                if !key_vec.contains(&new_param.key()) {
+                    key_vec.push(new_param.key());
+                }
+            }
 
-            let mut params: HashMap<&str, &str> = self
-                .params
-                .split_whitespace()
-                .map(|kernel_param| kernel_param.split_once('=').unwrap_or((kernel_param, "")))
-                .collect();
-
-            for (new_key, new_value) in &new_params {
-                for (key, value) in params.iter_mut() {
-                    if *key == *new_key {
-                        log::debug!("key '{}' found", key);
-                        if value != new_value {
-                            log::info!("changing key {} from {} to {}", key, value, new_value);
-
-                            *value = new_value;
-                            change = true
-                        } else {
-                            log::debug!("key '{}' value does not change ({})", key, value);
-                        }
+            for key in key_vec {
+                if kernel_cmdline.contains(key) {
+                    log::debug!("key '{}' found", key);
+
+                    let new_value_vec: Vec<Option<&str>> = new_kernel_cmdline
+                        .iter()
+                        .filter(|param| param.key() == key)
+                        .map(|param| param.value())
+                        .collect();
+
+                    let changed = match new_value_vec.as_slice() {
+                        [new_value] => kernel_cmdline.set(key, *new_value),
+                        _ => kernel_cmdline.set_all(key, &new_value_vec),
+                    };
+
+                    if changed {
+                        log::info!(
+                            "changing key {} to {:?}",
+                            key,
+                            new_value_vec
+                                .iter()
+                                .map(|new_value| new_value.unwrap_or_default())
+                                .collect::<Vec<&str>>()
+                        );
+                        change = true;
                     }
                 }
             }
 
-            if change == false {
+            if !change {
                 log::debug!("No value change in kernel params. Checking is either new params have been added or removed");
-                if new_params.len() != params.len() {
+                if new_kernel_cmdline.len() != kernel_cmdline.len() {
                     log::info!("num kernel parameters have changed");
                     change = true;
                 }
             }
 
-            self.params = params
-                .iter()
-                .map(|(key, value)| {
-                    if !value.is_empty() {
-                        format!("{key}={value}")
-                    } else {
-                        key.to_string()
-                    }
-                })
-                .collect::<Vec<String>>()
-                .join(" ");
+            self.set_kernel_cmdline(&kernel_cmdline);
 
             change
         }
 
         /// Update kernel parameter. If kernel parameter exists, then it will be updated with new
-        /// Note: This function won't make any change to params without values (eg: 'quiet') since
-        /// they don't have values
-        /// value. otherwise nothing will change
+        /// value, otherwise nothing will change
+        /// Returns true if the kernel parameter exists
         pub fn update_kernel_param(&mut self, key: &str, new_value: &str) -> bool {
-            let mut changed = false;
-            // convert kernel params to a hashmap
-            let mut params: HashMap<&str, &str> = self
-                .params
-                .split_whitespace()
-                .map(|kernel_param| kernel_param.split_once('=').unwrap_or((kernel_param, "")))
-                .map(|(key, value)| (key.trim(), value.trim()))
-                .collect();
-
-            // Update kernel param with new value
-            // params.entry(key).and_modify(|value| *value = new_value);
-            for (current_key, current_value) in params.iter_mut() {
-                if *current_key == key {
-                    *current_value = new_value;
-                    changed = true;
-                }
-            }
-
-            // Create new kernel params as a string
-            self.params = params
-                .iter()
-                .map(|(key, value)| {
-                    if !value.is_empty() {
-                        format!("{key}={value}")
-                    } else {
-                        key.to_string()
-                    }
-                })
-                .collect::<Vec<String>>()
-                .join(" ");
+            let mut kernel_cmdline = self.get_kernel_cmdline();
 
-            changed
-        }
-
-        /* /// Delete kernel parameter. If kernel parameter exists, then it will be removed, otherwise
-        /// nothing will be changed
-        /// Input expected the list of kernel param keys separated by space. eg: `console bad_page crashkernel hugepagelist intel_pstate`
-        pub fn delete_kernel_params(&mut self, keys: &str) {
-            let keys: Vec<&str> = keys.split_whitespace().collect();
+            if !kernel_cmdline.contains(key) {
+                return false;
+            }
 
-            let mut params: HashMap<&str, &str> = self
-                .params
-                .split_whitespace()
-                .map(|kernel_param| kernel_param.split_once('=').unwrap_or((kernel_param, "")))
-                .collect();
+            kernel_cmdline.set(key, Some(new_value));
 
-            for key in keys {
-                params.remove(key);
-            }
+            self.set_kernel_cmdline(&kernel_cmdline);
 
-            self.params = params
-                .iter()
-                .map(|(key, value)| {
-                    if !value.is_empty() {
-                        format!("{key}={value}")
-                    } else {
-                        key.to_string()
-                    }
-                })
-                .collect::<Vec<String>>()
-                .join(" ");
-        } */
+            true
+        }
 
-        /// Add a kernel parameter:
+        /// Add kernel parameters:
         ///  - if kernel parameter does not exists, then it will be added,
-        /// otherwise nothing will change
+        ///    otherwise nothing will change
+        ///
         /// Returns true if kernel params have change
         pub fn add_kernel_params(&mut self, new_kernel_params: &str) -> bool {
             let mut changed = false;
-            let mut params: HashMap<&str, &str> = self
-                .params
-                .split_whitespace()
-                .map(|kernel_param| kernel_param.split_once('=').unwrap_or((kernel_param, "")))
-                .map(|(key, value)| (key.trim(), value.trim()))
-                .collect();
 
-            let new_kernel_params_tuple: HashMap<&str, &str> = new_kernel_params
-                .split_whitespace()
-                .map(|kernel_param| kernel_param.split_once('=').unwrap_or((kernel_param, "")))
-                .collect();
-
-            for (key, new_value) in new_kernel_params_tuple {
-                // NOTE: do not use --> `params.entry(key).or_insert(new_value);` otherwise, I don't know
-                // how do we know if the key already exists or not
-                if params.contains_key(key) {
-                    log::info!("key '{}' already exists, the new kernel parameter won't be added since it already exists", key);
-                    return changed;
+            let mut kernel_cmdline = self.get_kernel_cmdline();
+
+            for new_param in KernelCmdline::parse(new_kernel_params).iter() {
+                if kernel_cmdline.contains(new_param.key()) {
+                    log::info!("key '{}' already exists, the new kernel parameter won't be added since it already exists", new_param.key());
                 } else {
                     log::info!(
                         "key '{}' not found, adding new kernel param with value '{}'",
-                        key,
-                        new_value
+                        new_param.key(),
+                        new_param.value().unwrap_or_default()
                     );
-                    params.insert(key, new_value);
+                    kernel_cmdline.append(new_param.key(), new_param.value());
                     changed = true
                 }
             }
 
-            self.params = params
-                .iter()
-                .map(|(key, value)| {
-                    if !value.is_empty() {
-                        format!("{key}={value}")
-                    } else {
-                        key.to_string()
-                    }
-                })
-                .collect::<Vec<String>>()
-                .join(" ");
+            self.set_kernel_cmdline(&kernel_cmdline);
 
             changed
         }
 
-        /// Delete kernel parameter. If kernel parameter exists, then it will be removed, otherwise
-        /// nothing will be changed
-        pub fn delete_kernel_params(&mut self, key: &str) -> bool {
-            let mut params: HashMap<&str, &str> = self
-                .params
-                .split_whitespace()
-                .map(|kernel_param| kernel_param.split_once('=').unwrap_or((kernel_param, "")))
-                .map(|(key, value)| (key.trim(), value.trim()))
-                .collect();
+        /// Delete kernel parameters. If kernel parameter exists, then it will be removed, otherwise
+        /// nothing will be changed. All occurrences of a kernel parameter are removed
+        /// Input expected the list of kernel param keys separated by space. eg: `console bad_page crashkernel hugepagelist intel_pstate`
+        /// Returns true if kernel params have change
+        pub fn delete_kernel_params(&mut self, keys: &str) -> bool {
+            let mut kernel_cmdline = self.get_kernel_cmdline();
 
-            let changed = params.remove(key).is_some();
+            let mut changed = false;
 
-            self.params = params
-                .iter()
-                .map(|(key, value)| {
-                    if !value.is_empty() {
-                        format!("{key}={value}")
-                    } else {
-                        key.to_string()
-                    }
-                })
-                .collect::<Vec<String>>()
-                .join(" ");
+            for key in keys.split_whitespace() {
+                changed |= kernel_cmdline.remove(key) > 0;
+            }
+
+            self.set_kernel_cmdline(&kernel_cmdline);
 
             changed
         }
@@ -518,22 +349,37 @@ pub mod bootparameters {
     pub mod http_client {
 
         use serde_json::Value;
-        use tokio::sync::Semaphore;
 
         use core::result::Result;
-        use std::{sync::Arc, time::Instant};
 
-        use crate::error::Error;
+        use crate::{
+            bss::{
+                fetcher::{BootParametersFetcher, BootParametersIndex},
+                kernel_param_policy,
+            },
+            error::Error,
+        };
 
         use super::BootParameters;
 
-        pub fn post(
+        /// Create nodes boot params, ref --> https://apidocs.svc.cscs.ch/iaas/bss/tag/bootparameters/paths/~1bootparameters/post/
+        /// Fails without changing BSS if the kernel params violate the site kernel param policy
+        /// (see `bss::kernel_param_policy::set_site_policy`)
+        pub async fn post(
             base_url: &str,
             auth_token: &str,
             root_cert: &[u8],
             boot_parameters: BootParameters,
         ) -> Result<(), Error> {
-            let client_builder = reqwest::blocking::Client::builder()
+            kernel_param_policy::check_site_policy(
+                base_url,
+                auth_token,
+                root_cert,
+                std::slice::from_ref(&boot_parameters),
+            )
+            .await?;
+
+            let client_builder = reqwest::Client::builder()
                 .add_root_certificate(reqwest::Certificate::from_pem(root_cert)?);
 
             // Build client
@@ -548,29 +394,103 @@ pub mod bootparameters {
                 client_builder.build()?
             };
 
-            let api_url = format!("{}/boot/v1/bootparameters", base_url);
+            let api_url = format!("{}/bss/boot/v1/bootparameters", base_url);
 
             let response = client
                 .post(api_url)
                 .bearer_auth(auth_token)
                 .json(&boot_parameters)
                 .send()
-                .map_err(|error| Error::NetError(error))?;
+                .await
+                .map_err(Error::NetError)?;
 
             if response.status().is_success() {
                 Ok(())
             } else {
-                Err(Error::Message(response.text()?))
+                Err(Error::Message(
+                    response.text().await.map_err(Error::NetError)?,
+                ))
+            }
+        }
+
+        /// Delete nodes boot params, ref --> https://apidocs.svc.cscs.ch/iaas/bss/tag/bootparameters/paths/~1bootparameters/delete/
+        /// Only the identifiers in `boot_parameters` (hosts, macs or nids) are used to select the
+        /// boot params to delete
+        pub async fn delete(
+            base_url: &str,
+            auth_token: &str,
+            root_cert: &[u8],
+            boot_parameters: &BootParameters,
+        ) -> Result<(), Error> {
+            let client_builder = reqwest::Client::builder()
+                .add_root_certificate(reqwest::Certificate::from_pem(root_cert)?);
+
+            // Build client
+            let client = if let Ok(socks5_env) = std::env::var("SOCKS5") {
+                // socks5 proxy
+                log::debug!("SOCKS5 enabled");
+                let socks5proxy = reqwest::Proxy::all(socks5_env)?;
+
+                // rest client to authenticate
+                client_builder.proxy(socks5proxy).build()?
+            } else {
+                client_builder.build()?
+            };
+
+            let api_url = format!("{}/bss/boot/v1/bootparameters", base_url);
+
+            let response = client
+                .delete(api_url)
+                .bearer_auth(auth_token)
+                .json(boot_parameters)
+                .send()
+                .await
+                .map_err(Error::NetError)?;
+
+            if response.status().is_success() {
+                Ok(())
+            } else {
+                Err(Error::Message(
+                    response.text().await.map_err(Error::NetError)?,
+                ))
             }
         }
 
         /// Change nodes boot params, ref --> https://apidocs.svc.cscs.ch/iaas/bss/tag/bootparameters/paths/~1bootparameters/put/
+        /// Fails without changing BSS if the kernel params violate the site kernel param policy
+        /// (see `bss::kernel_param_policy::set_site_policy`)
         pub async fn put(
             shasta_base_url: &str,
             shasta_token: &str,
             shasta_root_cert: &[u8],
             boot_parameters: BootParameters,
-        ) -> Result<Vec<Value>, reqwest::Error> {
+        ) -> Result<Vec<Value>, Error> {
+            kernel_param_policy::check_site_policy(
+                shasta_base_url,
+                shasta_token,
+                shasta_root_cert,
+                std::slice::from_ref(&boot_parameters),
+            )
+            .await?;
+
+            put_unchecked(
+                shasta_base_url,
+                shasta_token,
+                shasta_root_cert,
+                boot_parameters,
+            )
+            .await
+        }
+
+        /// Same as `put` without checking the site kernel param policy. Used by callers which
+        /// already checked all the boot parameters they change, or which restore boot parameters
+        /// BSS had before
+        pub(crate) async fn put_unchecked(
+            shasta_base_url: &str,
+            shasta_token: &str,
+            shasta_root_cert: &[u8],
+            boot_parameters: BootParameters,
+        ) -> Result<Vec<Value>, Error> {
             let client;
 
             let client_builder = reqwest::Client::builder()
@@ -604,14 +524,28 @@ pub mod bootparameters {
                 .error_for_status()?
                 .json()
                 .await
+                .map_err(Error::NetError)
         }
 
+        /// Fails without changing BSS if the kernel params violate the site kernel param policy
+        /// (see `bss::kernel_param_policy::set_site_policy`)
         pub async fn patch(
             shasta_base_url: &str,
             shasta_token: &str,
             shasta_root_cert: &[u8],
             boot_parameters: &BootParameters,
-        ) -> Result<Vec<Value>, reqwest::Error> {
+        ) -> Result<Vec<Value>, Error> {
+            // BSS does not change the kernel params if the patch has none
+            if !boot_parameters.params.is_empty() {
+                kernel_param_policy::check_site_policy(
+                    shasta_base_url,
+                    shasta_token,
+                    shasta_root_cert,
+                    std::slice::from_ref(boot_parameters),
+                )
+                .await?;
+            }
+
             let client;
 
             let client_builder = reqwest::Client::builder()
@@ -641,60 +575,23 @@ pub mod bootparameters {
                 .error_for_status()?
                 .json()
                 .await
+                .map_err(Error::NetError)
         }
 
+        /// Get boot params for a list of nodes. Fails if the boot params of any node could not be
+        /// fetched, use `bss::fetcher::BootParametersFetcher` to tune the number of nodes per
+        /// request and concurrency or to get partial results
         pub async fn get(
             shasta_token: &str,
             shasta_base_url: &str,
             shasta_root_cert: &[u8],
             xnames: &[String],
-        ) -> Result<Vec<BootParameters>, reqwest::Error> {
-            let start = Instant::now();
-
-            let chunk_size = 30;
-
-            let mut boot_params_vec = Vec::new();
-
-            let mut tasks = tokio::task::JoinSet::new();
-
-            let sem = Arc::new(Semaphore::new(10)); // CSM 1.3.1 higher number of concurrent tasks won't
-
-            for sub_node_list in xnames.chunks(chunk_size) {
-                let shasta_token_string = shasta_token.to_string();
-                let shasta_base_url_string = shasta_base_url.to_string();
-                let shasta_root_cert_vec = shasta_root_cert.to_vec();
-
-                // let hsm_subgroup_nodes_string: String = sub_node_list.join(",");
-
-                let permit = Arc::clone(&sem).acquire_owned().await;
-
-                let node_vec = sub_node_list.to_vec();
-
-                tasks.spawn(async move {
-                    let _permit = permit; // Wait semaphore to allow new tasks https://github.com/tokio-rs/tokio/discussions/2648#discussioncomment-34885
-
-                    get_raw(
-                        &shasta_token_string,
-                        &shasta_base_url_string,
-                        &shasta_root_cert_vec,
-                        // &hsm_subgroup_nodes_string,
-                        &node_vec,
-                    )
-                    .await
-                    .unwrap()
-                });
-            }
-
-            while let Some(message) = tasks.join_next().await {
-                if let Ok(mut node_status_vec) = message {
-                    boot_params_vec.append(&mut node_status_vec);
-                }
-            }
-
-            let duration = start.elapsed();
-            log::info!("Time elapsed to get BSS bootparameters is: {:?}", duration);
-
-            Ok(boot_params_vec)
+        ) -> Result<Vec<BootParameters>, Error> {
+            BootParametersFetcher::new(shasta_token, shasta_base_url, shasta_root_cert)
+                .fetch(xnames)
+                .await
+                .into_complete()
+                .map(BootParametersIndex::into_vec)
         }
 
         /// Get node boot params, ref --> https://apidocs.svc.cscs.ch/iaas/bss/tag/bootparameters/paths/~1bootparameters/get/
@@ -0,0 +1,465 @@
+//! Switch the boot image of nodes. `BootParameters::update_boot_image` only rewrites the boot
+//! parameters, this module checks the image can actually boot the nodes before updating BSS:
+//! the IMS image exists, its boot artifacts are in S3 and its architecture matches the nodes
+//! hardware
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bos::{
+        self,
+        session::shasta::http_client::v2::{BosSession, Operation},
+        template::mesa::builder::get_bos_arch,
+    },
+    error::Error,
+    hsm,
+    ims::{self, image::r#struct::Image},
+};
+
+use super::{
+    kernel_param_policy,
+    snapshot::{self, BssSnapshot},
+};
+
+/// S3 bucket where IMS stores the boot artifacts
+const BOOT_IMAGES_BUCKET: &str = "boot-images";
+
+/// Boot artifacts each image needs in S3
+const BOOT_ARTIFACT_VEC: [&str; 3] = ["kernel", "initrd", "rootfs"];
+
+#[derive(thiserror::Error, Debug)]
+pub enum BootImageCheckError {
+    #[error("IMS image '{0}' not found")]
+    ImageNotFound(String),
+    #[error("Boot artifact 's3://{bucket}/{key}' not available. Reason: {reason}")]
+    MissingArtifact {
+        bucket: String,
+        key: String,
+        reason: String,
+    },
+    #[error("Boot artifact 's3://{bucket}/{key}' is empty")]
+    EmptyArtifact { bucket: String, key: String },
+    #[error("Node '{0}' not found in HSM")]
+    NodeNotFound(String),
+    #[error("Node '{xname}' arch '{node_arch}' does not match IMS image arch '{image_arch}'")]
+    ArchMismatch {
+        xname: String,
+        node_arch: String,
+        image_arch: String,
+    },
+    #[error("Node '{0}' has no BSS boot parameters")]
+    MissingBootParameters(String),
+    #[error("BOS sessiontemplate '{template}' does not boot IMS image '{image_id}', rebooting with it would revert the boot image")]
+    TemplateImageMismatch { template: String, image_id: String },
+    #[error(transparent)]
+    Csm(#[from] Error),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BootImageSwitchReport {
+    pub image_id: String,
+    /// Nodes which boot parameters changed
+    pub updated_xname_vec: Vec<String>,
+    /// Boot parameters before the switch, can be used to roll back with `snapshot::restore`
+    pub snapshot: BssSnapshot,
+    /// BOS session rebooting the nodes, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bos_session_name: Option<String>,
+}
+
+/// Checks the IMS image can boot the nodes. All problems found are returned at once.
+/// If `bos_session_template_name_opt` is provided, also checks the BOS sessiontemplate boots the
+/// image, otherwise BOS would overwrite the boot parameters with its own image when rebooting
+/// Returns the IMS image
+pub async fn check(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    image_id: &str,
+    xname_vec: &[String],
+    bos_session_template_name_opt: Option<&str>,
+) -> Result<Image, Vec<BootImageCheckError>> {
+    let mut error_vec = Vec::new();
+
+    // IMS
+    let image = match ims::image::mesa::http_client::get(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        Some(image_id),
+    )
+    .await
+    {
+        Ok(mut image_vec) if !image_vec.is_empty() => image_vec.remove(0),
+        Ok(_) => {
+            return Err(vec![BootImageCheckError::ImageNotFound(
+                image_id.to_string(),
+            )])
+        }
+        Err(error) if error.status() == Some(reqwest::StatusCode::NOT_FOUND) => {
+            return Err(vec![BootImageCheckError::ImageNotFound(
+                image_id.to_string(),
+            )])
+        }
+        Err(error) => return Err(vec![BootImageCheckError::Csm(Error::NetError(error))]),
+    };
+
+    // S3
+    match ims::s3::s3_auth(shasta_token, shasta_base_url, shasta_root_cert).await {
+        Ok(sts_value) => {
+            let mut artifact_size_vec = Vec::new();
+
+            for artifact in BOOT_ARTIFACT_VEC {
+                let key = format!("{}/{}", image_id, artifact);
+
+                let size_rslt = ims::s3::s3_get_object_size(&sts_value, &key, BOOT_IMAGES_BUCKET)
+                    .await
+                    .map_err(|error| error.to_string());
+
+                artifact_size_vec.push((key, size_rslt));
+            }
+
+            error_vec.extend(check_artifact_vec(artifact_size_vec));
+        }
+        Err(error) => error_vec.push(BootImageCheckError::Csm(Error::NetError(error))),
+    }
+
+    // HSM
+    // NOTE: IMS images without arch are x86_64
+    let image_arch = get_bos_arch(image.arch.as_deref().unwrap_or("x86_64"));
+
+    match hsm::component::http_client::get_all_nodes(
+        shasta_base_url,
+        shasta_token,
+        shasta_root_cert,
+        None,
+    )
+    .await
+    {
+        Ok(component_array) => {
+            let xname_arch_map: HashMap<String, Option<String>> = component_array
+                .components
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|component| Some((component.id?, component.arch)))
+                .collect();
+
+            error_vec.extend(check_node_arch(xname_vec, &xname_arch_map, &image_arch));
+        }
+        Err(error) => error_vec.push(BootImageCheckError::Csm(error)),
+    }
+
+    // BOS
+    if let Some(bos_session_template_name) = bos_session_template_name_opt {
+        match bos::template::shasta::http_client::v2::get(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            Some(bos_session_template_name),
+        )
+        .await
+        {
+            Ok(bos_session_template_vec) => {
+                let boots_image =
+                    bos_session_template_vec
+                        .first()
+                        .is_some_and(|bos_session_template| {
+                            bos_session_template.boot_sets.is_some()
+                                && bos_session_template
+                                    .get_image_vec()
+                                    .iter()
+                                    .any(|template_image_id| template_image_id == image_id)
+                        });
+
+                if !boots_image {
+                    error_vec.push(BootImageCheckError::TemplateImageMismatch {
+                        template: bos_session_template_name.to_string(),
+                        image_id: image_id.to_string(),
+                    });
+                }
+            }
+            Err(error) => error_vec.push(BootImageCheckError::Csm(error)),
+        }
+    }
+
+    if error_vec.is_empty() {
+        Ok(image)
+    } else {
+        Err(error_vec)
+    }
+}
+
+/// Boot artifacts missing or empty. `artifact_size_vec` has the S3 key of each boot artifact with
+/// its size, or the reason the size could not be read
+fn check_artifact_vec(
+    artifact_size_vec: Vec<(String, Result<i64, String>)>,
+) -> Vec<BootImageCheckError> {
+    artifact_size_vec
+        .into_iter()
+        .filter_map(|(key, size_rslt)| match size_rslt {
+            Ok(size) if size > 0 => {
+                log::debug!("Boot artifact '{}' size: {}", key, size);
+                None
+            }
+            Ok(_) => Some(BootImageCheckError::EmptyArtifact {
+                bucket: BOOT_IMAGES_BUCKET.to_string(),
+                key,
+            }),
+            Err(reason) => Some(BootImageCheckError::MissingArtifact {
+                bucket: BOOT_IMAGES_BUCKET.to_string(),
+                key,
+                reason,
+            }),
+        })
+        .collect()
+}
+
+/// Nodes missing in HSM or which arch does not match the IMS image arch. `xname_arch_map` has
+/// the HSM arch of each node
+fn check_node_arch(
+    xname_vec: &[String],
+    xname_arch_map: &HashMap<String, Option<String>>,
+    image_arch: &str,
+) -> Vec<BootImageCheckError> {
+    xname_vec
+        .iter()
+        .filter_map(|xname| match xname_arch_map.get(xname) {
+            None => Some(BootImageCheckError::NodeNotFound(xname.clone())),
+            // Only X86 and ARM can be compared, HSM reports other archs as 'Other' or
+            // 'UNKNOWN'
+            Some(Some(node_arch))
+                if ["X86", "ARM"].contains(&node_arch.as_str()) && node_arch != image_arch =>
+            {
+                Some(BootImageCheckError::ArchMismatch {
+                    xname: xname.clone(),
+                    node_arch: node_arch.clone(),
+                    image_arch: image_arch.to_string(),
+                })
+            }
+            Some(_) => None,
+        })
+        .collect()
+}
+
+/// Sets the boot image of nodes after checking the image can boot them (see `check`). Boot
+/// parameters are saved in a snapshot before changing them and restored if any node fails to
+/// update.
+/// If `bos_session_template_name_opt` is provided, the nodes are rebooted with a BOS session
+/// using that sessiontemplate once BSS is updated
+/// NOTE: only the image id is changed in the kernel parameters, other kernel parameters like
+/// the rootfs etag are kept as they are
+pub async fn set_boot_image(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    image_id: &str,
+    xname_vec: &[String],
+    bos_session_template_name_opt: Option<&str>,
+) -> Result<BootImageSwitchReport, Vec<BootImageCheckError>> {
+    check(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        image_id,
+        xname_vec,
+        bos_session_template_name_opt,
+    )
+    .await?;
+
+    let snapshot = BssSnapshot::take(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        &format!("boot-image-{}", image_id),
+        None,
+        xname_vec,
+    )
+    .await
+    .map_err(|error| vec![BootImageCheckError::Csm(error)])?;
+
+    let mut boot_parameters_vec = Vec::new();
+    let mut error_vec = Vec::new();
+
+    for xname in xname_vec {
+        let Some(mut boot_parameters) = snapshot.get_node_boot_parameters(xname) else {
+            error_vec.push(BootImageCheckError::MissingBootParameters(xname.clone()));
+            continue;
+        };
+
+        let (kernel, initrd) = (
+            boot_parameters.kernel.clone(),
+            boot_parameters.initrd.clone(),
+        );
+
+        match boot_parameters.update_boot_image(image_id) {
+            Ok(changed)
+                if changed
+                    || boot_parameters.kernel != kernel
+                    || boot_parameters.initrd != initrd =>
+            {
+                boot_parameters_vec.push(boot_parameters)
+            }
+            Ok(_) => log::info!("Node '{}' already boots image '{}'", xname, image_id),
+            Err(error) => error_vec.push(BootImageCheckError::Csm(error)),
+        }
+    }
+
+    if !error_vec.is_empty() {
+        return Err(error_vec);
+    }
+
+    // Check all nodes against the site kernel param policy before changing BSS
+    kernel_param_policy::check_site_policy(
+        shasta_base_url,
+        shasta_token,
+        shasta_root_cert,
+        &boot_parameters_vec,
+    )
+    .await
+    .map_err(|error| vec![BootImageCheckError::Csm(error)])?;
+
+    let mut updated_xname_vec = Vec::new();
+
+    for boot_parameters in boot_parameters_vec {
+        let xname = boot_parameters.hosts.join(",");
+
+        log::info!("Set boot image '{}' to node '{}'", image_id, xname);
+
+        if let Err(error) = super::bootparameters::http_client::put_unchecked(
+            shasta_base_url,
+            shasta_token,
+            shasta_root_cert,
+            boot_parameters,
+        )
+        .await
+        {
+            log::error!(
+                "Could not update BSS boot parameters for node '{}', restoring snapshot. Reason:\n{}",
+                xname,
+                error
+            );
+
+            let mut error_vec = vec![BootImageCheckError::Csm(error)];
+
+            if let Err(error) = snapshot::restore_unchecked(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                &snapshot,
+            )
+            .await
+            {
+                error_vec.push(BootImageCheckError::Csm(error));
+            }
+
+            return Err(error_vec);
+        }
+
+        updated_xname_vec.push(xname);
+    }
+
+    let bos_session_name = if let Some(bos_session_template_name) = bos_session_template_name_opt {
+        let bos_session = BosSession {
+            name: None,
+            tenant: None,
+            operation: Some(Operation::Reboot),
+            template_name: bos_session_template_name.to_string(),
+            limit: Some(xname_vec.join(",")),
+            stage: None,
+            components: None,
+            include_disabled: None,
+            status: None,
+        };
+
+        let bos_session_value = bos::session::shasta::http_client::v2::post(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            bos_session,
+        )
+        .await
+        .map_err(|error| vec![BootImageCheckError::Csm(error)])?;
+
+        bos_session_value["name"].as_str().map(str::to_string)
+    } else {
+        None
+    };
+
+    Ok(BootImageSwitchReport {
+        image_id: image_id.to_string(),
+        updated_xname_vec,
+        snapshot,
+        bos_session_name,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_artifact_vec() {
+        let error_vec = check_artifact_vec(vec![
+            (
+                "6c644208-104a-473d-802c-410219026335/kernel".to_string(),
+                Ok(10),
+            ),
+            (
+                "6c644208-104a-473d-802c-410219026335/initrd".to_string(),
+                Ok(0),
+            ),
+            (
+                "6c644208-104a-473d-802c-410219026335/rootfs".to_string(),
+                Err("NoSuchKey".to_string()),
+            ),
+        ]);
+
+        assert_eq!(error_vec.len(), 2);
+        assert!(matches!(
+            &error_vec[0],
+            BootImageCheckError::EmptyArtifact { key, .. }
+                if key == "6c644208-104a-473d-802c-410219026335/initrd"
+        ));
+        assert!(matches!(
+            &error_vec[1],
+            BootImageCheckError::MissingArtifact { key, reason, .. }
+                if key == "6c644208-104a-473d-802c-410219026335/rootfs" && reason == "NoSuchKey"
+        ));
+    }
+
+    #[test]
+    fn test_check_node_arch() {
+        let xname_arch_map: HashMap<String, Option<String>> = HashMap::from([
+            ("x1000c1s7b0n0".to_string(), Some("X86".to_string())),
+            ("x1000c1s7b0n1".to_string(), Some("ARM".to_string())),
+            ("x1000c1s7b1n0".to_string(), Some("UNKNOWN".to_string())),
+            ("x1000c1s7b1n1".to_string(), None),
+        ]);
+
+        let xname_vec: Vec<String> = [
+            "x1000c1s7b0n0",
+            "x1000c1s7b0n1",
+            "x1000c1s7b1n0",
+            "x1000c1s7b1n1",
+            "x1000c1s7b2n0",
+        ]
+        .iter()
+        .map(|xname| xname.to_string())
+        .collect();
+
+        let error_vec = check_node_arch(&xname_vec, &xname_arch_map, "X86");
+
+        assert_eq!(error_vec.len(), 2);
+        assert!(matches!(
+            &error_vec[0],
+            BootImageCheckError::ArchMismatch { xname, node_arch, image_arch }
+                if xname == "x1000c1s7b0n1" && node_arch == "ARM" && image_arch == "X86"
+        ));
+        assert!(matches!(
+            &error_vec[1],
+            BootImageCheckError::NodeNotFound(xname) if xname == "x1000c1s7b2n0"
+        ));
+    }
+}
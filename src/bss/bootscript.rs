@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+
+use super::kernel_cmdline::KernelCmdline;
+
+/// Boot artifacts in the iPXE bootscript BSS serves to a node. Useful to debug boot failures
+/// without having to reboot the node
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct Bootscript {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kernel: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initrd: Option<String>,
+    /// Kernel command line, kernel parameters in the iPXE `kernel` command
+    pub params: String,
+}
+
+impl Bootscript {
+    /// Parses an iPXE bootscript like:
+    ///
+    /// #!ipxe
+    /// kernel --name kernel http://rgw-vip.nmn/boot-images/<image id>/kernel initrd=initrd console=ttyS0,115200 || goto boot_retry
+    /// initrd --name initrd http://rgw-vip.nmn/boot-images/<image id>/initrd || goto boot_retry
+    /// boot || goto boot_retry
+    pub fn parse(bootscript: &str) -> Self {
+        let mut kernel = None;
+        let mut initrd = None;
+        let mut params = String::new();
+
+        for line in bootscript.lines() {
+            // Drop iPXE error handling, eg '|| goto boot_retry'
+            let command = line.split(" || ").next().unwrap_or_default().trim();
+
+            let mut arg_iter = command.split_whitespace();
+
+            let Some(command_name @ ("kernel" | "initrd")) = arg_iter.next() else {
+                continue;
+            };
+
+            // Skip iPXE options (eg '--name kernel')
+            let mut url_opt = None;
+            while let Some(arg) = arg_iter.next() {
+                if arg.starts_with("--") {
+                    if !arg.contains('=') {
+                        arg_iter.next();
+                    }
+                } else {
+                    url_opt = Some(arg.to_string());
+                    break;
+                }
+            }
+
+            if command_name == "kernel" {
+                kernel = url_opt;
+                params = arg_iter.collect::<Vec<&str>>().join(" ");
+            } else {
+                initrd = url_opt;
+            }
+        }
+
+        Self {
+            kernel,
+            initrd,
+            params,
+        }
+    }
+
+    pub fn get_kernel_cmdline(&self) -> KernelCmdline {
+        KernelCmdline::parse(&self.params)
+    }
+}
+
+pub mod http_client {
+    use crate::error::Error;
+
+    use super::Bootscript;
+
+    /// Get the iPXE bootscript BSS serves to a node, ref --> https://apidocs.svc.cscs.ch/iaas/bss/tag/bootscript/paths/~1bootscript/get/
+    /// The node is identified by either its xname, MAC or NID
+    pub async fn get_raw(
+        base_url: &str,
+        auth_token: &str,
+        root_cert: &[u8],
+        name_opt: Option<&str>,
+        mac_opt: Option<&str>,
+        nid_opt: Option<u32>,
+        arch_opt: Option<&str>,
+    ) -> Result<String, Error> {
+        if name_opt.is_none() && mac_opt.is_none() && nid_opt.is_none() {
+            return Err(Error::Message(
+                "Provide a node xname, MAC or NID to get its BSS bootscript".to_string(),
+            ));
+        }
+
+        let client_builder = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(root_cert)?);
+
+        // Build client
+        let client = if let Ok(socks5_env) = std::env::var("SOCKS5") {
+            // socks5 proxy
+            log::debug!("SOCKS5 enabled");
+            let socks5proxy = reqwest::Proxy::all(socks5_env)?;
+
+            // rest client to authenticate
+            client_builder.proxy(socks5proxy).build()?
+        } else {
+            client_builder.build()?
+        };
+
+        let api_url = format!("{}/bss/boot/v1/bootscript", base_url);
+
+        let nid_opt = nid_opt.map(|nid| nid.to_string());
+
+        let query_params = [
+            ("name", name_opt),
+            ("mac", mac_opt),
+            ("nid", nid_opt.as_deref()),
+            ("arch", arch_opt),
+        ];
+
+        let response = client
+            .get(api_url)
+            .query(&query_params)
+            .bearer_auth(auth_token)
+            .send()
+            .await
+            .map_err(Error::NetError)?;
+
+        if response.status().is_success() {
+            response.text().await.map_err(Error::NetError)
+        } else {
+            Err(Error::Message(
+                response.text().await.map_err(Error::NetError)?,
+            ))
+        }
+    }
+
+    pub async fn get(
+        base_url: &str,
+        auth_token: &str,
+        root_cert: &[u8],
+        name_opt: Option<&str>,
+        mac_opt: Option<&str>,
+        nid_opt: Option<u32>,
+        arch_opt: Option<&str>,
+    ) -> Result<Bootscript, Error> {
+        get_raw(
+            base_url, auth_token, root_cert, name_opt, mac_opt, nid_opt, arch_opt,
+        )
+        .await
+        .map(|bootscript| Bootscript::parse(&bootscript))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bootscript() {
+        let bootscript = Bootscript::parse(
+            "#!ipxe
+kernel --name kernel http://rgw-vip.nmn/boot-images/6c644208-104a-473d-802c-410219026335/kernel?X-Amz-Signature=abc initrd=initrd console=ttyS0,115200 quiet xname=x1000c1s7b0n0 nid=1 || goto boot_retry
+initrd --name initrd http://rgw-vip.nmn/boot-images/6c644208-104a-473d-802c-410219026335/initrd?X-Amz-Signature=abc || goto boot_retry
+boot || goto boot_retry
+:boot_retry
+sleep 30
+chain https://api-gw-service-nmn.local/apis/bss/boot/v1/bootscript?mac=b4:2e:99:be:1a:d3&retry=1",
+        );
+
+        assert_eq!(
+            bootscript.kernel.as_deref(),
+            Some("http://rgw-vip.nmn/boot-images/6c644208-104a-473d-802c-410219026335/kernel?X-Amz-Signature=abc")
+        );
+        assert_eq!(
+            bootscript.initrd.as_deref(),
+            Some("http://rgw-vip.nmn/boot-images/6c644208-104a-473d-802c-410219026335/initrd?X-Amz-Signature=abc")
+        );
+        assert_eq!(
+            bootscript.params,
+            "initrd=initrd console=ttyS0,115200 quiet xname=x1000c1s7b0n0 nid=1"
+        );
+        assert_eq!(
+            bootscript.get_kernel_cmdline().get("xname"),
+            Some("x1000c1s7b0n0")
+        );
+    }
+}
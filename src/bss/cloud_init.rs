@@ -0,0 +1,587 @@
+//! Typed cloud-init data stored in BSS boot parameters. BSS serves `meta-data` and `user-data` to
+//! the nodes and receives their `phone-home` data. The boot parameters entry with host `Global`
+//! holds the cloud-init data common to all nodes
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{error::Error, hsm};
+
+use super::bootparameters::{http_client, BootParameters};
+
+/// BSS host holding the cloud-init data common to all nodes
+pub const GLOBAL_HOST: &str = "Global";
+
+const CLOUD_CONFIG_HEADER: &str = "#cloud-config";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct CloudInit {
+    #[serde(rename = "meta-data")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta_data: Option<Map<String, Value>>,
+    /// cloud-config
+    #[serde(rename = "user-data")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_data: Option<Map<String, Value>>,
+    /// Data sent by the node once cloud-init finishes
+    #[serde(rename = "phone-home")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phone_home: Option<PhoneHome>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct PhoneHome {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pub_key_dsa: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pub_key_rsa: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pub_key_ecdsa: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fqdn: Option<String>,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum CloudInitValidationError {
+    #[error("user-data is not cloud-config, it must start with '{CLOUD_CONFIG_HEADER}'")]
+    MissingCloudConfigHeader,
+    #[error("user-data is not valid YAML: {0}")]
+    InvalidYaml(String),
+    #[error("user-data must be a YAML mapping")]
+    NotAMapping,
+    #[error("user-data key '{key}' {reason}")]
+    InvalidKey { key: String, reason: String },
+}
+
+impl CloudInit {
+    /// Sets user-data from a cloud-config YAML document
+    pub fn set_user_data_from_cloud_config(
+        &mut self,
+        cloud_config: &str,
+    ) -> Result<(), Vec<CloudInitValidationError>> {
+        if !cloud_config.trim_start().starts_with(CLOUD_CONFIG_HEADER) {
+            return Err(vec![CloudInitValidationError::MissingCloudConfigHeader]);
+        }
+
+        let user_data = match serde_yaml::from_str::<Value>(cloud_config) {
+            Ok(Value::Object(user_data)) => user_data,
+            Ok(Value::Null) => Map::new(),
+            Ok(_) => return Err(vec![CloudInitValidationError::NotAMapping]),
+            Err(error) => {
+                return Err(vec![CloudInitValidationError::InvalidYaml(
+                    error.to_string(),
+                )])
+            }
+        };
+
+        validate_user_data(&user_data)?;
+
+        self.user_data = Some(user_data);
+
+        Ok(())
+    }
+
+    /// Returns user-data as a cloud-config YAML document
+    pub fn get_cloud_config(&self) -> Result<String, Error> {
+        let user_data = serde_yaml::to_string(&self.user_data.clone().unwrap_or_default())
+            .map_err(|error| Error::Message(error.to_string()))?;
+
+        Ok(format!("{}\n{}", CLOUD_CONFIG_HEADER, user_data))
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<CloudInitValidationError>> {
+        self.user_data.as_ref().map_or(Ok(()), validate_user_data)
+    }
+
+    /// Returns `self` with `overrides` applied on top. meta-data and user-data are merged key by
+    /// key (nested mappings are merged too, lists are replaced), phone-home is replaced
+    pub fn merge(&self, overrides: &CloudInit) -> CloudInit {
+        let merge_map = |base: &Option<Map<String, Value>>,
+                         overrides: &Option<Map<String, Value>>| {
+            match (base, overrides) {
+                (Some(base), Some(overrides)) => {
+                    let mut merged = Value::Object(base.clone());
+                    merge_value(&mut merged, &Value::Object(overrides.clone()));
+                    merged.as_object().cloned()
+                }
+                (base, None) => base.clone(),
+                (None, overrides) => overrides.clone(),
+            }
+        };
+
+        CloudInit {
+            meta_data: merge_map(&self.meta_data, &overrides.meta_data),
+            user_data: merge_map(&self.user_data, &overrides.user_data),
+            phone_home: overrides
+                .phone_home
+                .clone()
+                .or_else(|| self.phone_home.clone()),
+        }
+    }
+}
+
+fn merge_value(base: &mut Value, overrides: &Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                merge_value(base.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+        (base, overrides) => *base = overrides.clone(),
+    }
+}
+
+/// Checks the cloud-config modules most used in the boot parameters have the type cloud-init
+/// expects. Unknown keys are not checked
+fn validate_user_data(user_data: &Map<String, Value>) -> Result<(), Vec<CloudInitValidationError>> {
+    let mut error_vec = Vec::new();
+
+    let mut invalid_key = |key: &str, reason: &str| {
+        error_vec.push(CloudInitValidationError::InvalidKey {
+            key: key.to_string(),
+            reason: reason.to_string(),
+        })
+    };
+
+    let is_string_list = |value: &Value| {
+        value
+            .as_array()
+            .is_some_and(|value_vec| value_vec.iter().all(Value::is_string))
+    };
+
+    for (key, value) in user_data {
+        match key.as_str() {
+            "ssh_authorized_keys" | "runcmd" | "bootcmd" | "packages" if !value.is_array() => {
+                invalid_key(key, "must be a list")
+            }
+            "ssh_authorized_keys" if !is_string_list(value) => {
+                invalid_key(key, "must be a list of public keys")
+            }
+            "hostname" | "fqdn" | "local-hostname" | "timezone" if !value.is_string() => {
+                invalid_key(key, "must be a string")
+            }
+            "ntp" => match value.as_object() {
+                Some(ntp) => {
+                    for ntp_key in ["servers", "pools"] {
+                        if ntp.get(ntp_key).is_some_and(|value| !is_string_list(value)) {
+                            invalid_key(
+                                &format!("ntp.{}", ntp_key),
+                                "must be a list of hostnames or IP addresses",
+                            );
+                        }
+                    }
+                }
+                None => invalid_key(key, "must be a mapping"),
+            },
+            "write_files" => {
+                let is_valid = value.as_array().is_some_and(|file_vec| {
+                    file_vec.iter().all(|file| {
+                        file["path"]
+                            .as_str()
+                            .is_some_and(|path| path.starts_with('/'))
+                    })
+                });
+
+                if !is_valid {
+                    invalid_key(key, "must be a list of files with an absolute 'path'")
+                }
+            }
+            "users" => {
+                let is_valid = value.as_array().is_some_and(|user_vec| {
+                    user_vec
+                        .iter()
+                        .all(|user| user.is_string() || user["name"].is_string())
+                });
+
+                if !is_valid {
+                    invalid_key(key, "must be a list of users with a 'name'")
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if error_vec.is_empty() {
+        Ok(())
+    } else {
+        Err(error_vec)
+    }
+}
+
+/// Get the cloud-init data of a node, or the global one if `xname` is `GLOBAL_HOST`
+pub async fn get(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname: &str,
+) -> Result<CloudInit, Error> {
+    get_boot_parameters(shasta_token, shasta_base_url, shasta_root_cert, xname)
+        .await?
+        .get_cloud_init()
+}
+
+/// Replace the cloud-init data of nodes, or the global one if `xname_vec` contains `GLOBAL_HOST`.
+/// user-data is validated before updating BSS
+pub async fn set(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: &[String],
+    cloud_init: &CloudInit,
+) -> Result<(), Error> {
+    cloud_init.validate().map_err(validation_error)?;
+
+    for xname in xname_vec {
+        let boot_parameters =
+            get_boot_parameters(shasta_token, shasta_base_url, shasta_root_cert, xname).await?;
+
+        put_boot_parameters(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            get_node_boot_parameters(&boot_parameters, xname, cloud_init)?,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Applies cloud-init defaults to all nodes in an HSM group, `node_override_map` (xname -->
+/// cloud-init) overrides the defaults for specific nodes. The current cloud-init data of each node
+/// is merged on top of the defaults and the overrides on top of both (see `CloudInit::merge`), so
+/// node specific keys already in BSS are kept and the precedence is
+/// group defaults < current node cloud-init data < node override.
+/// All nodes are validated before updating BSS. Returns the cloud-init data set to each node
+pub async fn apply_hsm_group_defaults(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    hsm_group_name: &str,
+    defaults: &CloudInit,
+    node_override_map: &HashMap<String, CloudInit>,
+    dry_run: bool,
+) -> Result<HashMap<String, CloudInit>, Error> {
+    let xname_vec = hsm::group::utils::get_member_vec_from_hsm_name_vec(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        vec![hsm_group_name.to_string()],
+    )
+    .await;
+
+    if xname_vec.is_empty() {
+        return Err(Error::Message(format!(
+            "HSM group '{}' not found or has no members",
+            hsm_group_name
+        )));
+    }
+
+    if let Some(xname) = node_override_map
+        .keys()
+        .find(|xname| !xname_vec.contains(xname))
+    {
+        return Err(Error::Message(format!(
+            "Node '{}' is not a member of HSM group '{}'",
+            xname, hsm_group_name
+        )));
+    }
+
+    let boot_parameters_vec =
+        http_client::get(shasta_token, shasta_base_url, shasta_root_cert, &xname_vec).await?;
+
+    let mut node_boot_parameters_vec = Vec::new();
+
+    for xname in &xname_vec {
+        let Some(boot_parameters) = boot_parameters_vec
+            .iter()
+            .find(|boot_parameters| boot_parameters.hosts.contains(xname))
+        else {
+            return Err(Error::Message(format!(
+                "Node '{}' has no BSS boot parameters",
+                xname
+            )));
+        };
+
+        let cloud_init = get_node_cloud_init(
+            defaults,
+            &boot_parameters.get_cloud_init()?,
+            node_override_map.get(xname),
+        );
+
+        cloud_init.validate().map_err(|error_vec| {
+            Error::Message(format!("Node '{}': {}", xname, validation_error(error_vec)))
+        })?;
+
+        let node_boot_parameters = get_node_boot_parameters(boot_parameters, xname, &cloud_init)?;
+
+        node_boot_parameters_vec.push((cloud_init, node_boot_parameters));
+    }
+
+    let mut node_cloud_init_map = HashMap::new();
+
+    for (cloud_init, node_boot_parameters) in node_boot_parameters_vec {
+        let xname = node_boot_parameters.hosts[0].clone();
+
+        if !dry_run {
+            log::info!("Update cloud-init data for node '{}'", xname);
+
+            put_boot_parameters(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                node_boot_parameters,
+            )
+            .await?;
+        }
+
+        node_cloud_init_map.insert(xname, cloud_init);
+    }
+
+    Ok(node_cloud_init_map)
+}
+
+/// Cloud-init data of a node, see `apply_hsm_group_defaults` for the precedence
+fn get_node_cloud_init(
+    defaults: &CloudInit,
+    current: &CloudInit,
+    node_override_opt: Option<&CloudInit>,
+) -> CloudInit {
+    let cloud_init = defaults.merge(current);
+
+    match node_override_opt {
+        Some(node_override) => cloud_init.merge(node_override),
+        None => cloud_init,
+    }
+}
+
+/// Boot parameters to update the cloud-init data of a single node. BSS entries may be shared by
+/// many nodes, the entry is narrowed to `xname` so the other nodes in it are not modified
+fn get_node_boot_parameters(
+    boot_parameters: &BootParameters,
+    xname: &str,
+    cloud_init: &CloudInit,
+) -> Result<BootParameters, Error> {
+    let mut node_boot_parameters = BootParameters {
+        hosts: vec![xname.to_string()],
+        macs: None,
+        nids: None,
+        ..boot_parameters.clone()
+    };
+
+    node_boot_parameters.set_cloud_init(cloud_init)?;
+
+    Ok(node_boot_parameters)
+}
+
+async fn get_boot_parameters(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname: &str,
+) -> Result<BootParameters, Error> {
+    http_client::get_raw(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        &[xname.to_string()],
+    )
+    .await?
+    .into_iter()
+    .find(|boot_parameters| boot_parameters.hosts.iter().any(|host| host == xname))
+    .ok_or_else(|| Error::Message(format!("No BSS boot parameters found for '{}'", xname)))
+}
+
+async fn put_boot_parameters(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    boot_parameters: BootParameters,
+) -> Result<(), Error> {
+    http_client::put(
+        shasta_base_url,
+        shasta_token,
+        shasta_root_cert,
+        boot_parameters,
+    )
+    .await
+    .map(|_| ())
+}
+
+fn validation_error(error_vec: Vec<CloudInitValidationError>) -> Error {
+    Error::Message(format!(
+        "Invalid cloud-init user-data: {}",
+        error_vec
+            .iter()
+            .map(|error| error.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cloud_config() {
+        let mut cloud_init = CloudInit::default();
+
+        cloud_init
+            .set_user_data_from_cloud_config(
+                "#cloud-config
+ssh_authorized_keys:
+  - ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIB admin@cscs.ch
+ntp:
+  enabled: true
+  servers:
+    - ntp1.cscs.ch
+",
+            )
+            .unwrap();
+
+        assert_eq!(
+            cloud_init.user_data.as_ref().unwrap()["ntp"]["servers"][0],
+            "ntp1.cscs.ch"
+        );
+        assert!(cloud_init
+            .get_cloud_config()
+            .unwrap()
+            .starts_with("#cloud-config\n"));
+
+        assert_eq!(
+            cloud_init.set_user_data_from_cloud_config("ntp: {}"),
+            Err(vec![CloudInitValidationError::MissingCloudConfigHeader])
+        );
+
+        let error_vec = cloud_init
+            .set_user_data_from_cloud_config(
+                "#cloud-config
+ssh_authorized_keys: ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIB admin@cscs.ch
+ntp:
+  servers: ntp1.cscs.ch
+write_files:
+  - path: etc/motd
+",
+            )
+            .unwrap_err();
+
+        assert_eq!(error_vec.len(), 3);
+    }
+
+    #[test]
+    fn test_merge() {
+        let defaults: CloudInit = serde_json::from_value(serde_json::json!({
+            "meta-data": { "region": "lugano" },
+            "user-data": {
+                "ntp": { "enabled": true, "servers": ["ntp1.cscs.ch", "ntp2.cscs.ch"] },
+                "timezone": "Europe/Zurich"
+            }
+        }))
+        .unwrap();
+
+        let node_override: CloudInit = serde_json::from_value(serde_json::json!({
+            "user-data": {
+                "ntp": { "servers": ["ntp3.cscs.ch"] },
+                "hostname": "nid000001"
+            }
+        }))
+        .unwrap();
+
+        let cloud_init = defaults.merge(&node_override);
+
+        assert_eq!(
+            serde_json::to_value(&cloud_init).unwrap(),
+            serde_json::json!({
+                "meta-data": { "region": "lugano" },
+                "user-data": {
+                    "ntp": { "enabled": true, "servers": ["ntp3.cscs.ch"] },
+                    "timezone": "Europe/Zurich",
+                    "hostname": "nid000001"
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_get_node_cloud_init() {
+        let defaults: CloudInit = serde_json::from_value(serde_json::json!({
+            "meta-data": { "region": "lugano", "cluster": "alps" },
+            "user-data": { "timezone": "Europe/Zurich", "hostname": "default" }
+        }))
+        .unwrap();
+
+        let current: CloudInit = serde_json::from_value(serde_json::json!({
+            "meta-data": { "cluster": "daint", "rack": "c1" },
+            "user-data": { "hostname": "nid000001" }
+        }))
+        .unwrap();
+
+        let node_override: CloudInit = serde_json::from_value(serde_json::json!({
+            "meta-data": { "rack": "c2" }
+        }))
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(get_node_cloud_init(&defaults, &current, None)).unwrap(),
+            serde_json::json!({
+                "meta-data": { "region": "lugano", "cluster": "daint", "rack": "c1" },
+                "user-data": { "timezone": "Europe/Zurich", "hostname": "nid000001" }
+            })
+        );
+
+        assert_eq!(
+            serde_json::to_value(get_node_cloud_init(
+                &defaults,
+                &current,
+                Some(&node_override)
+            ))
+            .unwrap(),
+            serde_json::json!({
+                "meta-data": { "region": "lugano", "cluster": "daint", "rack": "c2" },
+                "user-data": { "timezone": "Europe/Zurich", "hostname": "nid000001" }
+            })
+        );
+    }
+
+    #[test]
+    fn test_get_node_boot_parameters() {
+        let shared_boot_parameters: BootParameters = serde_json::from_value(serde_json::json!({
+            "hosts": ["x1000c1s7b0n0", "x1000c1s7b0n1"],
+            "macs": ["02:00:00:00:00:01", "02:00:00:00:00:02"],
+            "nids": [1, 2],
+            "params": "console=ttyS0",
+            "kernel": "s3://boot-images/image-id/kernel",
+            "initrd": "s3://boot-images/image-id/initrd",
+            "cloud-init": { "user-data": { "hostname": "shared" } }
+        }))
+        .unwrap();
+
+        let cloud_init: CloudInit = serde_json::from_value(serde_json::json!({
+            "user-data": { "hostname": "nid000001" }
+        }))
+        .unwrap();
+
+        let node_boot_parameters =
+            get_node_boot_parameters(&shared_boot_parameters, "x1000c1s7b0n0", &cloud_init)
+                .unwrap();
+
+        assert_eq!(
+            node_boot_parameters.hosts,
+            vec!["x1000c1s7b0n0".to_string()]
+        );
+        assert!(node_boot_parameters.macs.is_none());
+        assert!(node_boot_parameters.nids.is_none());
+        assert_eq!(node_boot_parameters.params, shared_boot_parameters.params);
+        assert_eq!(node_boot_parameters.kernel, shared_boot_parameters.kernel);
+        assert_eq!(node_boot_parameters.get_cloud_init().unwrap(), cloud_init);
+        // Shared entry is left untouched
+        assert_eq!(shared_boot_parameters.hosts.len(), 2);
+    }
+}
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+use super::{bootparameters::BootParameters, hosts::Host};
+
+/// Everything BSS uses to build the bootscripts: the hosts it knows about and all boot
+/// parameters
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DumpState {
+    #[serde(default)]
+    #[serde(rename = "Components")]
+    pub components: Vec<Host>,
+    #[serde(default)]
+    #[serde(rename = "Params")]
+    pub params: Vec<BootParameters>,
+}
+
+pub mod http_client {
+    use crate::error::Error;
+
+    use super::DumpState;
+
+    /// Get BSS internal state, ref --> https://apidocs.svc.cscs.ch/iaas/bss/tag/dumpstate/paths/~1dumpstate/get/
+    pub async fn get(
+        base_url: &str,
+        auth_token: &str,
+        root_cert: &[u8],
+    ) -> Result<DumpState, Error> {
+        let client_builder = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(root_cert)?);
+
+        // Build client
+        let client = if let Ok(socks5_env) = std::env::var("SOCKS5") {
+            // socks5 proxy
+            log::debug!("SOCKS5 enabled");
+            let socks5proxy = reqwest::Proxy::all(socks5_env)?;
+
+            // rest client to authenticate
+            client_builder.proxy(socks5proxy).build()?
+        } else {
+            client_builder.build()?
+        };
+
+        let api_url = format!("{}/bss/boot/v1/dumpstate", base_url);
+
+        let response = client
+            .get(api_url)
+            .bearer_auth(auth_token)
+            .send()
+            .await
+            .map_err(Error::NetError)?;
+
+        if response.status().is_success() {
+            response.json().await.map_err(Error::NetError)
+        } else {
+            Err(Error::Message(
+                response.text().await.map_err(Error::NetError)?,
+            ))
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+/// BSS endpoints which accesses are recorded
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint {
+    #[serde(rename = "bootscript")]
+    Bootscript,
+    #[serde(rename = "user-data")]
+    UserData,
+}
+
+impl Endpoint {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Endpoint::Bootscript => "bootscript",
+            Endpoint::UserData => "user-data",
+        }
+    }
+}
+
+/// Last time a node called a BSS endpoint, eg last time a node asked for its bootscript
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EndpointAccess {
+    pub name: String,
+    pub endpoint: Endpoint,
+    /// Unix timestamp
+    pub last_epoch: i64,
+}
+
+impl EndpointAccess {
+    pub fn get_last_access(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        chrono::DateTime::from_timestamp(self.last_epoch, 0)
+    }
+}
+
+pub mod http_client {
+    use crate::error::Error;
+
+    use super::{Endpoint, EndpointAccess};
+
+    /// Get the last access of nodes to BSS endpoints, ref --> https://apidocs.svc.cscs.ch/iaas/bss/tag/endpoint-history/paths/~1endpoint-history/get/
+    pub async fn get(
+        base_url: &str,
+        auth_token: &str,
+        root_cert: &[u8],
+        name_opt: Option<&str>,
+        endpoint_opt: Option<Endpoint>,
+    ) -> Result<Vec<EndpointAccess>, Error> {
+        let client_builder = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(root_cert)?);
+
+        // Build client
+        let client = if let Ok(socks5_env) = std::env::var("SOCKS5") {
+            // socks5 proxy
+            log::debug!("SOCKS5 enabled");
+            let socks5proxy = reqwest::Proxy::all(socks5_env)?;
+
+            // rest client to authenticate
+            client_builder.proxy(socks5proxy).build()?
+        } else {
+            client_builder.build()?
+        };
+
+        let api_url = format!("{}/bss/boot/v1/endpoint-history", base_url);
+
+        let query_params = [
+            ("name", name_opt),
+            ("endpoint", endpoint_opt.as_ref().map(Endpoint::as_str)),
+        ];
+
+        let response = client
+            .get(api_url)
+            .query(&query_params)
+            .bearer_auth(auth_token)
+            .send()
+            .await
+            .map_err(Error::NetError)?;
+
+        if response.status().is_success() {
+            // BSS returns null if there is no history
+            response
+                .json::<Option<Vec<EndpointAccess>>>()
+                .await
+                .map(Option::unwrap_or_default)
+                .map_err(Error::NetError)
+        } else {
+            Err(Error::Message(
+                response.text().await.map_err(Error::NetError)?,
+            ))
+        }
+    }
+}
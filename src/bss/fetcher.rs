@@ -0,0 +1,352 @@
+//! Fetch BSS boot parameters for large node sets. Nodes are requested in chunks running
+//! concurrently, chunks failing don't stop the others so callers get partial results plus the
+//! list of chunks that failed
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+
+use crate::error::Error;
+
+use super::bootparameters::{http_client, BootParameters};
+
+/// BSS identifies a boot parameters entry by its hosts, MACs and NIDs
+type EntryId = (Vec<String>, Vec<String>, Vec<u32>);
+
+/// Chunk of nodes which boot parameters could not be fetched
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChunkError {
+    pub xname_vec: Vec<String>,
+    pub error: String,
+}
+
+/// Boot parameters indexed by xname, MAC and NID. An entry shared by several nodes (eg an entry
+/// with hosts [x1000c1s7b0n0, x1000c1s7b0n1]) is stored once
+#[derive(Debug, Clone, Default)]
+pub struct BootParametersIndex {
+    boot_parameters_vec: Vec<BootParameters>,
+    xname_map: HashMap<String, usize>,
+    mac_map: HashMap<String, usize>,
+    nid_map: HashMap<u32, usize>,
+}
+
+impl BootParametersIndex {
+    pub fn new(boot_parameters_vec: Vec<BootParameters>) -> Self {
+        let mut index = Self::default();
+
+        let mut entry_id_set: HashSet<EntryId> = HashSet::new();
+
+        for boot_parameters in boot_parameters_vec {
+            let mut host_vec = boot_parameters.hosts.clone();
+            host_vec.sort();
+            let mut mac_vec = boot_parameters.macs.clone().unwrap_or_default();
+            mac_vec.sort();
+            let mut nid_vec = boot_parameters.nids.clone().unwrap_or_default();
+            nid_vec.sort();
+
+            if !entry_id_set.insert((host_vec, mac_vec, nid_vec)) {
+                continue;
+            }
+
+            let position = index.boot_parameters_vec.len();
+
+            for host in &boot_parameters.hosts {
+                index.xname_map.insert(host.clone(), position);
+            }
+
+            for mac in boot_parameters.macs.iter().flatten() {
+                index.mac_map.insert(mac.to_lowercase(), position);
+            }
+
+            for nid in boot_parameters.nids.iter().flatten() {
+                index.nid_map.insert(*nid, position);
+            }
+
+            index.boot_parameters_vec.push(boot_parameters);
+        }
+
+        index
+    }
+
+    pub fn get_by_xname(&self, xname: &str) -> Option<&BootParameters> {
+        self.xname_map
+            .get(xname)
+            .map(|position| &self.boot_parameters_vec[*position])
+    }
+
+    /// MACs are case insensitive
+    pub fn get_by_mac(&self, mac: &str) -> Option<&BootParameters> {
+        self.mac_map
+            .get(&mac.to_lowercase())
+            .map(|position| &self.boot_parameters_vec[*position])
+    }
+
+    pub fn get_by_nid(&self, nid: u32) -> Option<&BootParameters> {
+        self.nid_map
+            .get(&nid)
+            .map(|position| &self.boot_parameters_vec[*position])
+    }
+
+    pub fn contains_xname(&self, xname: &str) -> bool {
+        self.xname_map.contains_key(xname)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BootParameters> {
+        self.boot_parameters_vec.iter()
+    }
+
+    /// Number of boot parameters entries, not number of nodes
+    pub fn len(&self) -> usize {
+        self.boot_parameters_vec.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.boot_parameters_vec.is_empty()
+    }
+
+    pub fn into_vec(self) -> Vec<BootParameters> {
+        self.boot_parameters_vec
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BootParametersFetchResult {
+    pub index: BootParametersIndex,
+    pub chunk_error_vec: Vec<ChunkError>,
+}
+
+impl BootParametersFetchResult {
+    /// True if all chunks were fetched
+    pub fn is_complete(&self) -> bool {
+        self.chunk_error_vec.is_empty()
+    }
+
+    /// Returns the boot parameters fetched, fails if any chunk failed
+    pub fn into_complete(self) -> Result<BootParametersIndex, Error> {
+        if self.is_complete() {
+            return Ok(self.index);
+        }
+
+        Err(Error::Message(format!(
+            "Could not get BSS boot parameters for {} chunks of nodes. Reason:\n{}",
+            self.chunk_error_vec.len(),
+            self.chunk_error_vec
+                .iter()
+                .map(|chunk_error| chunk_error.error.as_str())
+                .collect::<Vec<&str>>()
+                .join("\n")
+        )))
+    }
+
+    /// Nodes requested without boot parameters in the result, either because BSS has no boot
+    /// parameters for them or because their chunk failed
+    pub fn get_missing_xname_vec(&self, xname_vec: &[String]) -> Vec<String> {
+        xname_vec
+            .iter()
+            .filter(|xname| !self.index.contains_xname(xname))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Fetch BSS boot parameters in chunks of nodes, works like `bootparameters::http_client::get`
+/// but the chunk size and the number of concurrent requests can be tuned
+///
+/// eg:
+///
+/// let result = BootParametersFetcher::new(shasta_token, shasta_base_url, shasta_root_cert)
+///     .chunk_size(50)
+///     .concurrency(20)
+///     .fetch(&xname_vec)
+///     .await;
+pub struct BootParametersFetcher {
+    shasta_token: String,
+    shasta_base_url: String,
+    shasta_root_cert: Vec<u8>,
+    chunk_size: usize,
+    concurrency: usize,
+}
+
+impl BootParametersFetcher {
+    pub fn new(shasta_token: &str, shasta_base_url: &str, shasta_root_cert: &[u8]) -> Self {
+        Self {
+            shasta_token: shasta_token.to_string(),
+            shasta_base_url: shasta_base_url.to_string(),
+            shasta_root_cert: shasta_root_cert.to_vec(),
+            chunk_size: 30,
+            concurrency: 10,
+        }
+    }
+
+    /// Number of nodes per request. Defaults to 30
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Max number of concurrent requests. Defaults to 10, CSM 1.3.1 does not handle many more
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub async fn fetch(&self, xname_vec: &[String]) -> BootParametersFetchResult {
+        let start = Instant::now();
+
+        let mut xname_vec = xname_vec.to_vec();
+        xname_vec.sort();
+        xname_vec.dedup();
+
+        let mut boot_parameters_vec = Vec::new();
+        let mut chunk_error_vec = Vec::new();
+
+        let mut tasks = tokio::task::JoinSet::new();
+
+        let sem = Arc::new(Semaphore::new(self.concurrency));
+
+        for chunk in xname_vec.chunks(self.chunk_size) {
+            let shasta_token = self.shasta_token.clone();
+            let shasta_base_url = self.shasta_base_url.clone();
+            let shasta_root_cert = self.shasta_root_cert.clone();
+
+            let chunk_xname_vec = chunk.to_vec();
+
+            let permit = Arc::clone(&sem).acquire_owned().await;
+
+            tasks.spawn(async move {
+                let _permit = permit; // Wait semaphore to allow new tasks https://github.com/tokio-rs/tokio/discussions/2648#discussioncomment-34885
+
+                let result = http_client::get_raw(
+                    &shasta_token,
+                    &shasta_base_url,
+                    &shasta_root_cert,
+                    &chunk_xname_vec,
+                )
+                .await;
+
+                (chunk_xname_vec, result)
+            });
+        }
+
+        while let Some(message) = tasks.join_next().await {
+            match message {
+                Ok((_, Ok(mut chunk_boot_parameters_vec))) => {
+                    boot_parameters_vec.append(&mut chunk_boot_parameters_vec)
+                }
+                Ok((chunk_xname_vec, Err(error))) => {
+                    log::warn!(
+                        "Could not get BSS bootparameters for {} nodes. Reason:\n{}",
+                        chunk_xname_vec.len(),
+                        error
+                    );
+
+                    chunk_error_vec.push(ChunkError {
+                        xname_vec: chunk_xname_vec,
+                        error: error.to_string(),
+                    })
+                }
+                // Task panicked or was cancelled, nodes in the chunk are unknown at this point
+                Err(error) => chunk_error_vec.push(ChunkError {
+                    xname_vec: Vec::new(),
+                    error: error.to_string(),
+                }),
+            }
+        }
+
+        log::info!(
+            "Time elapsed to get BSS bootparameters is: {:?}",
+            start.elapsed()
+        );
+
+        BootParametersFetchResult {
+            index: BootParametersIndex::new(boot_parameters_vec),
+            chunk_error_vec,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boot_parameters_index() {
+        let shared_boot_parameters = BootParameters::new(
+            vec!["x1000c1s7b0n0", "x1000c1s7b0n1"],
+            Some(vec!["B4:2E:99:BE:1A:D3"]),
+            Some(vec!["1", "2"]),
+            "quiet",
+            "s3://boot-images/6c644208-104a-473d-802c-410219026335/kernel",
+            "s3://boot-images/6c644208-104a-473d-802c-410219026335/initrd",
+            None,
+        );
+
+        // Same entry returned by the chunks of both nodes
+        let index = BootParametersIndex::new(vec![
+            shared_boot_parameters.clone(),
+            BootParameters::new(
+                vec!["x1000c1s7b1n0"],
+                None,
+                Some(vec!["3"]),
+                "console=ttyS0",
+                "",
+                "",
+                None,
+            ),
+            shared_boot_parameters,
+        ]);
+
+        assert_eq!(index.len(), 2);
+        assert_eq!(
+            index
+                .get_by_xname("x1000c1s7b0n1")
+                .map(|bp| bp.params.as_str()),
+            Some("quiet")
+        );
+        assert_eq!(
+            index
+                .get_by_mac("b4:2e:99:be:1a:d3")
+                .map(|bp| bp.params.as_str()),
+            Some("quiet")
+        );
+        assert_eq!(
+            index.get_by_nid(3).map(|bp| bp.params.as_str()),
+            Some("console=ttyS0")
+        );
+        assert!(index.get_by_xname("x1000c1s7b1n1").is_none());
+
+        let result = BootParametersFetchResult {
+            index,
+            chunk_error_vec: Vec::new(),
+        };
+
+        assert_eq!(
+            result
+                .get_missing_xname_vec(&["x1000c1s7b0n0".to_string(), "x1000c1s7b1n1".to_string()]),
+            vec!["x1000c1s7b1n1"]
+        );
+    }
+
+    #[test]
+    fn test_into_complete() {
+        let result = BootParametersFetchResult {
+            index: BootParametersIndex::default(),
+            chunk_error_vec: vec![ChunkError {
+                xname_vec: vec!["x1000c1s7b0n0".to_string()],
+                error: "503 Service Unavailable".to_string(),
+            }],
+        };
+
+        assert!(matches!(
+            result.into_complete(),
+            Err(Error::Message(message)) if message.contains("503 Service Unavailable")
+        ));
+
+        assert!(BootParametersFetchResult::default().into_complete().is_ok());
+    }
+}
@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hsm::component::types::Component;
+
+/// HSM component as cached by BSS, BSS adds the network details it uses to identify the node
+/// asking for a bootscript
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Host {
+    #[serde(flatten)]
+    pub component: Component,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "FQDN")]
+    pub fqdn: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "MAC")]
+    pub mac: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "EndpointEnabled")]
+    pub endpoint_enabled: Option<bool>,
+}
+
+pub mod http_client {
+    use crate::error::Error;
+
+    use super::Host;
+
+    /// Get the hosts BSS knows about, ref --> https://apidocs.svc.cscs.ch/iaas/bss/tag/hosts/paths/~1hosts/get/
+    pub async fn get(
+        base_url: &str,
+        auth_token: &str,
+        root_cert: &[u8],
+        name_opt: Option<&str>,
+        mac_opt: Option<&str>,
+        nid_opt: Option<u32>,
+    ) -> Result<Vec<Host>, Error> {
+        let client_builder = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(root_cert)?);
+
+        // Build client
+        let client = if let Ok(socks5_env) = std::env::var("SOCKS5") {
+            // socks5 proxy
+            log::debug!("SOCKS5 enabled");
+            let socks5proxy = reqwest::Proxy::all(socks5_env)?;
+
+            // rest client to authenticate
+            client_builder.proxy(socks5proxy).build()?
+        } else {
+            client_builder.build()?
+        };
+
+        let api_url = format!("{}/bss/boot/v1/hosts", base_url);
+
+        let nid_opt = nid_opt.map(|nid| nid.to_string());
+
+        let query_params = [
+            ("name", name_opt),
+            ("mac", mac_opt),
+            ("nid", nid_opt.as_deref()),
+        ];
+
+        let response = client
+            .get(api_url)
+            .query(&query_params)
+            .bearer_auth(auth_token)
+            .send()
+            .await
+            .map_err(Error::NetError)?;
+
+        if response.status().is_success() {
+            response.json().await.map_err(Error::NetError)
+        } else {
+            Err(Error::Message(
+                response.text().await.map_err(Error::NetError)?,
+            ))
+        }
+    }
+
+    /// Refresh the hosts BSS caches from HSM, ref --> https://apidocs.svc.cscs.ch/iaas/bss/tag/hosts/paths/~1hosts/post/
+    pub async fn post(base_url: &str, auth_token: &str, root_cert: &[u8]) -> Result<(), Error> {
+        let client_builder = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(root_cert)?);
+
+        // Build client
+        let client = if let Ok(socks5_env) = std::env::var("SOCKS5") {
+            // socks5 proxy
+            log::debug!("SOCKS5 enabled");
+            let socks5proxy = reqwest::Proxy::all(socks5_env)?;
+
+            // rest client to authenticate
+            client_builder.proxy(socks5proxy).build()?
+        } else {
+            client_builder.build()?
+        };
+
+        let api_url = format!("{}/bss/boot/v1/hosts", base_url);
+
+        let response = client
+            .post(api_url)
+            .bearer_auth(auth_token)
+            .send()
+            .await
+            .map_err(Error::NetError)?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error::Message(
+                response.text().await.map_err(Error::NetError)?,
+            ))
+        }
+    }
+}
@@ -0,0 +1,443 @@
+//! Kernel command line as stored in BSS boot parameters. Kernel parameters are kept in order,
+//! including duplicated keys (eg several `console=`) and flags without value (eg `quiet`).
+//! Parameters not modified are kept as they were, so parsing and printing a kernel command line
+//! returns the same string byte for byte
+
+use std::{fmt, str::FromStr};
+
+/// A single kernel parameter, eg `console=ttyS0,115200`, `quiet` or `dyndbg="file x.c +p"`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KernelParam {
+    key: String,
+    value: Option<String>,
+    /// Kernel parameter as written in the kernel command line
+    raw: String,
+    /// Whitespaces after the kernel parameter
+    separator: String,
+}
+
+impl KernelParam {
+    pub fn new(key: &str, value: Option<&str>) -> Self {
+        let raw = match value {
+            Some(value) if value.is_empty() || value.contains(char::is_whitespace) => {
+                format!("{}=\"{}\"", key, value)
+            }
+            Some(value) => format!("{}={}", key, value),
+            None => key.to_string(),
+        };
+
+        Self {
+            key: key.to_string(),
+            value: value.map(str::to_string),
+            raw,
+            separator: String::new(),
+        }
+    }
+
+    /// Parses a kernel parameter the same way the linux kernel does, quotes around the value
+    /// or around the whole parameter are removed
+    fn parse(raw: &str, separator: &str) -> Self {
+        let unquoted = match raw.strip_prefix('"') {
+            Some(unquoted) => unquoted.strip_suffix('"').unwrap_or(unquoted),
+            None => raw,
+        };
+
+        let (key, value) = match unquoted.split_once('=') {
+            Some((key, value)) => {
+                let value = match value.strip_prefix('"') {
+                    Some(value) => value.strip_suffix('"').unwrap_or(value),
+                    None => value,
+                };
+
+                (key, Some(value.to_string()))
+            }
+            None => (unquoted, None),
+        };
+
+        Self {
+            key: key.to_string(),
+            value,
+            raw: raw.to_string(),
+            separator: separator.to_string(),
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// None for flags (eg `quiet`)
+    pub fn value(&self) -> Option<&str> {
+        self.value.as_deref()
+    }
+
+    pub fn is_flag(&self) -> bool {
+        self.value.is_none()
+    }
+
+    /// Updates the value keeping the whitespaces after the kernel parameter. Returns true if the
+    /// value changed
+    fn set_value(&mut self, value: Option<&str>) -> bool {
+        if self.value.as_deref() == value {
+            return false;
+        }
+
+        let separator = std::mem::take(&mut self.separator);
+        *self = KernelParam::new(&self.key, value);
+        self.separator = separator;
+
+        true
+    }
+}
+
+impl fmt::Display for KernelParam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KernelCmdline {
+    /// Whitespaces before the first kernel parameter
+    prefix: String,
+    param_vec: Vec<KernelParam>,
+}
+
+impl KernelCmdline {
+    pub fn parse(kernel_cmdline: &str) -> Self {
+        let prefix_len = kernel_cmdline.len() - kernel_cmdline.trim_start().len();
+        let prefix = kernel_cmdline[..prefix_len].to_string();
+
+        let mut param_vec = Vec::new();
+        let mut rest = &kernel_cmdline[prefix_len..];
+
+        while !rest.is_empty() {
+            // Whitespaces inside quotes are part of the kernel parameter
+            let mut in_quote = false;
+            let param_len = rest
+                .char_indices()
+                .find(|(_, c)| {
+                    if *c == '"' {
+                        in_quote = !in_quote;
+                    }
+                    c.is_whitespace() && !in_quote
+                })
+                .map(|(index, _)| index)
+                .unwrap_or(rest.len());
+
+            let (raw, after) = rest.split_at(param_len);
+            let separator_len = after.len() - after.trim_start().len();
+            let (separator, after) = after.split_at(separator_len);
+
+            param_vec.push(KernelParam::parse(raw, separator));
+
+            rest = after;
+        }
+
+        Self { prefix, param_vec }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &KernelParam> {
+        self.param_vec.iter()
+    }
+
+    /// Number of kernel parameters, duplicated keys are counted once per occurrence
+    pub fn len(&self) -> usize {
+        self.param_vec.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.param_vec.is_empty()
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.param_vec.iter().any(|param| param.key == key)
+    }
+
+    /// True if both kernel command lines have the same kernel parameters in the same order,
+    /// regardless of quoting and whitespaces
+    pub fn is_equivalent(&self, other: &KernelCmdline) -> bool {
+        self.param_vec
+            .iter()
+            .map(|param| (param.key(), param.value()))
+            .eq(other
+                .param_vec
+                .iter()
+                .map(|param| (param.key(), param.value())))
+    }
+
+    /// Returns the value of the last occurrence of a kernel parameter, which is the one the
+    /// kernel uses. Flags return an empty string
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.param_vec
+            .iter()
+            .rev()
+            .find(|param| param.key == key)
+            .map(|param| param.value().unwrap_or_default())
+    }
+
+    /// Returns the values of all occurrences of a kernel parameter, eg all `console` values
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.param_vec
+            .iter()
+            .filter(|param| param.key == key)
+            .map(|param| param.value().unwrap_or_default())
+            .collect()
+    }
+
+    /// Returns the value of a kernel parameter parsed into `T`, eg `get_as::<u32>("rd.retry")`
+    pub fn get_as<T: FromStr>(&self, key: &str) -> Option<Result<T, T::Err>> {
+        self.get(key).map(str::parse)
+    }
+
+    /// Sets the value of a kernel parameter. All occurrences of the kernel parameter are updated
+    /// in place, so duplicated keys keep their number and position. If the kernel parameter does
+    /// not exist, it is appended
+    /// Returns true if the kernel command line changed
+    pub fn set(&mut self, key: &str, value: Option<&str>) -> bool {
+        if !self.contains(key) {
+            self.append(key, value);
+            return true;
+        }
+
+        let mut changed = false;
+
+        for param in self.param_vec.iter_mut().filter(|param| param.key == key) {
+            changed |= param.set_value(value);
+        }
+
+        changed
+    }
+
+    /// Replaces all occurrences of a kernel parameter as a group, eg to change
+    /// `console=tty0 console=ttyS0` with new `console` values. Occurrences are updated in order,
+    /// extra values are inserted after the last occurrence and occurrences left without value
+    /// are removed. If the kernel parameter does not exist, the values are appended
+    /// Returns true if the kernel command line changed
+    pub fn set_all(&mut self, key: &str, value_vec: &[Option<&str>]) -> bool {
+        let index_vec: Vec<usize> = self
+            .param_vec
+            .iter()
+            .enumerate()
+            .filter(|(_, param)| param.key == key)
+            .map(|(index, _)| index)
+            .collect();
+
+        let mut changed = false;
+
+        for (index, value) in index_vec.iter().zip(value_vec) {
+            changed |= self.param_vec[*index].set_value(*value);
+        }
+
+        match index_vec.last() {
+            None => {
+                for value in value_vec {
+                    self.append(key, *value);
+                    changed = true;
+                }
+            }
+            Some(last_index) if value_vec.len() > index_vec.len() => {
+                for (insert_index, value) in (last_index + 1..).zip(&value_vec[index_vec.len()..]) {
+                    // New kernel parameter takes the separator of the previous one so the
+                    // whitespaces at the end of the kernel command line are kept
+                    let mut param = KernelParam::new(key, *value);
+                    param.separator = std::mem::replace(
+                        &mut self.param_vec[insert_index - 1].separator,
+                        " ".to_string(),
+                    );
+
+                    self.param_vec.insert(insert_index, param);
+                }
+
+                changed = true;
+            }
+            Some(_) => {
+                let surplus_index_vec = &index_vec[value_vec.len()..];
+
+                let mut position = 0;
+                let removed = self.retain(|_| {
+                    let keep = !surplus_index_vec.contains(&position);
+                    position += 1;
+                    keep
+                });
+
+                changed |= removed > 0;
+            }
+        }
+
+        changed
+    }
+
+    /// Appends a kernel parameter at the end, even if the key already exists (eg to add a second
+    /// `console`)
+    pub fn append(&mut self, key: &str, value: Option<&str>) {
+        self.push(KernelParam::new(key, value));
+    }
+
+    /// Removes all occurrences of a kernel parameter. Returns the number of kernel parameters
+    /// removed
+    pub fn remove(&mut self, key: &str) -> usize {
+        self.retain(|param| param.key != key)
+    }
+
+    fn push(&mut self, param: KernelParam) {
+        if let Some(last) = self.param_vec.last_mut() {
+            if last.separator.is_empty() {
+                last.separator = " ".to_string();
+            }
+        }
+
+        self.param_vec.push(param);
+    }
+
+    /// Removes the kernel parameters not matching `f`. If the last kernel parameter is removed,
+    /// its separator is moved to the new last one so the kernel command line does not end with a
+    /// dangling separator. Returns the number of kernel parameters removed
+    fn retain<F>(&mut self, mut f: F) -> usize
+    where
+        F: FnMut(&KernelParam) -> bool,
+    {
+        let len = self.param_vec.len();
+
+        // `f` is called once per kernel parameter and in order
+        let keep_vec: Vec<bool> = self.param_vec.iter().map(&mut f).collect();
+
+        let trailing_separator_opt = match (self.param_vec.last(), keep_vec.last()) {
+            (Some(last), Some(false)) => Some(last.separator.clone()),
+            _ => None,
+        };
+
+        let mut keep_iter = keep_vec.into_iter();
+        self.param_vec.retain(|_| keep_iter.next().unwrap_or(true));
+
+        if let (Some(trailing_separator), Some(last)) =
+            (trailing_separator_opt, self.param_vec.last_mut())
+        {
+            last.separator = trailing_separator;
+        }
+
+        len - self.param_vec.len()
+    }
+}
+
+impl fmt::Display for KernelCmdline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.prefix)?;
+
+        for param in &self.param_vec {
+            write!(f, "{}{}", param.raw, param.separator)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for KernelCmdline {
+    type Err = std::convert::Infallible;
+
+    fn from_str(kernel_cmdline: &str) -> Result<Self, Self::Err> {
+        Ok(Self::parse(kernel_cmdline))
+    }
+}
+
+impl From<&str> for KernelCmdline {
+    fn from(kernel_cmdline: &str) -> Self {
+        Self::parse(kernel_cmdline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for kernel_cmdline in [
+            "",
+            "quiet",
+            "  console=tty0   console=ttyS0,115200 quiet  ",
+            "ds=nocloud-net;s=http://10.92.100.81:8888/ rootfallback=LABEL=BOOTRAID",
+            "dyndbg=\"file drivers/x.c +p\" \"foo=bar baz\" empty= spire_join_token=${SPIRE_JOIN_TOKEN}",
+        ] {
+            assert_eq!(KernelCmdline::parse(kernel_cmdline).to_string(), kernel_cmdline);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let kernel_cmdline = KernelCmdline::parse(
+            "console=tty0 console=ttyS0,115200 quiet dyndbg=\"file x.c +p\" rd.retry=10 root=live:LABEL=SQFSRAID",
+        );
+
+        assert_eq!(kernel_cmdline.len(), 6);
+        assert_eq!(kernel_cmdline.get("console"), Some("ttyS0,115200"));
+        assert_eq!(
+            kernel_cmdline.get_all("console"),
+            vec!["tty0", "ttyS0,115200"]
+        );
+        assert_eq!(kernel_cmdline.get("quiet"), Some(""));
+        assert_eq!(kernel_cmdline.get("dyndbg"), Some("file x.c +p"));
+        assert_eq!(kernel_cmdline.get("root"), Some("live:LABEL=SQFSRAID"));
+        assert_eq!(kernel_cmdline.get_as::<u32>("rd.retry"), Some(Ok(10)));
+        assert_eq!(kernel_cmdline.get("missing"), None);
+    }
+
+    #[test]
+    fn test_modify() {
+        let mut kernel_cmdline =
+            KernelCmdline::parse("console=tty0  quiet console=ttyS0,115200 crashkernel=360M");
+
+        assert!(!kernel_cmdline.set("crashkernel", Some("360M")));
+        assert!(kernel_cmdline.set("console", Some("ttyS1")));
+        assert_eq!(
+            kernel_cmdline.to_string(),
+            "console=ttyS1  quiet console=ttyS1 crashkernel=360M"
+        );
+
+        kernel_cmdline.append("dyndbg", Some("file x.c +p"));
+        kernel_cmdline.append("console", Some("tty0"));
+        assert_eq!(
+            kernel_cmdline.to_string(),
+            "console=ttyS1  quiet console=ttyS1 crashkernel=360M dyndbg=\"file x.c +p\" console=tty0"
+        );
+
+        assert_eq!(kernel_cmdline.remove("console"), 3);
+        assert_eq!(kernel_cmdline.remove("console"), 0);
+        assert_eq!(
+            kernel_cmdline.to_string(),
+            "quiet crashkernel=360M dyndbg=\"file x.c +p\""
+        );
+    }
+
+    #[test]
+    fn test_set_all() {
+        let mut kernel_cmdline =
+            KernelCmdline::parse("console=tty0  quiet console=ttyS0,115200 crashkernel=360M ");
+
+        assert!(!kernel_cmdline.set_all("console", &[Some("tty0"), Some("ttyS0,115200")]));
+
+        assert!(kernel_cmdline.set_all("console", &[Some("ttyS1"), Some("tty0"), Some("ttyS2")]));
+        assert_eq!(
+            kernel_cmdline.to_string(),
+            "console=ttyS1  quiet console=tty0 console=ttyS2 crashkernel=360M "
+        );
+
+        assert!(kernel_cmdline.set_all("console", &[Some("ttyS0")]));
+        assert_eq!(
+            kernel_cmdline.to_string(),
+            "console=ttyS0  quiet crashkernel=360M "
+        );
+
+        assert!(kernel_cmdline.set_all("crashkernel", &[Some("360M"), Some("512M")]));
+        assert_eq!(
+            kernel_cmdline.to_string(),
+            "console=ttyS0  quiet crashkernel=360M crashkernel=512M "
+        );
+
+        assert!(kernel_cmdline.set_all("rd.shell", &[None]));
+        assert_eq!(
+            kernel_cmdline.to_string(),
+            "console=ttyS0  quiet crashkernel=360M crashkernel=512M rd.shell"
+        );
+    }
+}
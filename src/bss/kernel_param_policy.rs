@@ -0,0 +1,618 @@
+//! Site policy for the kernel parameters in BSS boot parameters (eg `console` must be present,
+//! `rd.break` is forbidden, `ip` must follow a format). Policies are read from a YAML or JSON file:
+//!
+//! ```yaml
+//! required: [console]
+//! forbidden: [rd.break]
+//! values:
+//!   - key: ip
+//!     regex: '^(dhcp|none)$'
+//! hsm_groups:
+//!   - name: production
+//!     forbidden: [rd.debug, debug]
+//!   - name: test
+//!     ignore: [console]
+//! ```
+//!
+//! Rules in `hsm_groups` are added to the global ones for the nodes in those HSM groups, `ignore`
+//! drops the global rules for a kernel parameter and a value rule replaces the global value rule
+//! for the same kernel parameter.
+//!
+//! Once a policy is set for a CSM site with `set_site_policy`, all writers of BSS boot parameters
+//! (`bootparameters::http_client::post`/`put`/`patch` and the functions using them) check the
+//! kernel parameters against it before changing BSS
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{error::Error, hsm};
+
+use super::{
+    bootparameters::{http_client, BootParameters},
+    cloud_init::GLOBAL_HOST,
+    kernel_cmdline::KernelCmdline,
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ValueRule {
+    pub key: String,
+    /// All values of the kernel parameter must match, flags are checked as an empty value
+    pub regex: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct KernelParamRules {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forbidden: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<ValueRule>,
+    /// Kernel parameters which global rules are not applied, only used in HSM group rules
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct HsmGroupRules {
+    pub name: String,
+    #[serde(flatten)]
+    pub rules: KernelParamRules,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct KernelParamPolicy {
+    #[serde(flatten)]
+    pub rules: KernelParamRules,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hsm_groups: Vec<HsmGroupRules>,
+    /// Value rule regexes compiled when the policy is parsed, indexed by regex
+    #[serde(skip)]
+    regex_map: HashMap<String, Regex>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ViolationKind {
+    MissingRequired,
+    Forbidden,
+    InvalidValue { value: String, regex: String },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct KernelParamViolation {
+    /// BSS host (xname) the boot parameters belong to
+    pub host: String,
+    pub key: String,
+    #[serde(flatten)]
+    pub kind: ViolationKind,
+}
+
+impl std::fmt::Display for KernelParamViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ViolationKind::MissingRequired => write!(
+                f,
+                "'{}': required kernel parameter '{}' is missing",
+                self.host, self.key
+            ),
+            ViolationKind::Forbidden => write!(
+                f,
+                "'{}': kernel parameter '{}' is forbidden",
+                self.host, self.key
+            ),
+            ViolationKind::InvalidValue { value, regex } => write!(
+                f,
+                "'{}': kernel parameter '{}' value '{}' does not match '{}'",
+                self.host, self.key, value, regex
+            ),
+        }
+    }
+}
+
+impl KernelParamPolicy {
+    /// Reads a policy from a YAML or JSON string
+    pub fn parse(policy: &str) -> Result<Self, Error> {
+        let mut policy: Self = serde_yaml::from_str(policy)
+            .map_err(|error| Error::Message(format!("Invalid kernel param policy: {}", error)))?;
+
+        // Fail early on wrong regexes instead of when linting
+        let mut regex_map = HashMap::new();
+
+        for value_rule in policy.rules.values.iter().chain(
+            policy
+                .hsm_groups
+                .iter()
+                .flat_map(|group| &group.rules.values),
+        ) {
+            let regex = Regex::new(&value_rule.regex).map_err(|error| {
+                Error::Message(format!(
+                    "Invalid regex for kernel parameter '{}': {}",
+                    value_rule.key, error
+                ))
+            })?;
+
+            regex_map.insert(value_rule.regex.clone(), regex);
+        }
+
+        policy.regex_map = regex_map;
+
+        Ok(policy)
+    }
+
+    pub fn from_file(path: &std::path::Path) -> Result<Self, Error> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Rules applying to a node member of `hsm_group_vec`
+    pub fn get_rules(&self, hsm_group_vec: &[String]) -> KernelParamRules {
+        let group_rules_vec: Vec<&KernelParamRules> = self
+            .hsm_groups
+            .iter()
+            .filter(|group| hsm_group_vec.contains(&group.name))
+            .map(|group| &group.rules)
+            .collect();
+
+        let is_ignored = |key: &String| {
+            group_rules_vec
+                .iter()
+                .any(|group_rules| group_rules.ignore.contains(key))
+        };
+
+        let mut rules = KernelParamRules {
+            required: self
+                .rules
+                .required
+                .iter()
+                .filter(|key| !is_ignored(key))
+                .cloned()
+                .collect(),
+            forbidden: self
+                .rules
+                .forbidden
+                .iter()
+                .filter(|key| !is_ignored(key))
+                .cloned()
+                .collect(),
+            values: self
+                .rules
+                .values
+                .iter()
+                .filter(|value_rule| {
+                    !is_ignored(&value_rule.key)
+                        && !group_rules_vec.iter().any(|group_rules| {
+                            group_rules
+                                .values
+                                .iter()
+                                .any(|group_value_rule| group_value_rule.key == value_rule.key)
+                        })
+                })
+                .cloned()
+                .collect(),
+            ignore: Vec::new(),
+        };
+
+        for group_rules in group_rules_vec {
+            rules.required.extend(group_rules.required.iter().cloned());
+            rules
+                .forbidden
+                .extend(group_rules.forbidden.iter().cloned());
+            rules.values.extend(group_rules.values.iter().cloned());
+        }
+
+        rules.required.sort();
+        rules.required.dedup();
+        rules.forbidden.sort();
+        rules.forbidden.dedup();
+
+        rules
+    }
+
+    /// Checks the kernel parameters of a node member of `hsm_group_vec`
+    pub fn lint(
+        &self,
+        host: &str,
+        kernel_cmdline: &KernelCmdline,
+        hsm_group_vec: &[String],
+    ) -> Vec<KernelParamViolation> {
+        let rules = self.get_rules(hsm_group_vec);
+
+        let violation = |key: &str, kind: ViolationKind| KernelParamViolation {
+            host: host.to_string(),
+            key: key.to_string(),
+            kind,
+        };
+
+        let mut violation_vec = Vec::new();
+
+        for key in &rules.required {
+            if !kernel_cmdline.contains(key) {
+                violation_vec.push(violation(key, ViolationKind::MissingRequired));
+            }
+        }
+
+        for key in &rules.forbidden {
+            if kernel_cmdline.contains(key) {
+                violation_vec.push(violation(key, ViolationKind::Forbidden));
+            }
+        }
+
+        for value_rule in &rules.values {
+            // Regexes are compiled when the policy is parsed, policies built otherwise compile
+            // them here
+            let regex = match self.regex_map.get(&value_rule.regex) {
+                Some(regex) => regex.clone(),
+                None => match Regex::new(&value_rule.regex) {
+                    Ok(regex) => regex,
+                    Err(_) => continue,
+                },
+            };
+
+            for value in kernel_cmdline.get_all(&value_rule.key) {
+                if !regex.is_match(value) {
+                    violation_vec.push(violation(
+                        &value_rule.key,
+                        ViolationKind::InvalidValue {
+                            value: value.to_string(),
+                            regex: value_rule.regex.clone(),
+                        },
+                    ));
+                }
+            }
+        }
+
+        violation_vec
+    }
+
+    /// Checks the kernel parameters of each host in a BSS boot parameters entry.
+    /// `host_group_map` maps hosts to the HSM groups they are member of
+    pub fn lint_boot_parameters(
+        &self,
+        boot_parameters: &BootParameters,
+        host_group_map: &HashMap<String, Vec<String>>,
+    ) -> Vec<KernelParamViolation> {
+        let kernel_cmdline = boot_parameters.get_kernel_cmdline();
+
+        boot_parameters
+            .hosts
+            .iter()
+            .filter(|host| *host != GLOBAL_HOST)
+            .flat_map(|host| {
+                self.lint(
+                    host,
+                    &kernel_cmdline,
+                    host_group_map
+                        .get(host)
+                        .map(Vec::as_slice)
+                        .unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Checks the kernel parameters of all nodes in BSS against the policy
+pub async fn audit(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    policy: &KernelParamPolicy,
+) -> Result<Vec<KernelParamViolation>, Error> {
+    let boot_parameters_vec =
+        http_client::get_raw(shasta_token, shasta_base_url, shasta_root_cert, &[]).await?;
+
+    let host_group_map =
+        get_host_group_map(shasta_token, shasta_base_url, shasta_root_cert).await?;
+
+    Ok(boot_parameters_vec
+        .iter()
+        .flat_map(|boot_parameters| policy.lint_boot_parameters(boot_parameters, &host_group_map))
+        .collect())
+}
+
+/// HSM groups each node is member of (xname --> HSM group names). Nodes not in HSM are not in
+/// the map, only global rules apply to them
+async fn get_host_group_map(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+) -> Result<HashMap<String, Vec<String>>, Error> {
+    Ok(
+        hsm::memberships::http_client::get_all(shasta_token, shasta_base_url, shasta_root_cert)
+            .await?
+            .into_iter()
+            .map(|membership| (membership.id, membership.group_labels))
+            .collect(),
+    )
+}
+
+fn site_policy_map() -> &'static Mutex<HashMap<String, KernelParamPolicy>> {
+    static SITE_POLICY_MAP: OnceLock<Mutex<HashMap<String, KernelParamPolicy>>> = OnceLock::new();
+    SITE_POLICY_MAP.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Sets the policy BSS boot parameters writers check against for a CSM API base url
+pub fn set_site_policy(shasta_base_url: &str, policy: KernelParamPolicy) {
+    site_policy_map()
+        .lock()
+        .unwrap()
+        .insert(shasta_base_url.to_string(), policy);
+}
+
+/// Removes the policy of a CSM API base url, BSS boot parameters writers won't check kernel
+/// parameters anymore
+pub fn remove_site_policy(shasta_base_url: &str) {
+    site_policy_map().lock().unwrap().remove(shasta_base_url);
+}
+
+pub fn get_site_policy(shasta_base_url: &str) -> Option<KernelParamPolicy> {
+    site_policy_map()
+        .lock()
+        .unwrap()
+        .get(shasta_base_url)
+        .cloned()
+}
+
+/// Checks the kernel parameters against the policy of the CSM site, if any. Called by BSS boot
+/// parameters writers before changing BSS, callers changing many entries check all of them first
+/// so BSS is not left half updated
+pub(crate) async fn check_site_policy(
+    shasta_base_url: &str,
+    shasta_token: &str,
+    shasta_root_cert: &[u8],
+    boot_parameters_vec: &[BootParameters],
+) -> Result<(), Error> {
+    let Some(policy) = get_site_policy(shasta_base_url) else {
+        return Ok(());
+    };
+
+    check(
+        shasta_base_url,
+        shasta_token,
+        shasta_root_cert,
+        boot_parameters_vec,
+        &policy,
+    )
+    .await
+}
+
+/// Checks the kernel parameters against the policy before calling
+/// `bootparameters::http_client::put`. Nothing is sent to BSS if there are violations
+pub async fn put_checked(
+    shasta_base_url: &str,
+    shasta_token: &str,
+    shasta_root_cert: &[u8],
+    boot_parameters: BootParameters,
+    policy: &KernelParamPolicy,
+) -> Result<Vec<Value>, Error> {
+    check(
+        shasta_base_url,
+        shasta_token,
+        shasta_root_cert,
+        std::slice::from_ref(&boot_parameters),
+        policy,
+    )
+    .await?;
+
+    http_client::put(
+        shasta_base_url,
+        shasta_token,
+        shasta_root_cert,
+        boot_parameters,
+    )
+    .await
+}
+
+/// Checks the kernel parameters against the policy before calling
+/// `bootparameters::http_client::patch`. Nothing is sent to BSS if there are violations
+pub async fn patch_checked(
+    shasta_base_url: &str,
+    shasta_token: &str,
+    shasta_root_cert: &[u8],
+    boot_parameters: &BootParameters,
+    policy: &KernelParamPolicy,
+) -> Result<Vec<Value>, Error> {
+    // BSS does not change the kernel parameters if the patch has none
+    if !boot_parameters.params.is_empty() {
+        check(
+            shasta_base_url,
+            shasta_token,
+            shasta_root_cert,
+            std::slice::from_ref(boot_parameters),
+            policy,
+        )
+        .await?;
+    }
+
+    http_client::patch(
+        shasta_base_url,
+        shasta_token,
+        shasta_root_cert,
+        boot_parameters,
+    )
+    .await
+}
+
+/// HSM memberships are fetched once for all the boot parameters entries
+async fn check(
+    shasta_base_url: &str,
+    shasta_token: &str,
+    shasta_root_cert: &[u8],
+    boot_parameters_vec: &[BootParameters],
+    policy: &KernelParamPolicy,
+) -> Result<(), Error> {
+    let has_nodes = boot_parameters_vec
+        .iter()
+        .any(|boot_parameters| boot_parameters.hosts.iter().any(|host| host != GLOBAL_HOST));
+
+    // Global boot parameters are not linted, no need to ask HSM
+    let host_group_map = if has_nodes {
+        get_host_group_map(shasta_token, shasta_base_url, shasta_root_cert).await?
+    } else {
+        HashMap::new()
+    };
+
+    let violation_vec: Vec<KernelParamViolation> = boot_parameters_vec
+        .iter()
+        .flat_map(|boot_parameters| policy.lint_boot_parameters(boot_parameters, &host_group_map))
+        .collect();
+
+    if violation_vec.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Message(format!(
+            "Kernel parameters violate the site policy:\n{}",
+            violation_vec
+                .iter()
+                .map(|violation| violation.to_string())
+                .collect::<Vec<String>>()
+                .join("\n")
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint() {
+        let policy = KernelParamPolicy::parse(
+            r#"
+required: [console]
+forbidden: [rd.break]
+values:
+  - key: ip
+    regex: '^(dhcp|none)$'
+hsm_groups:
+  - name: production
+    forbidden: [rd.debug]
+    values:
+      - key: ip
+        regex: '^dhcp$'
+  - name: test
+    ignore: [console]
+"#,
+        )
+        .unwrap();
+
+        let kernel_cmdline = KernelCmdline::parse("ip=none rd.break rd.debug quiet");
+
+        let violation_vec = policy.lint("x1000c1s7b0n0", &kernel_cmdline, &[]);
+
+        assert_eq!(
+            violation_vec
+                .iter()
+                .map(|violation| (violation.key.as_str(), &violation.kind))
+                .collect::<Vec<_>>(),
+            vec![
+                ("console", &ViolationKind::MissingRequired),
+                ("rd.break", &ViolationKind::Forbidden),
+            ]
+        );
+
+        let violation_vec = policy.lint(
+            "x1000c1s7b0n0",
+            &kernel_cmdline,
+            &["production".to_string(), "test".to_string()],
+        );
+
+        assert_eq!(
+            violation_vec
+                .iter()
+                .map(|violation| violation.key.as_str())
+                .collect::<Vec<_>>(),
+            vec!["rd.break", "rd.debug", "ip"]
+        );
+        assert_eq!(
+            violation_vec[2].kind,
+            ViolationKind::InvalidValue {
+                value: "none".to_string(),
+                regex: "^dhcp$".to_string()
+            }
+        );
+
+        assert!(KernelParamPolicy::parse("values: [{key: ip, regex: '('}]").is_err());
+    }
+
+    #[test]
+    fn test_regex_map() {
+        let policy = KernelParamPolicy::parse(
+            r#"
+values:
+  - key: ip
+    regex: '^(dhcp|none)$'
+hsm_groups:
+  - name: production
+    values:
+      - key: ip
+        regex: '^dhcp$'
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(policy.regex_map.len(), 2);
+        assert!(policy.regex_map.contains_key("^dhcp$"));
+
+        // Policies not parsed compile their regexes when linting
+        let policy = KernelParamPolicy {
+            rules: KernelParamRules {
+                values: vec![ValueRule {
+                    key: "ip".to_string(),
+                    regex: "^dhcp$".to_string(),
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert_eq!(
+            policy
+                .lint("x1000c1s7b0n0", &KernelCmdline::parse("ip=none"), &[])
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_site_policy() {
+        let boot_parameters =
+            BootParameters::new(vec!["Global"], None, None, "rd.break", "", "", None);
+
+        let shasta_base_url = "https://api.test-check-site-policy.local/apis";
+
+        assert!(check_site_policy(
+            shasta_base_url,
+            "",
+            &[],
+            std::slice::from_ref(&boot_parameters)
+        )
+        .await
+        .is_ok());
+
+        set_site_policy(
+            shasta_base_url,
+            KernelParamPolicy::parse("forbidden: [rd.break]").unwrap(),
+        );
+
+        // Global boot parameters are not linted, only nodes
+        assert!(check_site_policy(
+            shasta_base_url,
+            "",
+            &[],
+            std::slice::from_ref(&boot_parameters)
+        )
+        .await
+        .is_ok());
+
+        remove_site_policy(shasta_base_url);
+
+        assert!(get_site_policy(shasta_base_url).is_none());
+    }
+}
@@ -0,0 +1,695 @@
+//! Snapshots of BSS boot parameters. `bootparameters::http_client::put`/`patch` overwrite the
+//! boot parameters of a node with no record of the previous values, a snapshot keeps a copy of the
+//! boot parameters of an HSM group or a list of nodes so they can be compared or restored later
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, hsm, ims};
+
+use super::{
+    bootparameters::{http_client, BootParameters},
+    fetcher::BootParametersFetcher,
+    kernel_cmdline::KernelCmdline,
+    kernel_param_policy,
+};
+
+/// Folder in the S3 bucket where snapshots are stored
+const S3_SNAPSHOT_PREFIX: &str = "bss-snapshots";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BssSnapshot {
+    pub name: String,
+    pub creation_time: String,
+    /// HSM group the snapshot was taken from, None if taken from a list of nodes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hsm_group: Option<String>,
+    pub xname_vec: Vec<String>,
+    pub boot_parameters_vec: Vec<BootParameters>,
+}
+
+impl BssSnapshot {
+    /// Saves the boot parameters of the nodes in an HSM group, or in `xname_vec` if no HSM group
+    /// is provided. Fails if the boot parameters of any node could not be fetched, a partial
+    /// snapshot would make `restore` delete the boot parameters of the nodes missing
+    pub async fn take(
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+        name: &str,
+        hsm_group_name_opt: Option<&str>,
+        xname_vec: &[String],
+    ) -> Result<Self, Error> {
+        validate_snapshot_name(name)?;
+
+        let xname_vec = if let Some(hsm_group_name) = hsm_group_name_opt {
+            let xname_vec = hsm::group::utils::get_member_vec_from_hsm_name_vec(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                vec![hsm_group_name.to_string()],
+            )
+            .await;
+
+            if xname_vec.is_empty() {
+                return Err(Error::Message(format!(
+                    "HSM group '{}' not found or has no members",
+                    hsm_group_name
+                )));
+            }
+
+            xname_vec
+        } else if !xname_vec.is_empty() {
+            xname_vec.to_vec()
+        } else {
+            return Err(Error::Message(
+                "Provide an HSM group or a list of nodes to take a BSS snapshot".to_string(),
+            ));
+        };
+
+        let boot_parameters_vec =
+            BootParametersFetcher::new(shasta_token, shasta_base_url, shasta_root_cert)
+                .fetch(&xname_vec)
+                .await
+                .into_complete()?
+                .into_vec();
+
+        Ok(Self {
+            name: name.to_string(),
+            creation_time: chrono::Utc::now().to_rfc3339(),
+            hsm_group: hsm_group_name_opt.map(str::to_string),
+            xname_vec,
+            boot_parameters_vec,
+        })
+    }
+
+    /// Boot parameters of a node in the snapshot. The node gets a copy of the boot parameters
+    /// with itself as the only host
+    pub fn get_node_boot_parameters(&self, xname: &str) -> Option<BootParameters> {
+        self.boot_parameters_vec
+            .iter()
+            .find(|boot_parameters| boot_parameters.hosts.iter().any(|host| host == xname))
+            .map(|boot_parameters| BootParameters {
+                hosts: vec![xname.to_string()],
+                macs: None,
+                nids: None,
+                ..boot_parameters.clone()
+            })
+    }
+
+    fn get_node_boot_parameters_map(&self) -> HashMap<&str, &BootParameters> {
+        self.boot_parameters_vec
+            .iter()
+            .flat_map(|boot_parameters| {
+                boot_parameters
+                    .hosts
+                    .iter()
+                    .map(move |host| (host.as_str(), boot_parameters))
+            })
+            .collect()
+    }
+}
+
+/// Where snapshots are stored. Each snapshot is a JSON file named after the snapshot
+#[derive(Debug, Clone)]
+pub enum SnapshotStore {
+    /// Local folder
+    Local(PathBuf),
+    /// S3 bucket, snapshots are stored under the 'bss-snapshots' folder
+    S3 { bucket: String },
+}
+
+impl SnapshotStore {
+    /// Returns the location of the snapshot saved
+    pub async fn save(
+        &self,
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+        snapshot: &BssSnapshot,
+    ) -> Result<String, Error> {
+        validate_snapshot_name(&snapshot.name)?;
+
+        let content = serde_json::to_vec_pretty(snapshot)?;
+
+        match self {
+            SnapshotStore::Local(folder) => {
+                std::fs::create_dir_all(folder)?;
+
+                let file_path = folder.join(format!("{}.json", snapshot.name));
+
+                std::fs::write(&file_path, content)?;
+
+                Ok(file_path.to_string_lossy().to_string())
+            }
+            SnapshotStore::S3 { bucket } => {
+                let sts_value = ims::s3::s3_auth(shasta_token, shasta_base_url, shasta_root_cert)
+                    .await
+                    .map_err(Error::NetError)?;
+
+                let object_path = get_s3_object_path(&snapshot.name);
+
+                ims::s3::s3_put_object_bytes(&sts_value, &object_path, bucket, content)
+                    .await
+                    .map_err(|error| Error::Message(error.to_string()))?;
+
+                Ok(format!("s3://{}/{}", bucket, object_path))
+            }
+        }
+    }
+
+    pub async fn load(
+        &self,
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+        name: &str,
+    ) -> Result<BssSnapshot, Error> {
+        validate_snapshot_name(name)?;
+
+        let content = match self {
+            SnapshotStore::Local(folder) => std::fs::read(folder.join(format!("{}.json", name)))?,
+            SnapshotStore::S3 { bucket } => {
+                let sts_value = ims::s3::s3_auth(shasta_token, shasta_base_url, shasta_root_cert)
+                    .await
+                    .map_err(Error::NetError)?;
+
+                ims::s3::s3_get_object_bytes(&sts_value, &get_s3_object_path(name), bucket)
+                    .await
+                    .map_err(|error| Error::Message(error.to_string()))?
+            }
+        };
+
+        Ok(serde_json::from_slice(&content)?)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum NodeDiffStatus {
+    /// Node only has boot parameters in the new snapshot
+    Added,
+    /// Node only has boot parameters in the old snapshot
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    pub old: String,
+    pub new: String,
+}
+
+/// Kernel parameter with different values. Keys present several times (eg `console`) list all
+/// their values
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct KernelParamChange {
+    pub key: String,
+    pub old_value_vec: Vec<String>,
+    pub new_value_vec: Vec<String>,
+}
+
+/// Differences in the boot parameters of a node between two snapshots
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct NodeBootParametersDiff {
+    pub xname: String,
+    pub status: NodeDiffStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kernel: Option<FieldChange>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initrd: Option<FieldChange>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_id: Option<FieldChange>,
+    /// Kernel parameters as written in the kernel command line
+    pub kernel_param_added_vec: Vec<String>,
+    pub kernel_param_removed_vec: Vec<String>,
+    pub kernel_param_changed_vec: Vec<KernelParamChange>,
+}
+
+/// Compares the boot parameters of each node in two snapshots. Nodes with the same boot
+/// parameters in both snapshots are not returned
+pub fn diff(old_snapshot: &BssSnapshot, new_snapshot: &BssSnapshot) -> Vec<NodeBootParametersDiff> {
+    let old_boot_parameters_map = old_snapshot.get_node_boot_parameters_map();
+    let new_boot_parameters_map = new_snapshot.get_node_boot_parameters_map();
+
+    let xname_set: BTreeSet<&str> = old_boot_parameters_map
+        .keys()
+        .chain(new_boot_parameters_map.keys())
+        .copied()
+        .collect();
+
+    xname_set
+        .into_iter()
+        .filter_map(|xname| {
+            diff_node_boot_parameters(
+                xname,
+                old_boot_parameters_map.get(xname).copied(),
+                new_boot_parameters_map.get(xname).copied(),
+            )
+        })
+        .collect()
+}
+
+fn diff_node_boot_parameters(
+    xname: &str,
+    old_boot_parameters_opt: Option<&BootParameters>,
+    new_boot_parameters_opt: Option<&BootParameters>,
+) -> Option<NodeBootParametersDiff> {
+    let status = match (old_boot_parameters_opt, new_boot_parameters_opt) {
+        (None, None) => return None,
+        (None, Some(_)) => NodeDiffStatus::Added,
+        (Some(_), None) => NodeDiffStatus::Removed,
+        (Some(_), Some(_)) => NodeDiffStatus::Changed,
+    };
+
+    let empty_boot_parameters = BootParameters::default();
+    let old_boot_parameters = old_boot_parameters_opt.unwrap_or(&empty_boot_parameters);
+    let new_boot_parameters = new_boot_parameters_opt.unwrap_or(&empty_boot_parameters);
+
+    let field_change = |old: String, new: String| (old != new).then_some(FieldChange { old, new });
+
+    let old_kernel_cmdline = old_boot_parameters.get_kernel_cmdline();
+    let new_kernel_cmdline = new_boot_parameters.get_kernel_cmdline();
+
+    // Keys sorted so the diff is stable
+    let key_set: BTreeSet<&str> = old_kernel_cmdline
+        .iter()
+        .chain(new_kernel_cmdline.iter())
+        .map(|kernel_param| kernel_param.key())
+        .collect();
+
+    let mut kernel_param_added_vec = Vec::new();
+    let mut kernel_param_removed_vec = Vec::new();
+    let mut kernel_param_changed_vec = Vec::new();
+
+    for key in key_set {
+        match (
+            old_kernel_cmdline.contains(key),
+            new_kernel_cmdline.contains(key),
+        ) {
+            (false, true) => {
+                kernel_param_added_vec.extend(get_raw_kernel_params(&new_kernel_cmdline, key))
+            }
+            (true, false) => {
+                kernel_param_removed_vec.extend(get_raw_kernel_params(&old_kernel_cmdline, key))
+            }
+            _ => {
+                let old_value_vec = old_kernel_cmdline.get_all(key);
+                let new_value_vec = new_kernel_cmdline.get_all(key);
+
+                if old_value_vec != new_value_vec {
+                    kernel_param_changed_vec.push(KernelParamChange {
+                        key: key.to_string(),
+                        old_value_vec: old_value_vec
+                            .iter()
+                            .map(|value| value.to_string())
+                            .collect(),
+                        new_value_vec: new_value_vec
+                            .iter()
+                            .map(|value| value.to_string())
+                            .collect(),
+                    });
+                }
+            }
+        }
+    }
+
+    let node_diff = NodeBootParametersDiff {
+        xname: xname.to_string(),
+        status,
+        kernel: field_change(
+            old_boot_parameters.kernel.clone(),
+            new_boot_parameters.kernel.clone(),
+        ),
+        initrd: field_change(
+            old_boot_parameters.initrd.clone(),
+            new_boot_parameters.initrd.clone(),
+        ),
+        image_id: field_change(
+            old_boot_parameters.get_boot_image(),
+            new_boot_parameters.get_boot_image(),
+        ),
+        kernel_param_added_vec,
+        kernel_param_removed_vec,
+        kernel_param_changed_vec,
+    };
+
+    let is_unchanged = node_diff.status == NodeDiffStatus::Changed
+        && node_diff.kernel.is_none()
+        && node_diff.initrd.is_none()
+        && node_diff.image_id.is_none()
+        && node_diff.kernel_param_added_vec.is_empty()
+        && node_diff.kernel_param_removed_vec.is_empty()
+        && node_diff.kernel_param_changed_vec.is_empty();
+
+    (!is_unchanged).then_some(node_diff)
+}
+
+fn get_raw_kernel_params(kernel_cmdline: &KernelCmdline, key: &str) -> Vec<String> {
+    kernel_cmdline
+        .iter()
+        .filter(|kernel_param| kernel_param.key() == key)
+        .map(|kernel_param| kernel_param.to_string())
+        .collect()
+}
+
+/// Restores the boot parameters of the nodes in a snapshot. Returns the changes applied, or the
+/// changes that would be applied if `dry_run` is true.
+/// Boot parameters are restored node by node, if any node fails the nodes already restored are
+/// rolled back to the boot parameters they had before calling this function so BSS is left as it
+/// was.
+/// Nodes without boot parameters in the snapshot get their boot parameters deleted, nodes without
+/// boot parameters before calling this function get them deleted when rolled back.
+/// All the boot parameters restored are checked against the site kernel param policy (see
+/// `bss::kernel_param_policy::set_site_policy`) before changing BSS, rolling back to the boot
+/// parameters BSS had before is not checked
+pub async fn restore(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    snapshot: &BssSnapshot,
+    dry_run: bool,
+) -> Result<Vec<NodeBootParametersDiff>, Error> {
+    restore_snapshot(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        snapshot,
+        dry_run,
+        true,
+    )
+    .await
+}
+
+/// Same as `restore` without checking the site kernel param policy. Used to put back boot
+/// parameters BSS had before a change, those must be restored even if they violate a policy set
+/// afterwards
+pub(crate) async fn restore_unchecked(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    snapshot: &BssSnapshot,
+) -> Result<Vec<NodeBootParametersDiff>, Error> {
+    restore_snapshot(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        snapshot,
+        false,
+        false,
+    )
+    .await
+}
+
+async fn restore_snapshot(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    snapshot: &BssSnapshot,
+    dry_run: bool,
+    check_site_policy: bool,
+) -> Result<Vec<NodeBootParametersDiff>, Error> {
+    let current_snapshot = BssSnapshot::take(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        &format!("{}-pre-restore", snapshot.name),
+        None,
+        &snapshot.xname_vec,
+    )
+    .await?;
+
+    let node_diff_vec = diff(&current_snapshot, snapshot);
+
+    if dry_run {
+        return Ok(node_diff_vec);
+    }
+
+    let node_restore_action_vec: Vec<(&str, NodeRestoreAction)> = node_diff_vec
+        .iter()
+        .map(|node_diff| {
+            (
+                node_diff.xname.as_str(),
+                get_node_restore_action(snapshot, &node_diff.xname),
+            )
+        })
+        .collect();
+
+    // Check all nodes before changing BSS so a violation does not leave the restore half done
+    if check_site_policy {
+        let boot_parameters_vec: Vec<BootParameters> = node_restore_action_vec
+            .iter()
+            .filter_map(|(_, node_restore_action)| match node_restore_action {
+                NodeRestoreAction::Put(boot_parameters) => Some(boot_parameters.clone()),
+                NodeRestoreAction::Delete(_) => None,
+            })
+            .collect();
+
+        kernel_param_policy::check_site_policy(
+            shasta_base_url,
+            shasta_token,
+            shasta_root_cert,
+            &boot_parameters_vec,
+        )
+        .await?;
+    }
+
+    let mut restored_xname_vec: Vec<&str> = Vec::new();
+
+    for (xname, node_restore_action) in node_restore_action_vec {
+        log::info!("Restoring BSS boot parameters for node '{}'", xname);
+
+        if let Err(error) = apply_node_restore_action(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            node_restore_action,
+        )
+        .await
+        {
+            log::error!(
+                "Could not restore BSS boot parameters for node '{}', rolling back. Reason:\n{}",
+                xname,
+                error
+            );
+
+            rollback(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                &current_snapshot,
+                &restored_xname_vec,
+            )
+            .await;
+
+            return Err(error);
+        }
+
+        restored_xname_vec.push(xname);
+    }
+
+    Ok(node_diff_vec)
+}
+
+async fn rollback(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    snapshot: &BssSnapshot,
+    xname_vec: &[&str],
+) {
+    for xname in xname_vec {
+        if let Err(error) = apply_node_restore_action(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            get_node_restore_action(snapshot, xname),
+        )
+        .await
+        {
+            log::error!(
+                "Could not roll back BSS boot parameters for node '{}'. Reason:\n{}",
+                xname,
+                error
+            );
+        }
+    }
+}
+
+/// Change to BSS needed to set the boot parameters of a node as they are in a snapshot. Boot
+/// parameters are not checked against the site kernel param policy when applied, `restore` checks
+/// them all beforehand
+#[derive(Debug)]
+enum NodeRestoreAction {
+    Put(BootParameters),
+    /// Node has no boot parameters in the snapshot
+    Delete(BootParameters),
+}
+
+fn get_node_restore_action(snapshot: &BssSnapshot, xname: &str) -> NodeRestoreAction {
+    match snapshot.get_node_boot_parameters(xname) {
+        Some(boot_parameters) => NodeRestoreAction::Put(boot_parameters),
+        None => NodeRestoreAction::Delete(BootParameters {
+            hosts: vec![xname.to_string()],
+            ..Default::default()
+        }),
+    }
+}
+
+async fn apply_node_restore_action(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    node_restore_action: NodeRestoreAction,
+) -> Result<(), Error> {
+    match node_restore_action {
+        NodeRestoreAction::Put(boot_parameters) => http_client::put_unchecked(
+            shasta_base_url,
+            shasta_token,
+            shasta_root_cert,
+            boot_parameters,
+        )
+        .await
+        .map(|_| ()),
+        NodeRestoreAction::Delete(boot_parameters) => {
+            http_client::delete(
+                shasta_base_url,
+                shasta_token,
+                shasta_root_cert,
+                &boot_parameters,
+            )
+            .await
+        }
+    }
+}
+
+fn get_s3_object_path(name: &str) -> String {
+    format!("{}/{}.json", S3_SNAPSHOT_PREFIX, name)
+}
+
+fn validate_snapshot_name(name: &str) -> Result<(), Error> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(Error::Message(format!(
+            "Invalid BSS snapshot name '{}'",
+            name
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(boot_parameters_vec: Vec<BootParameters>) -> BssSnapshot {
+        BssSnapshot {
+            name: "test".to_string(),
+            creation_time: String::new(),
+            hsm_group: None,
+            xname_vec: Vec::new(),
+            boot_parameters_vec,
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let old_snapshot = snapshot(vec![BootParameters::new(
+            vec!["x1000c1s7b0n0", "x1000c1s7b0n1"],
+            None,
+            None,
+            "console=tty0 console=ttyS0 quiet root=craycps-s3:s3://boot-images/6c644208-104a-473d-802c-410219026335/rootfs",
+            "s3://boot-images/6c644208-104a-473d-802c-410219026335/kernel",
+            "s3://boot-images/6c644208-104a-473d-802c-410219026335/initrd",
+            None,
+        )]);
+
+        let new_snapshot = snapshot(vec![
+            BootParameters::new(
+                vec!["x1000c1s7b0n0"],
+                None,
+                None,
+                "console=tty0 console=ttyS0 quiet root=craycps-s3:s3://boot-images/6c644208-104a-473d-802c-410219026335/rootfs",
+                "s3://boot-images/6c644208-104a-473d-802c-410219026335/kernel",
+                "s3://boot-images/6c644208-104a-473d-802c-410219026335/initrd",
+                None,
+            ),
+            BootParameters::new(
+                vec!["x1000c1s7b0n1", "x1000c1s7b1n0"],
+                None,
+                None,
+                "console=ttyS1 rd.retry=10 root=craycps-s3:s3://boot-images/59e0180a-3fdd-4936-bba7-14ba914ffd34/rootfs",
+                "s3://boot-images/59e0180a-3fdd-4936-bba7-14ba914ffd34/kernel",
+                "s3://boot-images/59e0180a-3fdd-4936-bba7-14ba914ffd34/initrd",
+                None,
+            ),
+        ]);
+
+        let node_diff_vec = diff(&old_snapshot, &new_snapshot);
+
+        assert_eq!(node_diff_vec.len(), 2);
+
+        let node_diff = &node_diff_vec[0];
+        assert_eq!(node_diff.xname, "x1000c1s7b0n1");
+        assert_eq!(node_diff.status, NodeDiffStatus::Changed);
+        assert_eq!(
+            node_diff
+                .image_id
+                .as_ref()
+                .map(|image_id| image_id.new.as_str()),
+            Some("59e0180a-3fdd-4936-bba7-14ba914ffd34")
+        );
+        assert!(node_diff.kernel.is_some() && node_diff.initrd.is_some());
+        assert_eq!(node_diff.kernel_param_added_vec, vec!["rd.retry=10"]);
+        assert_eq!(node_diff.kernel_param_removed_vec, vec!["quiet"]);
+        assert_eq!(
+            node_diff
+                .kernel_param_changed_vec
+                .iter()
+                .map(|kernel_param_change| kernel_param_change.key.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["console", "root"]
+        );
+        assert_eq!(
+            node_diff.kernel_param_changed_vec[0].old_value_vec,
+            vec!["tty0", "ttyS0"]
+        );
+
+        assert_eq!(node_diff_vec[1].xname, "x1000c1s7b1n0");
+        assert_eq!(node_diff_vec[1].status, NodeDiffStatus::Added);
+    }
+
+    #[test]
+    fn test_get_node_restore_action() {
+        let snapshot = snapshot(vec![BootParameters::new(
+            vec!["x1000c1s7b0n0", "x1000c1s7b0n1"],
+            None,
+            None,
+            "console=ttyS0 quiet",
+            "s3://boot-images/6c644208-104a-473d-802c-410219026335/kernel",
+            "s3://boot-images/6c644208-104a-473d-802c-410219026335/initrd",
+            None,
+        )]);
+
+        match get_node_restore_action(&snapshot, "x1000c1s7b0n1") {
+            NodeRestoreAction::Put(boot_parameters) => {
+                assert_eq!(boot_parameters.hosts, vec!["x1000c1s7b0n1"]);
+                assert_eq!(boot_parameters.params, "console=ttyS0 quiet");
+            }
+            node_restore_action => panic!("unexpected {:?}", node_restore_action),
+        }
+
+        // Node without boot parameters in the snapshot, eg added to BSS after the snapshot was
+        // taken, or restored and then rolled back
+        match get_node_restore_action(&snapshot, "x1000c1s7b1n0") {
+            NodeRestoreAction::Delete(boot_parameters) => {
+                assert_eq!(boot_parameters.hosts, vec!["x1000c1s7b1n0"]);
+                assert!(boot_parameters.macs.is_none() && boot_parameters.nids.is_none());
+            }
+            node_restore_action => panic!("unexpected {:?}", node_restore_action),
+        }
+    }
+}
@@ -1 +1,4 @@
 pub mod http_client;
+pub mod provenance;
+pub mod reconciler;
+pub mod retry;
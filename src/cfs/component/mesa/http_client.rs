@@ -3,7 +3,14 @@ use std::{sync::Arc, time::Instant};
 use serde_json::Value;
 use tokio::sync::Semaphore;
 
-use crate::{cfs::component::shasta::r#struct::v2::ComponentResponse, error::Error};
+use crate::{
+    cfs::component::shasta::{
+        self,
+        r#struct::{v2::ComponentResponse, v3::Component},
+    },
+    common::ownership::OwnershipTags,
+    error::Error,
+};
 
 pub async fn get_raw(
     shasta_token: &str,
@@ -53,7 +60,7 @@ pub async fn get_raw(
 
 /// Get components data.
 /// Currently, CSM will throw an error if many xnames are sent in the request, therefore, this
-/// method will paralelize multiple calls, each with a batch of xnames
+/// method will paralelize multiple calls, each with a batch of xnames. Fails if any batch fails
 pub async fn get_multiple(
     shasta_token: &str,
     shasta_base_url: &str,
@@ -107,15 +114,25 @@ pub async fn get_multiple(
                 None,
             )
             .await
-            .unwrap()
         });
 
         i += 1;
     }
 
     while let Some(message) = tasks.join_next().await {
-        if let Ok(mut cfs_component_vec) = message {
-            component_vec.append(&mut cfs_component_vec);
+        match message {
+            Ok(Ok(mut cfs_component_vec)) => component_vec.append(&mut cfs_component_vec),
+            Ok(Err(error)) => {
+                tasks.abort_all();
+                return Err(error);
+            }
+            Err(error) => {
+                tasks.abort_all();
+                return Err(Error::Message(format!(
+                    "Could not get a batch of CFS components. Reason:\n{}",
+                    error
+                )));
+            }
         }
     }
 
@@ -124,3 +141,136 @@ pub async fn get_multiple(
 
     Ok(component_vec)
 }
+
+/// Get CFS components filtered by CFS using the `configName`, `status` and `tags` (comma
+/// separated list of key=value) query parameters. Filters not provided are not sent, so calling
+/// this without filters returns all CFS components
+pub async fn get_filtered(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    configuration_name_opt: Option<&str>,
+    status_opt: Option<&str>,
+    tag_vec: &[(String, String)],
+) -> Result<Vec<ComponentResponse>, Error> {
+    let client_builder = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(shasta_root_cert)?);
+
+    // Build client
+    let client = if let Ok(socks5_env) = std::env::var("SOCKS5") {
+        // socks5 proxy
+        log::debug!("SOCKS5 enabled");
+        let socks5proxy = reqwest::Proxy::all(socks5_env)?;
+
+        // rest client to authenticate
+        client_builder.proxy(socks5proxy).build()?
+    } else {
+        client_builder.build()?
+    };
+
+    let api_url = shasta_base_url.to_owned() + "/cfs/v2/components";
+
+    let tags_opt = (!tag_vec.is_empty()).then(|| tags_query(tag_vec));
+
+    let response = client
+        .get(api_url)
+        .query(&[
+            ("configName", configuration_name_opt),
+            ("status", status_opt),
+            ("tags", tags_opt.as_deref()),
+        ])
+        .bearer_auth(shasta_token)
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        response
+            .json::<Vec<ComponentResponse>>()
+            .await
+            .map_err(Error::NetError)
+    } else {
+        let payload = response.json::<Value>().await?;
+        Err(Error::CsmError(payload))
+    }
+}
+
+/// Get CFS components with all the ownership tags provided. Fails if no ownership tags are
+/// provided instead of returning all CFS components
+pub async fn get_by_ownership_tags(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    ownership_tags: &OwnershipTags,
+) -> Result<Vec<ComponentResponse>, Error> {
+    if ownership_tags.is_empty() {
+        return Err(Error::Message(
+            "No ownership tags provided to filter CFS components".to_string(),
+        ));
+    }
+
+    let tag_vec: Vec<(String, String)> = ownership_tags
+        .to_tag_vec()
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect();
+
+    get_filtered(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        None,
+        None,
+        &tag_vec,
+    )
+    .await
+}
+
+/// Value of the CFS `tags` query parameter: comma separated list of key=value
+fn tags_query(tag_vec: &[(String, String)]) -> String {
+    tag_vec
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// Adds the mesa ownership tags to a list of CFS components. Other tags in the components are
+/// kept
+pub async fn set_ownership_tags(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: &[String],
+    ownership_tags: &OwnershipTags,
+) -> Result<Vec<Value>, Error> {
+    let component_vec = get_multiple(shasta_token, shasta_base_url, shasta_root_cert, xname_vec)
+        .await?
+        .into_iter()
+        .map(|component| {
+            let mut tags = component.tags.unwrap_or_default();
+            ownership_tags.merge_into(&mut tags);
+
+            Component {
+                id: component.id,
+                state: None,
+                state_append: None,
+                desired_config: None,
+                error_count: None,
+                retry_policy: None,
+                enabled: None,
+                configuration_status: None,
+                tags: Some(tags),
+                logs: None,
+            }
+        })
+        .collect();
+
+    shasta::http_client::v3::patch_component_list(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        component_vec,
+    )
+    .await
+    .map_err(Error::NetError)
+}
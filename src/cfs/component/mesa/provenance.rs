@@ -0,0 +1,351 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cfs::{
+        self,
+        component::shasta::r#struct::v2::{ComponentResponse, StateResponse},
+        configuration::mesa::r#struct::cfs_configuration_response::v2::{
+            CfsConfigurationResponse, Layer,
+        },
+    },
+    common::gitea,
+    error::Error,
+};
+
+use super::http_client;
+
+/// CFS appends these suffixes to the commit in the component state when a layer was not applied
+/// successfully
+const SKIPPED_COMMIT_SUFFIX: &str = "_skipped";
+const INCOMPLETE_COMMIT_SUFFIX: &str = "_incomplete";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum LayerStatus {
+    /// Layer applied with the commit in the desired configuration
+    UpToDate,
+    /// Layer applied with a different commit
+    OutOfDate,
+    /// Layer in the desired configuration but not in the component state
+    NotApplied,
+    /// CFS skipped the layer
+    Skipped,
+    /// Last attempt to apply the layer did not finish successfully
+    Incomplete,
+}
+
+/// Desired layer vs the layer applied to a node
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LayerProvenance {
+    pub layer_name: String,
+    pub clone_url: String,
+    pub playbook: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desired_commit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub applied_commit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_updated: Option<String>,
+    pub status: LayerStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NodeProvenance {
+    pub xname: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desired_config: Option<String>,
+    /// One entry per layer in the desired configuration. Empty if the desired configuration
+    /// could not be found
+    pub layer_vec: Vec<LayerProvenance>,
+    /// Layers applied to the node which are not part of the desired configuration
+    pub extra_state_vec: Vec<StateResponse>,
+}
+
+impl NodeProvenance {
+    pub fn is_up_to_date(&self) -> bool {
+        self.layer_vec
+            .iter()
+            .all(|layer| layer.status == LayerStatus::UpToDate)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ProvenanceReport {
+    pub node_vec: Vec<NodeProvenance>,
+    /// xnames without a CFS component
+    pub missing_component_vec: Vec<String>,
+    /// Desired configurations which could not be found
+    pub missing_configuration_vec: Vec<String>,
+}
+
+impl ProvenanceReport {
+    /// layer name --> xnames where the layer is not up to date
+    pub fn get_out_of_date_layer_map(&self) -> BTreeMap<String, Vec<String>> {
+        let mut layer_map: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+        for node in &self.node_vec {
+            for layer in &node.layer_vec {
+                if layer.status != LayerStatus::UpToDate {
+                    layer_map
+                        .entry(layer.layer_name.clone())
+                        .or_default()
+                        .push(node.xname.clone());
+                }
+            }
+        }
+
+        layer_map
+    }
+
+    /// xnames where the layer applied from repo `repo_name` is not exactly commit `commit_id`
+    /// (full sha or a prefix of it). Git ancestry is not checked, a node with a newer commit
+    /// which already contains `commit_id` is also returned.
+    /// eg: answers "is the security fix in commit 1a2b3c on these nodes?"
+    pub fn get_xname_vec_missing_commit(&self, repo_name: &str, commit_id: &str) -> Vec<String> {
+        self.node_vec
+            .iter()
+            .filter(|node| {
+                node.layer_vec
+                    .iter()
+                    .filter(|layer| {
+                        gitea::utils::get_repo_name_from_url(&layer.clone_url) == repo_name
+                    })
+                    .any(|layer| {
+                        layer.status != LayerStatus::UpToDate
+                            || layer
+                                .applied_commit
+                                .as_deref()
+                                .is_none_or(|applied_commit| !applied_commit.starts_with(commit_id))
+                    })
+            })
+            .map(|node| node.xname.clone())
+            .collect()
+    }
+}
+
+/// Returns, for each node, which layers were applied, at which commit and by which CFS session,
+/// compared against the layers in the node desired configuration
+pub async fn get_report(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: &[String],
+) -> Result<ProvenanceReport, Error> {
+    let component_vec =
+        http_client::get_multiple(shasta_token, shasta_base_url, shasta_root_cert, xname_vec)
+            .await?;
+
+    let mut configuration_map: HashMap<String, CfsConfigurationResponse> = HashMap::new();
+    let mut report = ProvenanceReport::default();
+
+    for configuration_name in component_vec
+        .iter()
+        .filter_map(|component| component.desired_config.as_ref())
+        .filter(|configuration_name| !configuration_name.is_empty())
+    {
+        if configuration_map.contains_key(configuration_name)
+            || report
+                .missing_configuration_vec
+                .contains(configuration_name)
+        {
+            continue;
+        }
+
+        match cfs::configuration::mesa::http_client::get(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            Some(configuration_name),
+        )
+        .await
+        .map(|mut configuration_vec| configuration_vec.pop())
+        {
+            Ok(Some(configuration)) => {
+                configuration_map.insert(configuration_name.clone(), configuration);
+            }
+            Ok(None) => {
+                log::warn!("CFS configuration '{}' not found", configuration_name);
+                report
+                    .missing_configuration_vec
+                    .push(configuration_name.clone());
+            }
+            Err(error) if error.is_not_found() => {
+                log::warn!("CFS configuration '{}' not found", configuration_name);
+                report
+                    .missing_configuration_vec
+                    .push(configuration_name.clone());
+            }
+            // Network or auth errors must not be reported as a missing configuration
+            Err(error) => return Err(error),
+        }
+    }
+
+    for xname in xname_vec {
+        match component_vec
+            .iter()
+            .find(|component| component.id.as_ref() == Some(xname))
+        {
+            Some(component) => {
+                let configuration_opt = component
+                    .desired_config
+                    .as_ref()
+                    .and_then(|configuration_name| configuration_map.get(configuration_name));
+
+                report
+                    .node_vec
+                    .push(get_node_provenance(component, configuration_opt));
+            }
+            None => report.missing_component_vec.push(xname.clone()),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Compares the component state against the layers in the desired configuration. Layers are
+/// matched by repo name and playbook since the clone url in the state may use a different host
+pub fn get_node_provenance(
+    component: &ComponentResponse,
+    configuration_opt: Option<&CfsConfigurationResponse>,
+) -> NodeProvenance {
+    let mut state_vec = component.state.clone().unwrap_or_default();
+
+    let layer_vec = configuration_opt
+        .map(|configuration| {
+            configuration
+                .layers
+                .iter()
+                .map(|layer| {
+                    let state_opt = state_vec
+                        .iter()
+                        .position(|state| is_same_layer(layer, state))
+                        .map(|idx| state_vec.remove(idx));
+
+                    get_layer_provenance(layer, state_opt)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    NodeProvenance {
+        xname: component.id.clone().unwrap_or_default(),
+        desired_config: component.desired_config.clone(),
+        layer_vec,
+        extra_state_vec: state_vec,
+    }
+}
+
+fn is_same_layer(layer: &Layer, state: &StateResponse) -> bool {
+    state.clone_url.as_deref().is_some_and(|clone_url| {
+        gitea::utils::get_repo_name_from_url(clone_url)
+            == gitea::utils::get_repo_name_from_url(&layer.clone_url)
+    }) && state.playbook.as_deref() == Some(layer.playbook.as_str())
+}
+
+fn get_layer_provenance(layer: &Layer, state_opt: Option<StateResponse>) -> LayerProvenance {
+    let (applied_commit, session_name, last_updated, status) = match state_opt {
+        None => (None, None, None, LayerStatus::NotApplied),
+        Some(state) => {
+            let commit = state.commit.unwrap_or_default();
+
+            let (applied_commit, status) =
+                if let Some(commit) = commit.strip_suffix(SKIPPED_COMMIT_SUFFIX) {
+                    (commit.to_string(), LayerStatus::Skipped)
+                } else if let Some(commit) = commit.strip_suffix(INCOMPLETE_COMMIT_SUFFIX) {
+                    (commit.to_string(), LayerStatus::Incomplete)
+                } else if layer.commit.as_ref() == Some(&commit) {
+                    (commit, LayerStatus::UpToDate)
+                } else {
+                    (commit, LayerStatus::OutOfDate)
+                };
+
+            (
+                Some(applied_commit),
+                state.session_name,
+                state.last_updated,
+                status,
+            )
+        }
+    };
+
+    LayerProvenance {
+        layer_name: layer.name.clone(),
+        clone_url: layer.clone_url.clone(),
+        playbook: layer.playbook.clone(),
+        desired_commit: layer.commit.clone(),
+        applied_commit,
+        session_name,
+        last_updated,
+        status,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_node_provenance() {
+        let configuration: CfsConfigurationResponse = serde_json::from_value(serde_json::json!({
+            "name": "zinal-config",
+            "lastUpdated": "2024-01-01T00:00:00Z",
+            "layers": [
+                { "name": "cos", "cloneUrl": "https://api-gw-service-nmn.local/vcs/cray/cos-config-management.git", "commit": "aaa", "playbook": "site.yml" },
+                { "name": "csm", "cloneUrl": "https://api-gw-service-nmn.local/vcs/cray/csm-config-management.git", "commit": "bbb", "playbook": "site.yml" },
+                { "name": "slurm", "cloneUrl": "https://api-gw-service-nmn.local/vcs/cray/slurm-config-management.git", "commit": "ccc", "playbook": "site.yml" },
+                { "name": "uss", "cloneUrl": "https://api-gw-service-nmn.local/vcs/cray/uss-config-management.git", "commit": "ddd", "playbook": "site.yml" }
+            ]
+        }))
+        .unwrap();
+
+        let component: ComponentResponse = serde_json::from_value(serde_json::json!({
+            "id": "x1000c1s7b0n0",
+            "desiredConfig": "zinal-config",
+            "state": [
+                { "cloneUrl": "https://vcs.cmn.alps.cscs.ch/vcs/cray/cos-config-management.git", "playbook": "site.yml", "commit": "aaa", "sessionName": "batcher-1" },
+                { "cloneUrl": "https://vcs.cmn.alps.cscs.ch/vcs/cray/csm-config-management.git", "playbook": "site.yml", "commit": "old", "sessionName": "batcher-0" },
+                { "cloneUrl": "https://vcs.cmn.alps.cscs.ch/vcs/cray/slurm-config-management.git", "playbook": "site.yml", "commit": "ccc_incomplete", "sessionName": "batcher-1" },
+                { "cloneUrl": "https://vcs.cmn.alps.cscs.ch/vcs/cray/sma-config-management.git", "playbook": "site.yml", "commit": "eee", "sessionName": "batcher-0" }
+            ]
+        }))
+        .unwrap();
+
+        let node_provenance = get_node_provenance(&component, Some(&configuration));
+
+        assert_eq!(
+            node_provenance
+                .layer_vec
+                .iter()
+                .map(|layer| layer.status.clone())
+                .collect::<Vec<LayerStatus>>(),
+            vec![
+                LayerStatus::UpToDate,
+                LayerStatus::OutOfDate,
+                LayerStatus::Incomplete,
+                LayerStatus::NotApplied
+            ]
+        );
+        assert_eq!(
+            node_provenance.layer_vec[0].session_name.as_deref(),
+            Some("batcher-1")
+        );
+        assert_eq!(node_provenance.extra_state_vec.len(), 1);
+        assert!(!node_provenance.is_up_to_date());
+
+        let report = ProvenanceReport {
+            node_vec: vec![node_provenance],
+            ..Default::default()
+        };
+
+        assert!(report
+            .get_xname_vec_missing_commit("cos-config-management", "aaa")
+            .is_empty());
+        assert_eq!(
+            report.get_xname_vec_missing_commit("csm-config-management", "bbb"),
+            vec!["x1000c1s7b0n0"]
+        );
+    }
+}
@@ -0,0 +1,404 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+use crate::{
+    cfs::component::shasta::{
+        self,
+        r#struct::{v2::ComponentResponse, v3::Component},
+    },
+    error::Error,
+    hsm,
+};
+
+use super::http_client;
+
+/// Result of reconciling a single CFS component
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ComponentOutcome {
+    /// `configuration_status` is 'configured'
+    Configured,
+    /// `configuration_status` is 'failed'
+    Failed,
+    /// Component is disabled, hence CFS won't configure it
+    Disabled,
+    /// Component was still being configured when the timeout was reached
+    TimedOut {
+        configuration_status: Option<String>,
+    },
+    /// CFS component could not be updated
+    PatchFailed { reason: String },
+    /// Member of the HSM group without a CFS component
+    NotFound,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReconcileReport {
+    /// xnames which `desired_config` or `enabled` was updated
+    pub patched_xname_vec: Vec<String>,
+    /// xname --> outcome
+    pub outcome_map: BTreeMap<String, ComponentOutcome>,
+    /// xnames which `error_count` reached the `retry_policy`. CFS won't try to configure them
+    /// again till their `error_count` is reset
+    pub error_count_exceeded_xname_vec: Vec<String>,
+}
+
+impl ReconcileReport {
+    /// True if all components are configured
+    pub fn is_success(&self) -> bool {
+        self.outcome_map
+            .values()
+            .all(|outcome| *outcome == ComponentOutcome::Configured)
+    }
+
+    pub fn get_xname_vec_with_outcome(&self, outcome: &ComponentOutcome) -> Vec<String> {
+        self.outcome_map
+            .iter()
+            .filter(|(_, xname_outcome)| *xname_outcome == outcome)
+            .map(|(xname, _)| xname.clone())
+            .collect()
+    }
+}
+
+/// Sets the desired configuration of all members of an HSM group. Only the CFS components which
+/// `desired_config` or `enabled` differ from the target are updated, then the components are
+/// followed till all of them are either configured or failed, or the timeout is reached
+///
+/// eg:
+///
+/// let report = CfsComponentReconciler::new("zinal", "zinal-cos-config-2.4.104")
+///     .timeout(Duration::from_secs(3600))
+///     .run(shasta_token, shasta_base_url, shasta_root_cert)
+///     .await?;
+pub struct CfsComponentReconciler {
+    hsm_group_name: String,
+    desired_configuration: String,
+    enabled: bool,
+    batch_size: usize,
+    timeout: Duration,
+    poll_interval: Duration,
+}
+
+impl CfsComponentReconciler {
+    pub fn new(hsm_group_name: &str, desired_configuration: &str) -> Self {
+        Self {
+            hsm_group_name: hsm_group_name.to_string(),
+            desired_configuration: desired_configuration.to_string(),
+            enabled: true,
+            batch_size: 60,
+            timeout: Duration::from_secs(3600),
+            poll_interval: Duration::from_secs(30),
+        }
+    }
+
+    /// Target value for the components `enabled` field. Defaults to true. Disabled components
+    /// are not followed since CFS won't configure them
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// Number of CFS components updated per request. Defaults to 60
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Max time to wait for the components to be configured. Defaults to 1 hour
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Time between checks of the components `configuration_status`. Defaults to 30 seconds
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Returns the xnames of the components which `desired_config` or `enabled` differ from the
+    /// target
+    pub fn get_out_of_sync_xname_vec(&self, component_vec: &[ComponentResponse]) -> Vec<String> {
+        component_vec
+            .iter()
+            .filter(|component| {
+                component.desired_config.as_deref() != Some(self.desired_configuration.as_str())
+                    || component.enabled != Some(self.enabled)
+            })
+            .filter_map(|component| component.id.clone())
+            .collect()
+    }
+
+    pub async fn run(
+        &self,
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+    ) -> Result<ReconcileReport, Error> {
+        let xname_vec = hsm::group::utils::get_member_vec_from_hsm_name_vec(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            vec![self.hsm_group_name.clone()],
+        )
+        .await;
+
+        if xname_vec.is_empty() {
+            return Err(Error::Message(format!(
+                "HSM group '{}' not found or has no members",
+                self.hsm_group_name
+            )));
+        }
+
+        let component_vec =
+            http_client::get_multiple(shasta_token, shasta_base_url, shasta_root_cert, &xname_vec)
+                .await?;
+
+        let mut report = ReconcileReport::default();
+
+        let component_map: HashMap<&String, &ComponentResponse> = component_vec
+            .iter()
+            .filter_map(|component| component.id.as_ref().map(|xname| (xname, component)))
+            .collect();
+
+        let component_xname_set: HashSet<&String> = component_map.keys().copied().collect();
+
+        for xname in &xname_vec {
+            if !component_xname_set.contains(xname) {
+                report
+                    .outcome_map
+                    .insert(xname.clone(), ComponentOutcome::NotFound);
+            }
+        }
+
+        // Update components out of sync
+        let out_of_sync_xname_vec = self.get_out_of_sync_xname_vec(&component_vec);
+
+        log::info!(
+            "{} CFS components in HSM group '{}' out of sync with configuration '{}'",
+            out_of_sync_xname_vec.len(),
+            self.hsm_group_name,
+            self.desired_configuration
+        );
+
+        for xname_batch in out_of_sync_xname_vec.chunks(self.batch_size) {
+            let component_batch = xname_batch
+                .iter()
+                .map(|xname| self.get_component_patch(component_map[xname]))
+                .collect();
+
+            match shasta::http_client::v3::patch_component_list(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                component_batch,
+            )
+            .await
+            {
+                Ok(_) => report.patched_xname_vec.extend_from_slice(xname_batch),
+                Err(error) => {
+                    log::warn!("Could not update CFS components. Reason:\n{}", error);
+
+                    for xname in xname_batch {
+                        report.outcome_map.insert(
+                            xname.clone(),
+                            ComponentOutcome::PatchFailed {
+                                reason: error.to_string(),
+                            },
+                        );
+                    }
+                }
+            }
+        }
+
+        // Follow components
+        let mut pending_xname_vec: Vec<String> = component_xname_set
+            .into_iter()
+            .filter(|xname| !report.outcome_map.contains_key(*xname))
+            .cloned()
+            .collect();
+
+        if !self.enabled {
+            for xname in pending_xname_vec.drain(..) {
+                report.outcome_map.insert(xname, ComponentOutcome::Disabled);
+            }
+        }
+
+        let deadline = Instant::now() + self.timeout;
+
+        let mut last_status_map: BTreeMap<String, Option<String>> = BTreeMap::new();
+
+        // Components just patched still report the status of their previous configuration till
+        // CFS picks up the change
+        if !report.patched_xname_vec.is_empty() && !pending_xname_vec.is_empty() {
+            tokio::time::sleep_until(std::cmp::min(Instant::now() + self.poll_interval, deadline))
+                .await;
+        }
+
+        while !pending_xname_vec.is_empty() {
+            let component_vec = http_client::get_multiple(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                &pending_xname_vec,
+            )
+            .await?;
+
+            for component in component_vec {
+                let Some(xname) = component.id.clone() else {
+                    continue;
+                };
+
+                if is_error_count_exceeded(&component)
+                    && !report.error_count_exceeded_xname_vec.contains(&xname)
+                {
+                    report.error_count_exceeded_xname_vec.push(xname.clone());
+                }
+
+                match get_final_outcome(&component) {
+                    Some(outcome) => {
+                        report.outcome_map.insert(xname.clone(), outcome);
+                        pending_xname_vec.retain(|pending_xname| *pending_xname != xname);
+                    }
+                    None => {
+                        last_status_map.insert(xname, component.configuration_status);
+                    }
+                }
+            }
+
+            log::info!(
+                "{} CFS components in HSM group '{}' still being configured",
+                pending_xname_vec.len(),
+                self.hsm_group_name
+            );
+
+            if pending_xname_vec.is_empty() || Instant::now() + self.poll_interval > deadline {
+                break;
+            }
+
+            tokio::time::sleep(self.poll_interval).await;
+        }
+
+        for xname in pending_xname_vec {
+            report.outcome_map.insert(
+                xname.clone(),
+                ComponentOutcome::TimedOut {
+                    configuration_status: last_status_map.remove(&xname).flatten(),
+                },
+            );
+        }
+
+        Ok(report)
+    }
+
+    /// The `error_count` is reset when the desired configuration changes, otherwise CFS would not
+    /// configure components which exhausted their retries with the previous configuration
+    fn get_component_patch(&self, component: &ComponentResponse) -> Component {
+        let error_count_opt =
+            if component.desired_config.as_deref() != Some(self.desired_configuration.as_str()) {
+                Some(0)
+            } else {
+                None
+            };
+
+        Component {
+            id: component.id.clone(),
+            state: None,
+            state_append: None,
+            desired_config: Some(self.desired_configuration.clone()),
+            error_count: error_count_opt,
+            retry_policy: None,
+            enabled: Some(self.enabled),
+            configuration_status: None,
+            tags: None,
+            logs: None,
+        }
+    }
+}
+
+/// CFS stops configuring a component once its `error_count` reaches its `retry_policy`
+pub fn is_error_count_exceeded(component: &ComponentResponse) -> bool {
+    match (component.error_count, component.retry_policy) {
+        (Some(error_count), Some(retry_policy)) => error_count >= retry_policy,
+        _ => false,
+    }
+}
+
+/// Returns the outcome of a component which CFS is not going to configure anymore, None if CFS
+/// is still working on it
+fn get_final_outcome(component: &ComponentResponse) -> Option<ComponentOutcome> {
+    match component.configuration_status.as_deref() {
+        Some("configured") => Some(ComponentOutcome::Configured),
+        Some("failed") => Some(ComponentOutcome::Failed),
+        _ if is_error_count_exceeded(component) => Some(ComponentOutcome::Failed),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::test_fixtures::cfs_component as component;
+
+    use super::*;
+
+    #[test]
+    fn test_get_out_of_sync_xname_vec() {
+        let component_vec = vec![
+            component("x1000c1s7b0n0", "config-a", true, "configured", 0),
+            component("x1000c1s7b0n1", "config-b", true, "configured", 0),
+            component("x1000c1s7b1n0", "config-a", false, "configured", 0),
+        ];
+
+        assert_eq!(
+            CfsComponentReconciler::new("zinal", "config-a")
+                .get_out_of_sync_xname_vec(&component_vec),
+            vec!["x1000c1s7b0n1", "x1000c1s7b1n0"]
+        );
+    }
+
+    #[test]
+    fn test_get_final_outcome() {
+        assert_eq!(
+            get_final_outcome(&component("x1000c1s7b0n0", "a", true, "configured", 0)),
+            Some(ComponentOutcome::Configured)
+        );
+        assert_eq!(
+            get_final_outcome(&component("x1000c1s7b0n0", "a", true, "pending", 1)),
+            None
+        );
+        assert_eq!(
+            get_final_outcome(&component("x1000c1s7b0n0", "a", true, "pending", 3)),
+            Some(ComponentOutcome::Failed)
+        );
+        assert!(is_error_count_exceeded(&component(
+            "x1000c1s7b0n0",
+            "a",
+            true,
+            "failed",
+            3
+        )));
+    }
+
+    #[test]
+    fn test_get_component_patch_resets_error_count() {
+        let reconciler = CfsComponentReconciler::new("zinal", "config-a");
+
+        // New desired configuration, CFS must retry from scratch
+        assert_eq!(
+            reconciler
+                .get_component_patch(&component("x1000c1s7b0n0", "config-b", true, "failed", 3))
+                .error_count,
+            Some(0)
+        );
+        // Only 'enabled' changes, error count is kept
+        assert_eq!(
+            reconciler
+                .get_component_patch(&component("x1000c1s7b0n0", "config-a", false, "failed", 3))
+                .error_count,
+            None
+        );
+    }
+}
@@ -0,0 +1,328 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cfs::component::shasta::{
+        self,
+        r#struct::{
+            v2::{ComponentResponse, StateResponse},
+            v3::Component,
+        },
+    },
+    common::jwt_ops,
+    error::Error,
+    hsm,
+};
+
+use super::{http_client, reconciler::is_error_count_exceeded};
+
+/// What was reset in a CFS component, including the values before the reset
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ComponentResetRecord {
+    pub xname: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desired_config: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub configuration_status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    pub error_count: u64,
+    pub state_cleared: bool,
+    /// State history before the reset. Only recorded if the state history was cleared
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub state_vec: Vec<StateResponse>,
+}
+
+/// Audit record of a retry. Serialize it to keep track of who reset what and when
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ComponentRetryReport {
+    pub user: String,
+    pub timestamp: String,
+    pub dry_run: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hsm_group_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub configuration_name: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tag_vec: Vec<(String, String)>,
+    pub reset_vec: Vec<ComponentResetRecord>,
+    /// xname --> reason why the CFS component could not be reset
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failed_vec: Vec<(String, String)>,
+}
+
+/// Resets the `error_count` of failed CFS components and enables them so CFS tries to configure
+/// them again. Components are selected by HSM group, desired configuration and/or tags, components
+/// must match all of them. Without an HSM group, components are filtered by CFS (by configuration
+/// and tags or, if none is provided, by 'failed' status)
+///
+/// eg: retry failed components in HSM group 'zinal' and clear their state history
+///
+/// let report = CfsComponentRetry::new()
+///     .hsm_group_name("zinal")
+///     .clear_state(true)
+///     .run(shasta_token, shasta_base_url, shasta_root_cert)
+///     .await?;
+#[derive(Debug, Clone, Default)]
+pub struct CfsComponentRetry {
+    hsm_group_name_opt: Option<String>,
+    configuration_name_opt: Option<String>,
+    tag_vec: Vec<(String, String)>,
+    clear_state: bool,
+    dry_run: bool,
+}
+
+impl CfsComponentRetry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn hsm_group_name(mut self, hsm_group_name: &str) -> Self {
+        self.hsm_group_name_opt = Some(hsm_group_name.to_string());
+        self
+    }
+
+    /// Components which desired configuration is `configuration_name`
+    pub fn configuration_name(mut self, configuration_name: &str) -> Self {
+        self.configuration_name_opt = Some(configuration_name.to_string());
+        self
+    }
+
+    pub fn tag(mut self, key: &str, value: &str) -> Self {
+        self.tag_vec.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Also clear the components state history so all layers are applied again
+    pub fn clear_state(mut self, clear_state: bool) -> Self {
+        self.clear_state = clear_state;
+        self
+    }
+
+    /// Report what would be reset without updating the components
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// CFS status to filter by when components are not selected by HSM group. Only used if there
+    /// is no configuration or tag selector so we never download all CFS components in the
+    /// system, otherwise components which exceeded the error count but are not flagged as
+    /// 'failed' yet would be missed
+    fn server_side_status(&self) -> Option<&'static str> {
+        if self.configuration_name_opt.is_none() && self.tag_vec.is_empty() {
+            Some("failed")
+        } else {
+            None
+        }
+    }
+
+    /// True if the component failed and matches the configuration and tags selectors
+    fn is_selected(&self, component: &ComponentResponse) -> bool {
+        let is_failed = component.configuration_status.as_deref() == Some("failed")
+            || is_error_count_exceeded(component);
+
+        let is_configuration_match = self
+            .configuration_name_opt
+            .as_ref()
+            .is_none_or(|name| component.desired_config.as_ref() == Some(name));
+
+        let is_tag_match = self.tag_vec.iter().all(|(key, value)| {
+            component
+                .tags
+                .as_ref()
+                .and_then(|tags| tags.get(key))
+                .is_some_and(|tag_value| tag_value == value)
+        });
+
+        is_failed && is_configuration_match && is_tag_match
+    }
+
+    pub async fn run(
+        &self,
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+    ) -> Result<ComponentRetryReport, Error> {
+        let user = jwt_ops::get_preferred_username(shasta_token)
+            .map_err(|error| Error::Message(error.to_string()))?;
+
+        let mut component_vec = if let Some(hsm_group_name) = &self.hsm_group_name_opt {
+            let xname_vec = hsm::group::utils::get_member_vec_from_hsm_name_vec(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                vec![hsm_group_name.clone()],
+            )
+            .await;
+
+            if xname_vec.is_empty() {
+                return Err(Error::Message(format!(
+                    "HSM group '{}' not found or has no members",
+                    hsm_group_name
+                )));
+            }
+
+            http_client::get_multiple(shasta_token, shasta_base_url, shasta_root_cert, &xname_vec)
+                .await?
+        } else {
+            http_client::get_filtered(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                self.configuration_name_opt.as_deref(),
+                self.server_side_status(),
+                &self.tag_vec,
+            )
+            .await?
+        };
+
+        component_vec.retain(|component| self.is_selected(component));
+
+        let mut report = ComponentRetryReport {
+            user,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            dry_run: self.dry_run,
+            hsm_group_name: self.hsm_group_name_opt.clone(),
+            configuration_name: self.configuration_name_opt.clone(),
+            tag_vec: self.tag_vec.clone(),
+            reset_vec: Vec::new(),
+            failed_vec: Vec::new(),
+        };
+
+        let reset_vec: Vec<ComponentResetRecord> = component_vec
+            .into_iter()
+            .filter_map(|component| {
+                Some(ComponentResetRecord {
+                    xname: component.id?,
+                    desired_config: component.desired_config,
+                    configuration_status: component.configuration_status,
+                    enabled: component.enabled,
+                    error_count: component.error_count.unwrap_or_default(),
+                    state_cleared: self.clear_state,
+                    state_vec: if self.clear_state {
+                        component.state.unwrap_or_default()
+                    } else {
+                        Vec::new()
+                    },
+                })
+            })
+            .collect();
+
+        if self.dry_run || reset_vec.is_empty() {
+            report.reset_vec = reset_vec;
+            return Ok(report);
+        }
+
+        let component_patch_vec = reset_vec
+            .iter()
+            .map(|reset| Component {
+                id: Some(reset.xname.clone()),
+                state: if self.clear_state {
+                    Some(Vec::new())
+                } else {
+                    None
+                },
+                state_append: None,
+                desired_config: None,
+                error_count: Some(0),
+                retry_policy: None,
+                enabled: Some(true),
+                configuration_status: None,
+                tags: None,
+                logs: None,
+            })
+            .collect();
+
+        match shasta::http_client::v3::patch_component_list(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            component_patch_vec,
+        )
+        .await
+        {
+            Ok(_) => {
+                for reset in &reset_vec {
+                    log::info!(
+                        "User '{}' reset CFS component '{}' (error count {}, state cleared {})",
+                        report.user,
+                        reset.xname,
+                        reset.error_count,
+                        reset.state_cleared
+                    );
+                }
+
+                report.reset_vec = reset_vec;
+            }
+            Err(error) => {
+                report.failed_vec = reset_vec
+                    .into_iter()
+                    .map(|reset| (reset.xname, error.to_string()))
+                    .collect();
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::common::test_fixtures;
+
+    use super::*;
+
+    fn component(configuration_status: &str, error_count: u64) -> ComponentResponse {
+        let mut component = test_fixtures::cfs_component(
+            "x1000c1s7b0n0",
+            "config-a",
+            true,
+            configuration_status,
+            error_count,
+        );
+        component.tags = Some(HashMap::from([(
+            "mesa.owner".to_string(),
+            "alice".to_string(),
+        )]));
+        component
+    }
+
+    #[test]
+    fn test_is_selected() {
+        let retry = CfsComponentRetry::new()
+            .configuration_name("config-a")
+            .tag("mesa.owner", "alice");
+
+        assert!(retry.is_selected(&component("failed", 1)));
+        assert!(retry.is_selected(&component("pending", 3)));
+        assert!(!retry.is_selected(&component("configured", 0)));
+        assert!(!CfsComponentRetry::new()
+            .configuration_name("config-b")
+            .is_selected(&component("failed", 3)));
+        assert!(!CfsComponentRetry::new()
+            .tag("mesa.owner", "bob")
+            .is_selected(&component("failed", 3)));
+    }
+
+    #[test]
+    fn test_server_side_status() {
+        assert_eq!(
+            CfsComponentRetry::new().server_side_status(),
+            Some("failed")
+        );
+        assert_eq!(
+            CfsComponentRetry::new()
+                .configuration_name("config-a")
+                .server_side_status(),
+            None
+        );
+        assert_eq!(
+            CfsComponentRetry::new()
+                .tag("mesa.owner", "alice")
+                .server_side_status(),
+            None
+        );
+    }
+}
@@ -13,7 +13,7 @@ pub mod v2 {
         #[serde(skip_serializing_if = "Option::is_none")]
         pub commit: Option<String>,
         #[serde(skip_serializing_if = "Option::is_none")]
-        #[serde(rename = "sesisonName")]
+        #[serde(rename = "sessionName")]
         pub session_name: Option<String>,
         #[serde(skip_serializing_if = "Option::is_none")]
         #[serde(rename = "lastUpdated")]
@@ -57,7 +57,7 @@ pub mod v2 {
         #[serde(skip_serializing_if = "Option::is_none")]
         pub commit: Option<String>,
         #[serde(skip_serializing_if = "Option::is_none")]
-        #[serde(rename = "sesisonName")]
+        #[serde(rename = "sessionName")]
         pub session_name: Option<String>,
     }
 
@@ -173,10 +173,12 @@ pub mod v3 {
         pub state_append: Option<State>,
         #[serde(skip_serializing_if = "Option::is_none")]
         pub desired_config: Option<String>,
+        // BREAKING CHANGE: `error_count` and `retry_policy` used to be `Option<String>`, CFS v3
+        // sends and expects numbers
         #[serde(skip_serializing_if = "Option::is_none")]
-        pub error_count: Option<String>,
+        pub error_count: Option<u64>,
         #[serde(skip_serializing_if = "Option::is_none")]
-        pub retry_policy: Option<String>,
+        pub retry_policy: Option<u64>,
         #[serde(skip_serializing_if = "Option::is_none")]
         pub enabled: Option<bool>,
         #[serde(skip_serializing_if = "Option::is_none")]
@@ -2,12 +2,17 @@
 /// name, commit date, etc
 
 pub mod v2 {
-    use std::collections::BTreeMap;
-
     use serde::{Deserialize, Serialize};
-    use serde_yaml::Value;
 
-    use crate::common::gitea;
+    use crate::{
+        common::{
+            cray_product_catalog::{
+                k8s::K8sAccess, r#struct::ProductCatalog, utils::ProductCatalogResolver,
+            },
+            gitea,
+        },
+        error::Error,
+    };
 
     #[derive(Debug, Serialize, Deserialize, Clone)]
     pub struct Layer {
@@ -36,10 +41,24 @@ pub mod v2 {
         ims_required_dkms: Option<bool>,
     }
 
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct AdditionalInventory {
+        #[serde(rename = "cloneUrl")]
+        pub clone_url: String,
+        #[serde(skip_serializing_if = "Option::is_none")] // Either commit or branch is passed
+        pub commit: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub name: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")] // Either commit or branch is passed
+        pub branch: Option<String>,
+    }
+
     #[derive(Debug, Deserialize, Serialize, Clone)]
     pub struct CfsConfigurationRequest {
         pub name: String,
         pub layers: Vec<Layer>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub additional_inventory: Option<AdditionalInventory>,
     }
 
     impl Layer {
@@ -62,6 +81,10 @@ pub mod v2 {
                 special_parameters,
             }
         }
+
+        pub fn get_playbook(&self) -> &str {
+            &self.playbook
+        }
     }
 
     impl Default for CfsConfigurationRequest {
@@ -75,6 +98,7 @@ pub mod v2 {
             Self {
                 name: String::default(),
                 layers: Vec::default(),
+                additional_inventory: None,
             }
         }
 
@@ -87,12 +111,18 @@ pub mod v2 {
             gitea_base_url: &str,
             gitea_token: &str,
             configuration_yaml: &serde_yaml::Value,
-            cray_product_catalog: &BTreeMap<String, String>,
+            cray_product_catalog_opt: Option<&ProductCatalog>,
+            k8s_access_opt: Option<&K8sAccess<'_>>,
             site_name: &str,
-        ) -> (String, Self) {
+        ) -> Result<(String, Self), Error> {
             let cfs_configuration_name;
             let mut cfs_configuration = Self::new();
 
+            // Cray product catalog is fetched only if not provided and the SAT file has product
+            // layers
+            let mut product_catalog_resolver =
+                ProductCatalogResolver::new(cray_product_catalog_opt, k8s_access_opt);
+
             cfs_configuration_name = configuration_yaml["name"].as_str().unwrap().to_string();
 
             cfs_configuration.name = configuration_yaml["name"].as_str().unwrap().to_string();
@@ -199,40 +229,22 @@ pub mod v2 {
                     let product_version = layer_yaml["product"]["version"].as_str().unwrap();
                     let product_branch_value_opt = layer_yaml["product"].get("branch");
 
-                    let product = cray_product_catalog.get(product_name);
-
-                    if product.is_none() {
-                        eprintln!("Product {} not found in cray product catalog", product_name);
-                        std::process::exit(1);
-                    }
-
-                    let cos_cray_product_catalog =
-                        serde_yaml::from_str::<Value>(product.unwrap()).unwrap();
-
-                    let product_details_opt = cos_cray_product_catalog
-                        .get(product_version)
-                        .and_then(|product| product.get("configuration"));
-
-                    if product_details_opt.is_none() {
-                        eprintln!("Product details for product name '{}', product_version '{}' and 'configuration' not found in cray product catalog", product_name, product_version);
-                        std::process::exit(1);
-                    }
-
-                    let product_details = product_details_opt.unwrap().clone();
+                    let resolved_product = product_catalog_resolver
+                        .resolve(product_name, product_version)
+                        .await?;
 
                     log::debug!(
                         "CRAY product catalog details for product: {}, version: {}:\n{:#?}",
                         product_name,
                         product_version,
-                        product_details
+                        resolved_product
                     );
 
                     // Manta may run outside the CSM local network therefore we have to change the
                     // internal URLs for the external one
-                    let repo_url = product_details["clone_url"]
-                        .as_str()
-                        .unwrap()
-                        .to_string()
+                    let repo_url = resolved_product
+                        .configuration
+                        .clone_url
                         .replace("vcs.cmn.alps.cscs.ch", "api-gw-service-nmn.local");
 
                     let commit_id_opt = if product_branch_value_opt.is_some() {
@@ -252,7 +264,7 @@ pub mod v2 {
 
                         commit
                     } else {
-                        Some(product_details["commit"].as_str().unwrap().to_string())
+                        Some(resolved_product.configuration.commit.clone())
                     };
 
                     // IMPORTANT: CSM won't allow CFS configuration layers with both commit id and
@@ -282,7 +294,7 @@ pub mod v2 {
                 }
             }
 
-            (cfs_configuration_name, cfs_configuration)
+            Ok((cfs_configuration_name, cfs_configuration))
         }
 
         /* pub async fn create_from_repos(
@@ -381,12 +393,17 @@ pub mod v2 {
 }
 
 pub mod v3 {
-    use std::collections::BTreeMap;
-
     use serde::{Deserialize, Serialize};
-    use serde_yaml::Value;
 
-    use crate::common::gitea;
+    use crate::{
+        common::{
+            cray_product_catalog::{
+                k8s::K8sAccess, r#struct::ProductCatalog, utils::ProductCatalogResolver,
+            },
+            gitea,
+        },
+        error::Error,
+    };
 
     #[derive(Debug, Serialize, Deserialize, Clone)]
     pub struct Layer {
@@ -425,6 +442,10 @@ pub mod v3 {
                 source,
             }
         }
+
+        pub fn get_playbook(&self) -> &str {
+            &self.playbook
+        }
     }
 
     #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -435,11 +456,11 @@ pub mod v3 {
 
     #[derive(Debug, Deserialize, Serialize, Clone)]
     pub struct AdditionalInventory {
-        name: Option<String>,
-        clone_url: String,
-        source: Option<String>,
-        commit: Option<String>,
-        branch: Option<String>,
+        pub name: Option<String>,
+        pub clone_url: String,
+        pub source: Option<String>,
+        pub commit: Option<String>,
+        pub branch: Option<String>,
     }
 
     #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -475,12 +496,18 @@ pub mod v3 {
             gitea_base_url: &str,
             gitea_token: &str,
             configuration_yaml: &serde_yaml::Value,
-            cray_product_catalog: &BTreeMap<String, String>,
+            cray_product_catalog_opt: Option<&ProductCatalog>,
+            k8s_access_opt: Option<&K8sAccess<'_>>,
             site_name: &str,
-        ) -> (String, Self) {
+        ) -> Result<(String, Self), Error> {
             let cfs_configuration_name;
             let mut cfs_configuration = Self::new();
 
+            // Cray product catalog is fetched only if not provided and the SAT file has product
+            // layers
+            let mut product_catalog_resolver =
+                ProductCatalogResolver::new(cray_product_catalog_opt, k8s_access_opt);
+
             cfs_configuration_name = configuration_yaml["name"].as_str().unwrap().to_string();
 
             for layer_yaml in configuration_yaml["layers"].as_sequence().unwrap() {
@@ -588,40 +615,22 @@ pub mod v3 {
                     let product_branch_value_opt = layer_yaml["product"].get("branch");
                     let product_commit_value_opt = layer_yaml["product"].get("commit");
 
-                    let product = cray_product_catalog.get(product_name);
-
-                    if product.is_none() {
-                        eprintln!("Product {} not found in cray product catalog", product_name);
-                        std::process::exit(1);
-                    }
-
-                    let cos_cray_product_catalog =
-                        serde_yaml::from_str::<Value>(product.unwrap()).unwrap();
-
-                    let product_details_opt = cos_cray_product_catalog
-                        .get(product_version)
-                        .and_then(|product| product.get("configuration"));
-
-                    if product_details_opt.is_none() {
-                        eprintln!("Product details for product name '{}', product_version '{}' and 'configuration' not found in cray product catalog", product_name, product_version);
-                        std::process::exit(1);
-                    }
-
-                    let product_details = product_details_opt.unwrap().clone();
+                    let resolved_product = product_catalog_resolver
+                        .resolve(product_name, product_version)
+                        .await?;
 
                     log::debug!(
                         "CRAY product catalog details for product: {}, version: {}:\n{:#?}",
                         product_name,
                         product_version,
-                        product_details
+                        resolved_product
                     );
 
                     // Manta may run outside the CSM local network therefore we have to change the
                     // internal URLs for the external one
-                    let repo_url = product_details["clone_url"]
-                        .as_str()
-                        .unwrap()
-                        .to_string()
+                    let repo_url = resolved_product
+                        .configuration
+                        .clone_url
                         .replace("vcs.cmn.alps.cscs.ch", "api-gw-service-nmn.local");
 
                     let commit_id_opt = if let Some(commit_value) = product_commit_value_opt {
@@ -644,7 +653,7 @@ pub mod v3 {
                                 .unwrap(),
                             )
                         } else {
-                            Some(product_details["commit"].as_str().unwrap().to_string())
+                            Some(resolved_product.configuration.commit.clone())
                         }
                     };
 
@@ -677,7 +686,7 @@ pub mod v3 {
                 }
             }
 
-            (cfs_configuration_name, cfs_configuration)
+            Ok((cfs_configuration_name, cfs_configuration))
         }
 
         /* pub async fn create_from_repos(
@@ -11,7 +11,7 @@ use crate::{
 use globset::Glob;
 
 use super::r#struct::{
-    cfs_configuration_request::v2::CfsConfigurationRequest,
+    cfs_configuration_request::{self, v2::CfsConfigurationRequest},
     cfs_configuration_response::v2::CfsConfigurationResponse,
 };
 
@@ -32,6 +32,329 @@ pub async fn create(
     .await
 }
 
+/// Same as `create` but validates the CFS configuration against Gitea first (see `validate`) so
+/// errors like a typo in a playbook name are caught before CFS sessions start failing
+pub async fn validate_and_create(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    gitea_base_url: &str,
+    gitea_token: &str,
+    cfs_configuration_name: &str,
+    cfs_configuration: &CfsConfigurationRequest,
+) -> Result<CfsConfigurationResponse, crate::error::Error> {
+    if let Err(error_vec) = validate(
+        gitea_base_url,
+        gitea_token,
+        shasta_root_cert,
+        cfs_configuration,
+    )
+    .await
+    {
+        return Err(crate::error::Error::Message(format!(
+            "CFS configuration '{}' is not valid:\n{}",
+            cfs_configuration_name,
+            error_vec
+                .iter()
+                .map(|error| format!(" - {}", error))
+                .collect::<Vec<String>>()
+                .join("\n")
+        )));
+    }
+
+    create(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        cfs_configuration_name,
+        cfs_configuration,
+    )
+    .await
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ConfigurationValidationError {
+    #[error("layer '{layer}': repository '{repo_url}' not found")]
+    RepoNotFound { layer: String, repo_url: String },
+    #[error("layer '{layer}': commit '{commit}' not found in repository '{repo_url}'")]
+    CommitNotFound {
+        layer: String,
+        repo_url: String,
+        commit: String,
+    },
+    #[error("layer '{layer}': git ref '{git_ref}' not found in repository '{repo_url}'")]
+    RefNotFound {
+        layer: String,
+        repo_url: String,
+        git_ref: String,
+    },
+    #[error("layer '{layer}': no commit, branch or tag provided")]
+    MissingRef { layer: String },
+    #[error(
+        "layer '{layer}': playbook '{playbook}' not found in repository '{repo_url}' at '{git_ref}'"
+    )]
+    PlaybookNotFound {
+        layer: String,
+        repo_url: String,
+        git_ref: String,
+        playbook: String,
+    },
+    #[error("layer '{layer}': could not validate against Gitea. Reason: {reason}")]
+    Gitea { layer: String, reason: String },
+}
+
+/// Git details common to CFS configuration layers and additional inventory
+struct GitSource {
+    name: String,
+    clone_url: String,
+    commit: Option<String>,
+    branch: Option<String>,
+    tag: Option<String>,
+    // None for additional inventory since it has no playbook
+    playbook: Option<String>,
+}
+
+/// Validates the CFS configuration layers and additional inventory against Gitea:
+///  - repository exists
+///  - commit exists, or branch/tag exists if no commit provided
+///  - playbook file exists at that commit/branch/tag
+///
+/// All problems found are returned at once
+pub async fn validate(
+    gitea_base_url: &str,
+    gitea_token: &str,
+    shasta_root_cert: &[u8],
+    cfs_configuration: &CfsConfigurationRequest,
+) -> Result<(), Vec<ConfigurationValidationError>> {
+    validate_git_source_vec(&get_git_source_vec(cfs_configuration), |git_source| {
+        validate_git_source(gitea_base_url, gitea_token, shasta_root_cert, git_source)
+    })
+    .await
+}
+
+/// Same as `validate` for CFS v3 configurations
+pub async fn validate_v3(
+    gitea_base_url: &str,
+    gitea_token: &str,
+    shasta_root_cert: &[u8],
+    cfs_configuration: &cfs_configuration_request::v3::CfsConfigurationRequest,
+) -> Result<(), Vec<ConfigurationValidationError>> {
+    validate_git_source_vec(&get_git_source_vec_v3(cfs_configuration), |git_source| {
+        validate_git_source(gitea_base_url, gitea_token, shasta_root_cert, git_source)
+    })
+    .await
+}
+
+fn get_git_source_vec(cfs_configuration: &CfsConfigurationRequest) -> Vec<GitSource> {
+    let mut git_source_vec: Vec<GitSource> = cfs_configuration
+        .layers
+        .iter()
+        .map(|layer| GitSource {
+            name: layer
+                .name
+                .clone()
+                .unwrap_or(layer.clone_url.clone().unwrap_or_default()),
+            clone_url: layer.clone_url.clone().unwrap_or_default(),
+            commit: layer.commit.clone(),
+            branch: layer.branch.clone(),
+            tag: layer.tag.clone(),
+            playbook: Some(layer.get_playbook().to_string()),
+        })
+        .collect();
+
+    if let Some(additional_inventory) = &cfs_configuration.additional_inventory {
+        git_source_vec.push(GitSource {
+            name: "additional_inventory".to_string(),
+            clone_url: additional_inventory.clone_url.clone(),
+            commit: additional_inventory.commit.clone(),
+            branch: additional_inventory.branch.clone(),
+            tag: None,
+            playbook: None,
+        });
+    }
+
+    git_source_vec
+}
+
+fn get_git_source_vec_v3(
+    cfs_configuration: &cfs_configuration_request::v3::CfsConfigurationRequest,
+) -> Vec<GitSource> {
+    let mut git_source_vec: Vec<GitSource> = cfs_configuration
+        .layers
+        .iter()
+        .flatten()
+        .map(|layer| GitSource {
+            name: layer
+                .name
+                .clone()
+                .unwrap_or(layer.clone_url.clone().unwrap_or_default()),
+            clone_url: layer.clone_url.clone().unwrap_or_default(),
+            commit: layer.commit.clone(),
+            branch: layer.branch.clone(),
+            tag: None,
+            playbook: Some(layer.get_playbook().to_string()),
+        })
+        .collect();
+
+    if let Some(additional_inventory) = &cfs_configuration.additional_inventory {
+        git_source_vec.push(GitSource {
+            name: "additional_inventory".to_string(),
+            clone_url: additional_inventory.clone_url.clone(),
+            commit: additional_inventory.commit.clone(),
+            branch: additional_inventory.branch.clone(),
+            tag: None,
+            playbook: None,
+        });
+    }
+
+    git_source_vec
+}
+
+/// Validates each layer with `validate_git_source_fn` and collects the errors of all layers
+async fn validate_git_source_vec<'a, F, Fut>(
+    git_source_vec: &'a [GitSource],
+    validate_git_source_fn: F,
+) -> Result<(), Vec<ConfigurationValidationError>>
+where
+    F: Fn(&'a GitSource) -> Fut,
+    Fut: std::future::Future<Output = Result<(), ConfigurationValidationError>>,
+{
+    let mut error_vec = Vec::new();
+
+    for git_source in git_source_vec {
+        log::info!(
+            "Validating CFS configuration layer '{}' against Gitea",
+            git_source.name
+        );
+
+        if let Err(error) = validate_git_source_fn(git_source).await {
+            error_vec.push(error);
+        }
+    }
+
+    if error_vec.is_empty() {
+        Ok(())
+    } else {
+        Err(error_vec)
+    }
+}
+
+async fn validate_git_source(
+    gitea_base_url: &str,
+    gitea_token: &str,
+    shasta_root_cert: &[u8],
+    git_source: &GitSource,
+) -> Result<(), ConfigurationValidationError> {
+    let layer = git_source.name.clone();
+    let repo_url = git_source.clone_url.clone();
+
+    let gitea_error = |error: crate::error::Error| ConfigurationValidationError::Gitea {
+        layer: layer.clone(),
+        reason: error.to_string(),
+    };
+
+    let repo_name = common::gitea::utils::get_repo_name_from_url(&repo_url);
+
+    // Check repo exists
+    if common::gitea::http_client::get_repo(
+        gitea_base_url,
+        gitea_token,
+        shasta_root_cert,
+        repo_name,
+    )
+    .await
+    .map_err(gitea_error)?
+    .is_none()
+    {
+        return Err(ConfigurationValidationError::RepoNotFound { layer, repo_url });
+    }
+
+    // Check commit exists or branch/tag exists
+    let git_ref = if let Some(commit) = &git_source.commit {
+        if common::gitea::http_client::get_commit(
+            gitea_base_url,
+            gitea_token,
+            shasta_root_cert,
+            repo_name,
+            commit,
+        )
+        .await
+        .map_err(gitea_error)?
+        .is_none()
+        {
+            return Err(ConfigurationValidationError::CommitNotFound {
+                layer,
+                repo_url,
+                commit: commit.clone(),
+            });
+        }
+
+        commit.clone()
+    } else {
+        let (git_ref, git_ref_full_name) = if let Some(branch) = &git_source.branch {
+            (branch.clone(), format!("refs/heads/{}", branch))
+        } else if let Some(tag) = &git_source.tag {
+            (tag.clone(), format!("refs/tags/{}", tag))
+        } else {
+            return Err(ConfigurationValidationError::MissingRef { layer });
+        };
+
+        let ref_vec = common::gitea::http_client::get_all_refs(
+            gitea_base_url,
+            gitea_token,
+            repo_name,
+            shasta_root_cert,
+        )
+        .await
+        .map_err(gitea_error)?;
+
+        if !ref_vec
+            .iter()
+            .any(|ref_details| ref_details["ref"].as_str() == Some(git_ref_full_name.as_str()))
+        {
+            return Err(ConfigurationValidationError::RefNotFound {
+                layer,
+                repo_url,
+                git_ref,
+            });
+        }
+
+        git_ref
+    };
+
+    // Check playbook exists at the commit/branch/tag
+    if let Some(playbook) = &git_source.playbook {
+        // CFS uses 'site.yml' if no playbook provided
+        let playbook = if playbook.is_empty() {
+            "site.yml".to_string()
+        } else {
+            playbook.clone()
+        };
+
+        if common::gitea::http_client::get_file_metadata(
+            gitea_base_url,
+            gitea_token,
+            shasta_root_cert,
+            repo_name,
+            &playbook,
+            &git_ref,
+        )
+        .await
+        .map_err(gitea_error)?
+        .is_none()
+        {
+            return Err(ConfigurationValidationError::PlaybookNotFound {
+                layer,
+                repo_url,
+                git_ref,
+                playbook,
+            });
+        }
+    }
+
+    Ok(())
+}
+
 /// Filter the list of CFS configurations provided. This operation is very expensive since it is
 /// filtering by HSM group which means it needs to link CFS configurations with CFS sessions and
 /// BOS sessiontemplate. Aditionally, it will also fetch CFS components to find CFS sessions and
@@ -340,3 +663,88 @@ pub async fn get_derivatives(
         Some(ims_images),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Fails the layers which have no commit, like Gitea would if the commit was not found
+    async fn validate_commit_only(
+        git_source: &GitSource,
+    ) -> Result<(), ConfigurationValidationError> {
+        match &git_source.commit {
+            Some(_) => Ok(()),
+            None => Err(ConfigurationValidationError::MissingRef {
+                layer: git_source.name.clone(),
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_aggregates_errors_of_all_layers() {
+        let cfs_configuration: CfsConfigurationRequest = serde_json::from_value(json!({
+            "name": "test-configuration",
+            "layers": [
+                { "name": "cos", "cloneUrl": "https://vcs/cray/cos-config-management.git", "commit": "abc", "playbook": "site.yml" },
+                { "name": "csm", "cloneUrl": "https://vcs/cray/csm-config-management.git", "branch": "main", "playbook": "site.yml" },
+                { "cloneUrl": "https://vcs/cray/uan-config-management.git", "tag": "1.0.0", "playbook": "uan.yml" }
+            ],
+            "additional_inventory": { "cloneUrl": "https://vcs/cray/inventory.git", "branch": "main" }
+        }))
+        .unwrap();
+
+        let git_source_vec = get_git_source_vec(&cfs_configuration);
+
+        assert_eq!(git_source_vec.len(), 4);
+
+        let error_vec = validate_git_source_vec(&git_source_vec, validate_commit_only)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error_vec,
+            vec![
+                ConfigurationValidationError::MissingRef {
+                    layer: "csm".to_string()
+                },
+                // Layer without name is reported by its clone url
+                ConfigurationValidationError::MissingRef {
+                    layer: "https://vcs/cray/uan-config-management.git".to_string()
+                },
+                ConfigurationValidationError::MissingRef {
+                    layer: "additional_inventory".to_string()
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_v3_aggregates_errors_of_all_layers() {
+        let cfs_configuration: cfs_configuration_request::v3::CfsConfigurationRequest =
+            serde_json::from_value(json!({
+                "layers": [
+                    { "name": "cos", "clone_url": "https://vcs/cray/cos-config-management.git", "branch": "main", "playbook": "site.yml" },
+                    { "name": "csm", "clone_url": "https://vcs/cray/csm-config-management.git", "commit": "abc", "playbook": "site.yml" }
+                ]
+            }))
+            .unwrap();
+
+        let git_source_vec = get_git_source_vec_v3(&cfs_configuration);
+
+        assert_eq!(git_source_vec.len(), 2);
+
+        assert_eq!(
+            validate_git_source_vec(&git_source_vec, validate_commit_only).await,
+            Err(vec![ConfigurationValidationError::MissingRef {
+                layer: "cos".to_string()
+            }])
+        );
+
+        assert_eq!(
+            validate_git_source_vec(&git_source_vec[1..], validate_commit_only).await,
+            Ok(())
+        );
+    }
+}
@@ -68,7 +68,7 @@ pub mod shasta {
                 }
 
                 if let Some(is_succeded) = is_succeded_opt {
-                    request_payload.push(("succeced", is_succeded.to_string()));
+                    request_payload.push(("succeeded", is_succeded.to_string()));
                 }
 
                 if let Some(tags) = tags_opt {
@@ -274,7 +274,7 @@ pub mod shasta {
                 }
 
                 if let Some(is_succeded) = is_succeded_opt {
-                    request_payload.push(("succeced", is_succeded.to_string()));
+                    request_payload.push(("succeeded", is_succeded.to_string()));
                 }
 
                 if let Some(tags) = tags_opt {
@@ -283,6 +283,7 @@ pub mod shasta {
 
                 let response = client
                     .get(api_url)
+                    .query(&request_payload)
                     .bearer_auth(shasta_token)
                     .send()
                     .await
@@ -610,8 +611,11 @@ pub mod shasta {
 }
 
 pub mod mesa {
-    use crate::cfs;
-    use std::io::{self, Write};
+    pub mod builder;
+    pub mod model;
+    pub mod orchestrator;
+    pub mod query;
+    pub mod watcher;
 
     pub mod r#struct {
         pub mod v2 {
@@ -822,6 +826,10 @@ pub mod mesa {
             }
 
             impl CfsSessionPostRequest {
+                #[deprecated(
+                    since = "0.43.18",
+                    note = "Use `cfs::session::mesa::builder::CfsSessionBuilder` instead, it validates the CFS session and does not panic if image arguments are missing."
+                )]
                 pub fn new(
                     name: String,
                     configuration_name: String,
@@ -1102,11 +1110,24 @@ pub mod mesa {
 
             #[derive(Debug, Serialize, Deserialize, Clone)]
             pub struct ImageMap {
-                source_id: String,
-                result_name: String,
+                pub source_id: String,
+                pub result_name: String,
+            }
+
+            impl ImageMap {
+                pub fn new(source_id: &str, result_name: &str) -> Self {
+                    Self {
+                        source_id: source_id.to_string(),
+                        result_name: result_name.to_string(),
+                    }
+                }
             }
 
             impl CfsSessionPostRequest {
+                #[deprecated(
+                    since = "0.43.18",
+                    note = "Use `cfs::session::mesa::builder::CfsSessionBuilder` instead, it validates the CFS session and does not panic if image arguments are missing."
+                )]
                 pub fn new(
                     name: String,
                     configuration_name: String,
@@ -1180,7 +1201,8 @@ pub mod mesa {
 
         use super::{
             r#struct::v2::{CfsSessionGetResponse, CfsSessionPostRequest},
-            wait_cfs_session_to_finish,
+            utils::{self, CfsSessionSortOrder},
+            watcher::CfsSessionWatcher,
         };
 
         /// Fetch CFS sessions ref --> https://apidocs.svc.cscs.ch/paas/cfs/operation/get_sessions/
@@ -1211,28 +1233,7 @@ pub mod mesa {
             .await?;
 
             // Sort CFS sessions by start time order ASC
-            cfs_session_vec.sort_by(|a, b| {
-                a.status
-                    .as_ref()
-                    .unwrap()
-                    .session
-                    .as_ref()
-                    .unwrap()
-                    .start_time
-                    .as_ref()
-                    .unwrap()
-                    .cmp(
-                        b.status
-                            .as_ref()
-                            .unwrap()
-                            .session
-                            .as_ref()
-                            .unwrap()
-                            .start_time
-                            .as_ref()
-                            .unwrap(),
-                    )
-            });
+            utils::sort(&mut cfs_session_vec, CfsSessionSortOrder::StartTimeAsc);
 
             Ok(cfs_session_vec)
         }
@@ -1296,13 +1297,14 @@ pub mod mesa {
 
             // User does not want the CFS (ansible) logs but we still need to wait the CFS session to
             // finish (teardown). Wait till the CFS session finishes
-            wait_cfs_session_to_finish(
+            CfsSessionWatcher::new(
                 shasta_token,
                 shasta_base_url,
                 shasta_root_cert,
                 &cfs_session_name,
             )
-            .await;
+            .wait()
+            .await?;
 
             // Get CFS session status
             let cfs_session: CfsSessionGetResponse = get(
@@ -1408,7 +1410,7 @@ pub mod mesa {
                             target_hsm_vec.iter().any(|target_hsm| {
                                 hsm_group_name_vec
                                     .iter()
-                                    .any(|hsm_group_name| hsm_group_name.contains(target_hsm))
+                                    .any(|hsm_group_name| hsm_group_name == target_hsm)
                             })
                         }) || cfs_session
                             .get_target_xname()
@@ -1423,28 +1425,7 @@ pub mod mesa {
             }
 
             // Sort CFS sessions by start time order ASC
-            cfs_session_vec.sort_by(|a, b| {
-                a.status
-                    .as_ref()
-                    .unwrap()
-                    .session
-                    .as_ref()
-                    .unwrap()
-                    .start_time
-                    .as_ref()
-                    .unwrap()
-                    .cmp(
-                        b.status
-                            .as_ref()
-                            .unwrap()
-                            .session
-                            .as_ref()
-                            .unwrap()
-                            .start_time
-                            .as_ref()
-                            .unwrap(),
-                    )
-            });
+            sort(cfs_session_vec, CfsSessionSortOrder::StartTimeAsc);
 
             if let Some(limit_number) = limit_number_opt {
                 // Limiting the number of results to return to client
@@ -1479,48 +1460,26 @@ pub mod mesa {
             );
 
             // Checks either target.groups contains hsm_group_name or ansible.limit is a subset of
-            // hsm_group.members.ids
-            if !hsm_group_name_from_xnames_vec.is_empty() {
-                cfs_session_vec.retain(|cfs_session| {
-                    (keep_generic_sessions && is_session_image_generic(cfs_session))
-                        || cfs_session.get_target_hsm().is_some_and(|target_hsm_vec| {
-                            target_hsm_vec.iter().any(|target_hsm| {
-                                hsm_group_name_from_xnames_vec.contains(target_hsm)
-                            })
+            // hsm_group.members.ids. Xnames which do not belong to any HSM group can still be
+            // targeted through ansible.limit
+            cfs_session_vec.retain(|cfs_session| {
+                (keep_generic_sessions && is_session_image_generic(cfs_session))
+                    || cfs_session.get_target_hsm().is_some_and(|target_hsm_vec| {
+                        target_hsm_vec
+                            .iter()
+                            .any(|target_hsm| hsm_group_name_from_xnames_vec.contains(target_hsm))
+                    })
+                    || cfs_session
+                        .get_target_xname()
+                        .is_some_and(|target_xname_vec| {
+                            target_xname_vec
+                                .iter()
+                                .any(|target_xname| xname_vec.contains(&target_xname.as_str()))
                         })
-                        || cfs_session
-                            .get_target_xname()
-                            .is_some_and(|target_xname_vec| {
-                                target_xname_vec
-                                    .iter()
-                                    .any(|target_xname| xname_vec.contains(&target_xname.as_str()))
-                            })
-                });
-            }
+            });
 
             // Sort CFS sessions by start time order ASC
-            cfs_session_vec.sort_by(|a, b| {
-                a.status
-                    .as_ref()
-                    .unwrap()
-                    .session
-                    .as_ref()
-                    .unwrap()
-                    .start_time
-                    .as_ref()
-                    .unwrap()
-                    .cmp(
-                        b.status
-                            .as_ref()
-                            .unwrap()
-                            .session
-                            .as_ref()
-                            .unwrap()
-                            .start_time
-                            .as_ref()
-                            .unwrap(),
-                    )
-            });
+            sort(cfs_session_vec, CfsSessionSortOrder::StartTimeAsc);
 
             if let Some(limit_number) = limit_number_opt {
                 // Limiting the number of results to return to client
@@ -1530,6 +1489,45 @@ pub mod mesa {
             }
         }
 
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+        pub enum CfsSessionSortOrder {
+            /// Oldest first. CFS sessions not started yet go last
+            #[default]
+            StartTimeAsc,
+            /// Newest first. CFS sessions not started yet go first
+            StartTimeDesc,
+            NameAsc,
+            NameDesc,
+        }
+
+        /// Sort CFS sessions. CFS sessions without start time (pending) are considered newer than
+        /// any CFS session already started
+        pub fn sort(
+            cfs_session_vec: &mut [CfsSessionGetResponse],
+            sort_order: CfsSessionSortOrder,
+        ) {
+            let compare_start_time = |a: &CfsSessionGetResponse, b: &CfsSessionGetResponse| match (
+                a.get_start_time(),
+                b.get_start_time(),
+            ) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => a.name.cmp(&b.name),
+            };
+
+            match sort_order {
+                CfsSessionSortOrder::StartTimeAsc => cfs_session_vec.sort_by(compare_start_time),
+                CfsSessionSortOrder::StartTimeDesc => {
+                    cfs_session_vec.sort_by(|a, b| compare_start_time(b, a))
+                }
+                CfsSessionSortOrder::NameAsc => cfs_session_vec.sort_by(|a, b| a.name.cmp(&b.name)),
+                CfsSessionSortOrder::NameDesc => {
+                    cfs_session_vec.sort_by(|a, b| b.name.cmp(&a.name))
+                }
+            }
+        }
+
         /// Filter CFS sessions to the ones related to a CFS configuration
         pub fn filter_by_cofiguration(
             cfs_session_vec: &mut Vec<CfsSessionGetResponse>,
@@ -1657,6 +1655,10 @@ pub mod mesa {
         /// Check user has access to all groups in CFS session
         /// This function validates groups in CFS session against user auth token
         /// Returns the list of groups in the CFS session the user does not have access to
+        #[deprecated(
+            since = "0.43.18",
+            note = "Use `cfs::session::mesa::builder::CfsSessionBuilder` instead, it also checks the groups exist in HSM."
+        )]
         pub fn validate_groups(group_names: &[String], shasta_token: &str) -> Vec<String> {
             if common::jwt_ops::is_user_admin(shasta_token).unwrap() {
                 // Admins have access to all groups
@@ -1685,62 +1687,41 @@ pub mod mesa {
         }
     }
 
-    /// Wait a CFS session to finish
+    /// Wait a CFS session to finish. Returns how the CFS session finished, use
+    /// `watcher::CfsSessionWatcher` to set a timeout, cancellation or get status transitions
     pub async fn wait_cfs_session_to_finish(
         shasta_token: &str,
         shasta_base_url: &str,
         shasta_root_cert: &[u8],
         cfs_session_id: &str,
-    ) {
-        let mut i = 0;
-        let max = 3000; // Max ammount of attempts to check if CFS session has ended
-        loop {
-            let cfs_session_vec_rslt = cfs::session::mesa::http_client::get(
-                shasta_token,
-                shasta_base_url,
-                shasta_root_cert,
-                None,
-                None,
-                None,
-                Some(&cfs_session_id.to_string()),
-                None,
+    ) -> Result<watcher::CfsSessionWatchResult, crate::error::Error> {
+        log::info!("Waiting CFS session '{}' to finish", cfs_session_id);
+
+        let cfs_session_watch_result = watcher::CfsSessionWatcher::new(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            cfs_session_id,
+        )
+        .wait()
+        .await
+        .inspect_err(|error| {
+            log::error!(
+                "Could not wait CFS session '{}' to finish. Reason:\n{}",
+                cfs_session_id,
+                error
             )
-            .await;
+        })?;
 
-            let cfs_session = match cfs_session_vec_rslt {
-                Ok(cfs_session_vec) => cfs_session_vec.first().unwrap().clone(),
-                Err(e) => {
-                    eprintln!(
-                        "ERROR - Could not find CFS session '{}'.\nReason:\n{}\nExit",
-                        cfs_session_id, e
-                    );
-                    std::process::exit(1);
-                }
-            };
-
-            log::debug!("CFS session details:\n{:#?}", cfs_session);
-
-            let cfs_session_status = cfs_session.status.unwrap().session.unwrap().status.unwrap();
-
-            if cfs_session_status != "complete" && i < max {
-                print!("\x1B[2K"); // Clear current line
-                io::stdout().flush().unwrap();
-                println!(
-                "Waiting CFS session '{}' with status '{}'. Checking again in 2 secs. Attempt {} of {}.",
-                cfs_session_id, cfs_session_status, i, max
-            );
-                io::stdout().flush().unwrap();
+        let outcome = match &cfs_session_watch_result {
+            watcher::CfsSessionWatchResult::Succeeded(_) => "succeeded",
+            watcher::CfsSessionWatchResult::Failed(_) => "failed",
+            watcher::CfsSessionWatchResult::TimedOut(_) => "timed out",
+            watcher::CfsSessionWatchResult::Cancelled(_) => "cancelled",
+        };
 
-                tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+        log::info!("CFS session '{}' {}", cfs_session_id, outcome);
 
-                i += 1;
-            } else {
-                println!(
-                    "CFS session '{}' finished with status '{}'",
-                    cfs_session_id, cfs_session_status
-                );
-                break;
-            }
-        }
+        Ok(cfs_session_watch_result)
     }
 }
@@ -0,0 +1,552 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use regex::Regex;
+
+use crate::{cfs, common, common::ownership::OwnershipTags, error::Error, hsm, ims, node};
+
+use super::r#struct::v3::{CfsSessionPostRequest, Group, ImageMap, Target};
+
+/// CFS session names are used as k8s job names, CFS enforces this
+pub const CFS_SESSION_NAME_MAX_LENGTH: usize = 45;
+
+#[derive(thiserror::Error, Debug)]
+pub enum CfsSessionValidationError {
+    #[error("CFS session name '{name}' is not valid. {reason}")]
+    InvalidName { name: String, reason: String },
+    #[error("CFS configuration '{0}' not found")]
+    ConfigurationNotFound(String),
+    #[error("HSM group '{0}' not found")]
+    GroupNotFound(String),
+    #[error("User does not have access to HSM group '{0}'")]
+    GroupAccessDenied(String),
+    #[error("CFS session to build an image needs at least one target group")]
+    MissingGroups,
+    #[error("IMS image '{0}' not found")]
+    ImageNotFound(String),
+    #[error("xname '{0}' in ansible limit is not valid")]
+    InvalidXname(String),
+    #[error("Ansible verbosity '{0}' not valid, it must be between 0 and 4")]
+    InvalidAnsibleVerbosity(u8),
+    #[error("Could not validate CFS session. Reason: {0}")]
+    Csm(#[from] Error),
+}
+
+/// Target for CFS sessions configuring running nodes
+#[derive(Debug, Clone, Default)]
+pub struct Dynamic {
+    ansible_limit: Vec<String>,
+}
+
+/// Target for CFS sessions building an image
+#[derive(Debug, Clone)]
+pub struct Image {
+    base_image_id: String,
+    result_image_name: String,
+    group_vec: Vec<String>,
+}
+
+/// Typed builder for CFS v3 sessions. Dynamic sessions (configure running nodes) and image
+/// sessions (customize an IMS image) are different types so image only arguments (base image id,
+/// result image name and target groups) can't be missing or misused at compile time. Arguments
+/// are validated against CSM when building the CFS session request
+#[derive(Debug, Clone)]
+pub struct CfsSessionBuilder<T> {
+    name: String,
+    configuration_name: String,
+    configuration_limit: Option<String>,
+    ansible_config: Option<String>,
+    ansible_verbosity: Option<u8>,
+    ansible_passthrough: Option<String>,
+    tags: Option<HashMap<String, String>>,
+    debug_on_failure: bool,
+    target: T,
+}
+
+impl<T> CfsSessionBuilder<T> {
+    fn new_with_target(name: &str, configuration_name: &str, target: T) -> Self {
+        Self {
+            name: name.to_string(),
+            configuration_name: configuration_name.to_string(),
+            configuration_limit: None,
+            ansible_config: None,
+            ansible_verbosity: None,
+            ansible_passthrough: None,
+            tags: None,
+            debug_on_failure: false,
+            target,
+        }
+    }
+
+    pub fn configuration_limit(mut self, configuration_limit: &str) -> Self {
+        self.configuration_limit = Some(configuration_limit.to_string());
+        self
+    }
+
+    pub fn ansible_config(mut self, ansible_config: &str) -> Self {
+        self.ansible_config = Some(ansible_config.to_string());
+        self
+    }
+
+    pub fn ansible_verbosity(mut self, ansible_verbosity: u8) -> Self {
+        self.ansible_verbosity = Some(ansible_verbosity);
+        self
+    }
+
+    pub fn ansible_passthrough(mut self, ansible_passthrough: &str) -> Self {
+        self.ansible_passthrough = Some(ansible_passthrough.to_string());
+        self
+    }
+
+    pub fn tag(mut self, key: &str, value: &str) -> Self {
+        self.tags
+            .get_or_insert_with(HashMap::new)
+            .insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Adds the standard mesa ownership tags (see `common::ownership`)
+    pub fn ownership_tags(mut self, ownership_tags: &OwnershipTags) -> Self {
+        ownership_tags.merge_into(self.tags.get_or_insert_with(HashMap::new));
+        self
+    }
+
+    pub fn debug_on_failure(mut self, debug_on_failure: bool) -> Self {
+        self.debug_on_failure = debug_on_failure;
+        self
+    }
+
+    /// Validations which do not need to call CSM
+    fn validate_common(&self) -> Vec<CfsSessionValidationError> {
+        let mut error_vec = Vec::new();
+
+        if let Err(error) = validate_session_name(&self.name) {
+            error_vec.push(error);
+        }
+
+        if let Some(ansible_verbosity) = self.ansible_verbosity {
+            if ansible_verbosity > super::model::MAX_ANSIBLE_VERBOSITY {
+                error_vec.push(CfsSessionValidationError::InvalidAnsibleVerbosity(
+                    ansible_verbosity,
+                ));
+            }
+        }
+
+        error_vec
+    }
+
+    fn to_request(&self, target: Target, ansible_limit: Option<String>) -> CfsSessionPostRequest {
+        CfsSessionPostRequest {
+            name: self.name.clone(),
+            configuration_name: self.configuration_name.clone(),
+            configuration_limit: self.configuration_limit.clone(),
+            ansible_limit,
+            ansible_config: self.ansible_config.clone(),
+            ansible_verbosity: self.ansible_verbosity,
+            ansible_passthrough: self.ansible_passthrough.clone(),
+            target,
+            tags: self.tags.clone(),
+            debug_on_failure: self.debug_on_failure,
+        }
+    }
+}
+
+impl CfsSessionBuilder<Dynamic> {
+    /// CFS session to configure running nodes
+    pub fn dynamic(name: &str, configuration_name: &str) -> Self {
+        Self::new_with_target(name, configuration_name, Dynamic::default())
+    }
+
+    /// Limit the CFS session to a list of nodes
+    pub fn ansible_limit(mut self, xname_vec: &[String]) -> Self {
+        self.target.ansible_limit = xname_vec.to_vec();
+        self
+    }
+
+    /// Returns the CFS session request without calling CSM to validate it
+    pub fn build_unchecked(&self) -> Result<CfsSessionPostRequest, Vec<CfsSessionValidationError>> {
+        let mut error_vec = self.validate_common();
+
+        for xname in &self.target.ansible_limit {
+            if !node::utils::validate_xname_format(xname) {
+                error_vec.push(CfsSessionValidationError::InvalidXname(xname.clone()));
+            }
+        }
+
+        if !error_vec.is_empty() {
+            return Err(error_vec);
+        }
+
+        let ansible_limit = if self.target.ansible_limit.is_empty() {
+            None
+        } else {
+            Some(self.target.ansible_limit.join(","))
+        };
+
+        Ok(self.to_request(
+            Target {
+                definition: Some("dynamic".to_string()),
+                groups: None,
+                image_map: Some(Vec::new()),
+            },
+            ansible_limit,
+        ))
+    }
+
+    /// Returns the CFS session request after checking the CFS configuration exists. All problems
+    /// found are returned at once
+    pub async fn build(
+        &self,
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+    ) -> Result<CfsSessionPostRequest, Vec<CfsSessionValidationError>> {
+        let (cfs_session_opt, mut error_vec) = match self.build_unchecked() {
+            Ok(cfs_session) => (Some(cfs_session), Vec::new()),
+            Err(error_vec) => (None, error_vec),
+        };
+
+        if let Err(error) = validate_configuration(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &self.configuration_name,
+        )
+        .await
+        {
+            error_vec.push(error);
+        }
+
+        match cfs_session_opt {
+            Some(cfs_session) if error_vec.is_empty() => Ok(cfs_session),
+            _ => Err(error_vec),
+        }
+    }
+}
+
+impl CfsSessionBuilder<Image> {
+    /// CFS session to build a new image named `result_image_name` from IMS image
+    /// `base_image_id`. Target groups are added with `group`
+    pub fn image(
+        name: &str,
+        configuration_name: &str,
+        base_image_id: &str,
+        result_image_name: &str,
+    ) -> Self {
+        Self::new_with_target(
+            name,
+            configuration_name,
+            Image {
+                base_image_id: base_image_id.to_string(),
+                result_image_name: result_image_name.to_string(),
+                group_vec: Vec::new(),
+            },
+        )
+    }
+
+    /// Add a HSM group as ansible target group
+    pub fn group(mut self, group_name: &str) -> Self {
+        self.target.group_vec.push(group_name.to_string());
+        self
+    }
+
+    /// Returns the CFS session request without calling CSM to validate it
+    pub fn build_unchecked(&self) -> Result<CfsSessionPostRequest, Vec<CfsSessionValidationError>> {
+        let mut error_vec = self.validate_common();
+
+        if self.target.group_vec.is_empty() {
+            error_vec.push(CfsSessionValidationError::MissingGroups);
+        }
+
+        if !error_vec.is_empty() {
+            return Err(error_vec);
+        }
+
+        let target_group_vec: Vec<Group> = self
+            .target
+            .group_vec
+            .iter()
+            .map(|group_name| Group {
+                name: group_name.clone(),
+                members: vec![self.target.base_image_id.clone()],
+            })
+            .collect();
+
+        Ok(self.to_request(
+            Target {
+                definition: Some("image".to_string()),
+                groups: Some(target_group_vec),
+                image_map: Some(vec![ImageMap::new(
+                    &self.target.base_image_id,
+                    &self.target.result_image_name,
+                )]),
+            },
+            None,
+        ))
+    }
+
+    /// Returns the CFS session request after checking the CFS configuration, target HSM groups
+    /// and base image exist and the user has access to the target HSM groups. All problems found
+    /// are returned at once
+    pub async fn build(
+        &self,
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+    ) -> Result<CfsSessionPostRequest, Vec<CfsSessionValidationError>> {
+        let (cfs_session_opt, mut error_vec) = match self.build_unchecked() {
+            Ok(cfs_session) => (Some(cfs_session), Vec::new()),
+            Err(error_vec) => (None, error_vec),
+        };
+
+        if let Err(error) = validate_configuration(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &self.configuration_name,
+        )
+        .await
+        {
+            error_vec.push(error);
+        }
+
+        error_vec.extend(
+            validate_hsm_groups(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                &self.target.group_vec,
+            )
+            .await,
+        );
+
+        if let Err(error) = validate_image(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &self.target.base_image_id,
+        )
+        .await
+        {
+            error_vec.push(error);
+        }
+
+        match cfs_session_opt {
+            Some(cfs_session) if error_vec.is_empty() => Ok(cfs_session),
+            _ => Err(error_vec),
+        }
+    }
+}
+
+/// Name regex, compiled once
+fn name_regex() -> &'static Regex {
+    static NAME_REGEX: OnceLock<Regex> = OnceLock::new();
+    NAME_REGEX.get_or_init(|| Regex::new(r"^[a-z0-9]([-a-z0-9]*[a-z0-9])?$").unwrap())
+}
+
+/// Checks CFS session name is a valid k8s name: lowercase alphanumeric characters or '-', must
+/// start and end with an alphanumeric character and no longer than 45 characters
+pub fn validate_session_name(name: &str) -> Result<(), CfsSessionValidationError> {
+    let invalid_name = |reason: String| CfsSessionValidationError::InvalidName {
+        name: name.to_string(),
+        reason,
+    };
+
+    if name.is_empty() {
+        return Err(invalid_name("Name can't be empty".to_string()));
+    }
+
+    if name.len() > CFS_SESSION_NAME_MAX_LENGTH {
+        return Err(invalid_name(format!(
+            "Name must be {} characters or less",
+            CFS_SESSION_NAME_MAX_LENGTH
+        )));
+    }
+
+    if !name_regex().is_match(name) {
+        return Err(invalid_name(
+            "Name must contain only lowercase alphanumeric characters or '-' and must start and end with an alphanumeric character".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+async fn validate_configuration(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    configuration_name: &str,
+) -> Result<(), CfsSessionValidationError> {
+    match cfs::configuration::mesa::http_client::get(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        Some(configuration_name),
+    )
+    .await
+    {
+        Ok(configuration_vec) if !configuration_vec.is_empty() => Ok(()),
+        Ok(_) => Err(CfsSessionValidationError::ConfigurationNotFound(
+            configuration_name.to_string(),
+        )),
+        Err(error) if error.is_not_found() => Err(
+            CfsSessionValidationError::ConfigurationNotFound(configuration_name.to_string()),
+        ),
+        Err(error) => Err(CfsSessionValidationError::Csm(error)),
+    }
+}
+
+/// Checks HSM groups exist and the user has access to them
+async fn validate_hsm_groups(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    group_name_vec: &[String],
+) -> Vec<CfsSessionValidationError> {
+    let mut error_vec = Vec::new();
+
+    for group_name in group_name_vec {
+        match hsm::group::http_client::get(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            Some(group_name),
+        )
+        .await
+        {
+            Ok(hsm_group_vec) if !hsm_group_vec.is_empty() => {}
+            Ok(_) => error_vec.push(CfsSessionValidationError::GroupNotFound(
+                group_name.to_string(),
+            )),
+            Err(error) if error.is_not_found() => error_vec.push(
+                CfsSessionValidationError::GroupNotFound(group_name.to_string()),
+            ),
+            Err(error) => error_vec.push(CfsSessionValidationError::Csm(error)),
+        }
+    }
+
+    let is_user_admin = match common::jwt_ops::is_user_admin(shasta_token) {
+        Ok(is_user_admin) => is_user_admin,
+        Err(error) => {
+            error_vec.push(CfsSessionValidationError::Csm(Error::Message(
+                error.to_string(),
+            )));
+            return error_vec;
+        }
+    };
+
+    // Admins have access to all groups
+    if !is_user_admin {
+        let group_in_user_auth_token_vec =
+            match common::jwt_ops::get_roles_without_system_wide(shasta_token) {
+                Ok(group_vec) => group_vec,
+                Err(error) => {
+                    error_vec.push(CfsSessionValidationError::Csm(Error::Message(
+                        error.to_string(),
+                    )));
+                    return error_vec;
+                }
+            };
+
+        // Roles, subroles and system wide HSM groups are not in the user auth token
+        //TODO: Get rid of this by making sure CSM admins don't create HSM groups for system
+        //wide operations instead of using roles
+        let group_to_check_vec = hsm::group::hacks::filter_system_hsm_group_names(
+            hsm::group::hacks::filter_roles_and_subroles(group_name_vec.to_vec()),
+        );
+
+        error_vec.extend(
+            group_to_check_vec
+                .into_iter()
+                .filter(|group| !group_in_user_auth_token_vec.contains(group))
+                .map(CfsSessionValidationError::GroupAccessDenied),
+        );
+    }
+
+    error_vec
+}
+
+async fn validate_image(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    image_id: &str,
+) -> Result<(), CfsSessionValidationError> {
+    match ims::image::shasta::http_client::get_raw(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        Some(image_id),
+    )
+    .await
+    {
+        Ok(_) => Ok(()),
+        Err(error) if error.status() == Some(reqwest::StatusCode::NOT_FOUND) => Err(
+            CfsSessionValidationError::ImageNotFound(image_id.to_string()),
+        ),
+        Err(error) => Err(CfsSessionValidationError::Csm(Error::NetError(error))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_name_validation() {
+        assert!(validate_session_name("batcher-a1b2c3").is_ok());
+        assert!(validate_session_name("").is_err());
+        assert!(validate_session_name("Upper-case").is_err());
+        assert!(validate_session_name("-starts-with-dash").is_err());
+        assert!(validate_session_name("ends-with-dash-").is_err());
+        assert!(validate_session_name("under_score").is_err());
+        assert!(validate_session_name(&"a".repeat(CFS_SESSION_NAME_MAX_LENGTH)).is_ok());
+        assert!(validate_session_name(&"a".repeat(CFS_SESSION_NAME_MAX_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn test_dynamic_session_request() {
+        let cfs_session = CfsSessionBuilder::dynamic("my-session", "my-configuration")
+            .ansible_limit(&["x1000c1s7b0n0".to_string(), "x1000c1s7b0n1".to_string()])
+            .ansible_verbosity(2)
+            .build_unchecked()
+            .unwrap();
+
+        assert_eq!(cfs_session.target.definition.as_deref(), Some("dynamic"));
+        assert_eq!(
+            cfs_session.ansible_limit.as_deref(),
+            Some("x1000c1s7b0n0,x1000c1s7b0n1")
+        );
+
+        let error_vec = CfsSessionBuilder::dynamic("My_Session", "my-configuration")
+            .ansible_limit(&["nid000001".to_string()])
+            .ansible_verbosity(9)
+            .build_unchecked()
+            .unwrap_err();
+
+        assert_eq!(error_vec.len(), 3);
+    }
+
+    #[test]
+    fn test_image_session_request() {
+        let cfs_session =
+            CfsSessionBuilder::image("my-session", "my-configuration", "base-id", "new-image")
+                .group("compute")
+                .build_unchecked()
+                .unwrap();
+
+        assert_eq!(cfs_session.target.definition.as_deref(), Some("image"));
+        assert_eq!(
+            cfs_session.target.groups.as_ref().unwrap()[0].members,
+            vec!["base-id"]
+        );
+        assert!(cfs_session.ansible_limit.is_none());
+
+        assert!(matches!(
+            CfsSessionBuilder::image("my-session", "my-configuration", "base-id", "new-image")
+                .build_unchecked()
+                .unwrap_err()[..],
+            [CfsSessionValidationError::MissingGroups]
+        ));
+    }
+}
@@ -0,0 +1,760 @@
+//! CFS session model independent of the CFS API version. Convert from/to the v2 and v3 wire
+//! formats with `From`/`TryFrom`, or let the functions in this module pick the CFS API version
+//! the site supports (see `common::site_profile`)
+
+use std::collections::HashMap;
+
+use crate::{cfs, common::site_profile, error::Error};
+use serde::{Deserialize, Serialize};
+
+use super::r#struct::{v2, v3};
+
+/// Highest ansible verbosity CFS accepts
+pub const MAX_ANSIBLE_VERBOSITY: u8 = 4;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum CfsApiVersion {
+    V2,
+    V3,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Group {
+    pub name: String,
+    pub members: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ImageMap {
+    pub source_id: String,
+    pub result_name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum CfsSessionTarget {
+    /// Configure running nodes
+    Dynamic,
+    /// Build images. `image_map_vec` is only supported by CFS v3
+    Image {
+        group_vec: Vec<Group>,
+        image_map_vec: Vec<ImageMap>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Artifact {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#type: Option<String>,
+}
+
+/// Status of a CFS session already created
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct CfsSessionRunStatus {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job: Option<String>,
+    /// Only in CFS v3
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ims_job: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_time: Option<String>,
+    /// pending, running or complete
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub succeeded: Option<String>,
+    pub artifact_vec: Vec<Artifact>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CfsSession {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub configuration_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub configuration_limit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ansible_config: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ansible_limit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ansible_verbosity: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ansible_passthrough: Option<String>,
+    pub target: CfsSessionTarget,
+    pub tags: HashMap<String, String>,
+    /// Only in CFS v3
+    pub debug_on_failure: bool,
+    /// None if the CFS session has not been created yet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<CfsSessionRunStatus>,
+}
+
+impl CfsSession {
+    pub fn get_start_time(&self) -> Option<String> {
+        self.status
+            .as_ref()
+            .and_then(|status| status.start_time.clone())
+    }
+
+    pub fn get_result_id_vec(&self) -> Vec<String> {
+        self.status
+            .iter()
+            .flat_map(|status| status.artifact_vec.iter())
+            .filter_map(|artifact| artifact.result_id.clone())
+            .collect()
+    }
+
+    pub fn get_first_result_id(&self) -> Option<String> {
+        self.get_result_id_vec().first().cloned()
+    }
+
+    /// Returns list of HSM groups targeted
+    pub fn get_target_hsm(&self) -> Option<Vec<String>> {
+        match &self.target {
+            CfsSessionTarget::Image { group_vec, .. } => {
+                Some(group_vec.iter().map(|group| group.name.clone()).collect())
+            }
+            CfsSessionTarget::Dynamic => None,
+        }
+    }
+
+    /// Returns list of xnames targeted
+    pub fn get_target_xname(&self) -> Option<Vec<String>> {
+        self.ansible_limit.as_ref().map(|limit| {
+            limit
+                .split(',')
+                .map(|xname| xname.trim().to_string())
+                .collect()
+        })
+    }
+
+    pub fn is_target_def_image(&self) -> bool {
+        matches!(self.target, CfsSessionTarget::Image { .. })
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.status
+            .as_ref()
+            .is_some_and(|status| status.status.as_deref() == Some("complete"))
+    }
+
+    /// Returns 'true' if CFS session finished successfully. Unlike the v2/v3 structs, it does not
+    /// panic if the CFS session has no status yet
+    pub fn is_success(&self) -> bool {
+        self.status
+            .as_ref()
+            .is_some_and(|status| status.succeeded.as_deref() == Some("true"))
+    }
+}
+
+/// CFS sends the ansible verbosity as an integer, values out of the range CFS accepts (0 to
+/// `MAX_ANSIBLE_VERBOSITY`) are clamped instead of truncated
+fn verbosity_from_wire(verbosity: u64) -> u8 {
+    if verbosity > u64::from(MAX_ANSIBLE_VERBOSITY) {
+        log::warn!(
+            "Ansible verbosity '{}' out of range, using '{}'",
+            verbosity,
+            MAX_ANSIBLE_VERBOSITY
+        );
+        MAX_ANSIBLE_VERBOSITY
+    } else {
+        verbosity as u8
+    }
+}
+
+/// CFS sends the target definition as a string, anything which is not 'image' (eg 'dynamic',
+/// 'spec', 'repo') is treated as a session to configure running nodes
+fn target_from_wire(
+    definition_opt: Option<&str>,
+    group_vec: Vec<Group>,
+    image_map_vec: Vec<ImageMap>,
+) -> CfsSessionTarget {
+    if definition_opt == Some("image") {
+        CfsSessionTarget::Image {
+            group_vec,
+            image_map_vec,
+        }
+    } else {
+        CfsSessionTarget::Dynamic
+    }
+}
+
+impl From<v2::Group> for Group {
+    fn from(group: v2::Group) -> Self {
+        Self {
+            name: group.name,
+            members: group.members,
+        }
+    }
+}
+
+impl From<v3::Group> for Group {
+    fn from(group: v3::Group) -> Self {
+        Self {
+            name: group.name,
+            members: group.members,
+        }
+    }
+}
+
+impl From<Group> for v2::Group {
+    fn from(group: Group) -> Self {
+        Self {
+            name: group.name,
+            members: group.members,
+        }
+    }
+}
+
+impl From<Group> for v3::Group {
+    fn from(group: Group) -> Self {
+        Self {
+            name: group.name,
+            members: group.members,
+        }
+    }
+}
+
+impl From<v3::ImageMap> for ImageMap {
+    fn from(image_map: v3::ImageMap) -> Self {
+        Self {
+            source_id: image_map.source_id,
+            result_name: image_map.result_name,
+        }
+    }
+}
+
+impl From<ImageMap> for v3::ImageMap {
+    fn from(image_map: ImageMap) -> Self {
+        v3::ImageMap::new(&image_map.source_id, &image_map.result_name)
+    }
+}
+
+impl From<v2::Artifact> for Artifact {
+    fn from(artifact: v2::Artifact) -> Self {
+        Self {
+            image_id: artifact.image_id,
+            result_id: artifact.result_id,
+            r#type: artifact.r#type,
+        }
+    }
+}
+
+impl From<v3::Artifact> for Artifact {
+    fn from(artifact: v3::Artifact) -> Self {
+        Self {
+            image_id: artifact.image_id,
+            result_id: artifact.result_id,
+            r#type: artifact.r#type,
+        }
+    }
+}
+
+impl From<v2::Status> for CfsSessionRunStatus {
+    fn from(status: v2::Status) -> Self {
+        let session = status.session;
+
+        Self {
+            job: session.as_ref().and_then(|session| session.job.clone()),
+            ims_job: None,
+            start_time: session
+                .as_ref()
+                .and_then(|session| session.start_time.clone()),
+            completion_time: session
+                .as_ref()
+                .and_then(|session| session.completion_time.clone()),
+            status: session.as_ref().and_then(|session| session.status.clone()),
+            succeeded: session.and_then(|session| session.succeeded),
+            artifact_vec: status
+                .artifacts
+                .unwrap_or_default()
+                .into_iter()
+                .map(Artifact::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<v3::Status> for CfsSessionRunStatus {
+    fn from(status: v3::Status) -> Self {
+        let session = status.session;
+
+        Self {
+            job: session.as_ref().and_then(|session| session.job.clone()),
+            ims_job: session.as_ref().and_then(|session| session.ims_job.clone()),
+            start_time: session
+                .as_ref()
+                .and_then(|session| session.start_time.clone()),
+            completion_time: session
+                .as_ref()
+                .and_then(|session| session.completion_time.clone()),
+            status: session.as_ref().and_then(|session| session.status.clone()),
+            succeeded: session.and_then(|session| session.succeeded),
+            artifact_vec: status
+                .artifacts
+                .unwrap_or_default()
+                .into_iter()
+                .map(Artifact::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<v2::CfsSessionGetResponse> for CfsSession {
+    fn from(cfs_session: v2::CfsSessionGetResponse) -> Self {
+        let target = cfs_session.target.unwrap_or_default();
+        let ansible_opt = cfs_session.ansible;
+
+        Self {
+            name: cfs_session.name.unwrap_or_default(),
+            configuration_name: cfs_session
+                .configuration
+                .as_ref()
+                .and_then(|configuration| configuration.name.clone()),
+            configuration_limit: cfs_session
+                .configuration
+                .and_then(|configuration| configuration.limit),
+            ansible_config: ansible_opt
+                .as_ref()
+                .and_then(|ansible| ansible.config.clone()),
+            ansible_limit: ansible_opt
+                .as_ref()
+                .and_then(|ansible| ansible.limit.clone()),
+            ansible_verbosity: ansible_opt
+                .as_ref()
+                .and_then(|ansible| ansible.verbosity)
+                .map(verbosity_from_wire),
+            ansible_passthrough: ansible_opt.and_then(|ansible| ansible.passthrough),
+            target: target_from_wire(
+                target.definition.as_deref(),
+                target
+                    .groups
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(Group::from)
+                    .collect(),
+                Vec::new(),
+            ),
+            tags: cfs_session.tags.unwrap_or_default(),
+            debug_on_failure: false,
+            status: cfs_session.status.map(CfsSessionRunStatus::from),
+        }
+    }
+}
+
+impl From<v3::CfsSessionGetResponse> for CfsSession {
+    fn from(cfs_session: v3::CfsSessionGetResponse) -> Self {
+        let target = cfs_session.target.unwrap_or_default();
+        let ansible_opt = cfs_session.ansible;
+
+        Self {
+            name: cfs_session.name.unwrap_or_default(),
+            configuration_name: cfs_session
+                .configuration
+                .as_ref()
+                .and_then(|configuration| configuration.name.clone()),
+            configuration_limit: cfs_session
+                .configuration
+                .and_then(|configuration| configuration.limit),
+            ansible_config: ansible_opt
+                .as_ref()
+                .and_then(|ansible| ansible.config.clone()),
+            ansible_limit: ansible_opt
+                .as_ref()
+                .and_then(|ansible| ansible.limit.clone()),
+            ansible_verbosity: ansible_opt
+                .as_ref()
+                .and_then(|ansible| ansible.verbosity)
+                .map(verbosity_from_wire),
+            ansible_passthrough: ansible_opt.and_then(|ansible| ansible.passthrough),
+            target: target_from_wire(
+                target.definition.as_deref(),
+                target
+                    .groups
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(Group::from)
+                    .collect(),
+                target
+                    .image_map
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(ImageMap::from)
+                    .collect(),
+            ),
+            tags: cfs_session.tags.unwrap_or_default(),
+            debug_on_failure: cfs_session.debug_on_failure,
+            status: cfs_session.status.map(CfsSessionRunStatus::from),
+        }
+    }
+}
+
+impl From<v2::CfsSessionPostRequest> for CfsSession {
+    fn from(cfs_session: v2::CfsSessionPostRequest) -> Self {
+        Self {
+            name: cfs_session.name,
+            configuration_name: Some(cfs_session.configuration_name),
+            configuration_limit: cfs_session.configuration_limit,
+            ansible_config: cfs_session.ansible_config,
+            ansible_limit: cfs_session.ansible_limit,
+            ansible_verbosity: cfs_session.ansible_verbosity,
+            ansible_passthrough: cfs_session.ansible_passthrough,
+            target: target_from_wire(
+                cfs_session.target.definition.as_deref(),
+                cfs_session
+                    .target
+                    .groups
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(Group::from)
+                    .collect(),
+                Vec::new(),
+            ),
+            tags: cfs_session.tags.unwrap_or_default(),
+            debug_on_failure: false,
+            status: None,
+        }
+    }
+}
+
+impl From<v3::CfsSessionPostRequest> for CfsSession {
+    fn from(cfs_session: v3::CfsSessionPostRequest) -> Self {
+        Self {
+            name: cfs_session.name,
+            configuration_name: Some(cfs_session.configuration_name),
+            configuration_limit: cfs_session.configuration_limit,
+            ansible_config: cfs_session.ansible_config,
+            ansible_limit: cfs_session.ansible_limit,
+            ansible_verbosity: cfs_session.ansible_verbosity,
+            ansible_passthrough: cfs_session.ansible_passthrough,
+            target: target_from_wire(
+                cfs_session.target.definition.as_deref(),
+                cfs_session
+                    .target
+                    .groups
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(Group::from)
+                    .collect(),
+                cfs_session
+                    .target
+                    .image_map
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(ImageMap::from)
+                    .collect(),
+            ),
+            tags: cfs_session.tags.unwrap_or_default(),
+            debug_on_failure: cfs_session.debug_on_failure,
+            status: None,
+        }
+    }
+}
+
+impl TryFrom<CfsSession> for v3::CfsSessionPostRequest {
+    type Error = Error;
+
+    fn try_from(cfs_session: CfsSession) -> Result<Self, Self::Error> {
+        let configuration_name = cfs_session.configuration_name.ok_or_else(|| {
+            Error::Message(format!(
+                "CFS session '{}' has no configuration",
+                cfs_session.name
+            ))
+        })?;
+
+        let target = match cfs_session.target {
+            CfsSessionTarget::Dynamic => v3::Target {
+                definition: Some("dynamic".to_string()),
+                groups: None,
+                image_map: Some(Vec::new()),
+            },
+            CfsSessionTarget::Image {
+                group_vec,
+                image_map_vec,
+            } => v3::Target {
+                definition: Some("image".to_string()),
+                groups: Some(group_vec.into_iter().map(v3::Group::from).collect()),
+                image_map: Some(image_map_vec.into_iter().map(v3::ImageMap::from).collect()),
+            },
+        };
+
+        Ok(Self {
+            name: cfs_session.name,
+            configuration_name,
+            configuration_limit: cfs_session.configuration_limit,
+            ansible_limit: cfs_session.ansible_limit,
+            ansible_config: cfs_session.ansible_config,
+            ansible_verbosity: cfs_session.ansible_verbosity,
+            ansible_passthrough: cfs_session.ansible_passthrough,
+            target,
+            tags: Some(cfs_session.tags).filter(|tags| !tags.is_empty()),
+            debug_on_failure: cfs_session.debug_on_failure,
+        })
+    }
+}
+
+/// Fails if the CFS session uses features CFS v2 does not support (image map or debug on
+/// failure) since they would be silently dropped otherwise
+impl TryFrom<CfsSession> for v2::CfsSessionPostRequest {
+    type Error = Error;
+
+    fn try_from(cfs_session: CfsSession) -> Result<Self, Self::Error> {
+        let configuration_name = cfs_session.configuration_name.ok_or_else(|| {
+            Error::Message(format!(
+                "CFS session '{}' has no configuration",
+                cfs_session.name
+            ))
+        })?;
+
+        if cfs_session.debug_on_failure {
+            return Err(Error::Message(format!(
+                "CFS session '{}' uses 'debug on failure' which is not supported by CFS v2",
+                cfs_session.name
+            )));
+        }
+
+        let target = match cfs_session.target {
+            CfsSessionTarget::Dynamic => v2::Target {
+                definition: Some("dynamic".to_string()),
+                groups: None,
+            },
+            CfsSessionTarget::Image {
+                group_vec,
+                image_map_vec,
+            } => {
+                if !image_map_vec.is_empty() {
+                    return Err(Error::Message(format!(
+                        "CFS session '{}' sets result image names which is not supported by CFS v2",
+                        cfs_session.name
+                    )));
+                }
+
+                v2::Target {
+                    definition: Some("image".to_string()),
+                    groups: Some(group_vec.into_iter().map(v2::Group::from).collect()),
+                }
+            }
+        };
+
+        Ok(Self {
+            name: cfs_session.name,
+            configuration_name,
+            configuration_limit: cfs_session.configuration_limit,
+            ansible_limit: cfs_session.ansible_limit,
+            ansible_config: cfs_session.ansible_config,
+            ansible_verbosity: cfs_session.ansible_verbosity,
+            ansible_passthrough: cfs_session.ansible_passthrough,
+            target,
+            tags: Some(cfs_session.tags).filter(|tags| !tags.is_empty()),
+        })
+    }
+}
+
+/// Get CFS sessions using the CFS API version supported by the site
+pub async fn get(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    session_name_opt: Option<&String>,
+) -> Result<Vec<CfsSession>, Error> {
+    match site_profile::get(shasta_token, shasta_base_url, shasta_root_cert)
+        .await?
+        .get_cfs_api_version()?
+    {
+        CfsApiVersion::V3 => cfs::session::shasta::http_client::v3::get(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            session_name_opt,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await
+        .map(|cfs_session_vec| cfs_session_vec.into_iter().map(CfsSession::from).collect()),
+        CfsApiVersion::V2 => cfs::session::shasta::http_client::v2::get(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            session_name_opt,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await
+        .map(|cfs_session_vec| cfs_session_vec.into_iter().map(CfsSession::from).collect()),
+    }
+}
+
+/// Create a CFS session using the CFS API version supported by the site
+pub async fn post(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    cfs_session: CfsSession,
+) -> Result<CfsSession, Error> {
+    match site_profile::get(shasta_token, shasta_base_url, shasta_root_cert)
+        .await?
+        .get_cfs_api_version()?
+    {
+        CfsApiVersion::V3 => cfs::session::shasta::http_client::v3::post(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &v3::CfsSessionPostRequest::try_from(cfs_session)?,
+        )
+        .await
+        .map(CfsSession::from),
+        CfsApiVersion::V2 => cfs::session::shasta::http_client::v2::post(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &v2::CfsSessionPostRequest::try_from(cfs_session)?,
+        )
+        .await
+        .map(CfsSession::from),
+    }
+}
+
+/// Delete a CFS session using the CFS API version supported by the site
+pub async fn delete(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    session_name: &str,
+) -> Result<(), Error> {
+    match site_profile::get(shasta_token, shasta_base_url, shasta_root_cert)
+        .await?
+        .get_cfs_api_version()?
+    {
+        CfsApiVersion::V3 => {
+            cfs::session::shasta::http_client::v3::delete(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                session_name,
+            )
+            .await
+        }
+        CfsApiVersion::V2 => {
+            cfs::session::shasta::http_client::v2::delete(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                session_name,
+            )
+            .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image_session() -> CfsSession {
+        CfsSession {
+            name: "build-image".to_string(),
+            configuration_name: Some("zinal-config".to_string()),
+            configuration_limit: None,
+            ansible_config: None,
+            ansible_limit: None,
+            ansible_verbosity: Some(2),
+            ansible_passthrough: None,
+            target: CfsSessionTarget::Image {
+                group_vec: vec![Group {
+                    name: "zinal".to_string(),
+                    members: vec!["base-image-id".to_string()],
+                }],
+                image_map_vec: vec![ImageMap {
+                    source_id: "base-image-id".to_string(),
+                    result_name: "zinal-image".to_string(),
+                }],
+            },
+            tags: HashMap::from([("mesa.owner".to_string(), "alice".to_string())]),
+            debug_on_failure: false,
+            status: None,
+        }
+    }
+
+    #[test]
+    fn test_v3_post_request_round_trip() {
+        let cfs_session = image_session();
+
+        let v3_cfs_session = v3::CfsSessionPostRequest::try_from(cfs_session.clone()).unwrap();
+
+        assert_eq!(CfsSession::from(v3_cfs_session), cfs_session);
+    }
+
+    #[test]
+    fn test_v2_post_request_rejects_v3_only_features() {
+        let cfs_session = image_session();
+
+        assert!(v2::CfsSessionPostRequest::try_from(cfs_session.clone()).is_err());
+
+        let cfs_session = CfsSession {
+            target: CfsSessionTarget::Image {
+                group_vec: vec![Group {
+                    name: "zinal".to_string(),
+                    members: vec!["base-image-id".to_string()],
+                }],
+                image_map_vec: Vec::new(),
+            },
+            ..cfs_session
+        };
+
+        let v2_cfs_session = v2::CfsSessionPostRequest::try_from(cfs_session.clone()).unwrap();
+
+        assert_eq!(CfsSession::from(v2_cfs_session), cfs_session);
+    }
+
+    #[test]
+    fn test_from_v2_get_response() {
+        let cfs_session: v2::CfsSessionGetResponse = serde_json::from_value(serde_json::json!({
+            "name": "batcher-1",
+            "configuration": { "name": "zinal-config" },
+            "ansible": { "limit": "x1000c1s7b0n0, x1000c1s7b0n1" },
+            "target": { "definition": "dynamic", "groups": [] },
+            "status": {
+                "artifacts": [],
+                "session": { "status": "complete", "succeeded": "true", "startTime": "2024-01-01T00:00:00" }
+            }
+        }))
+        .unwrap();
+
+        let cfs_session = CfsSession::from(cfs_session);
+
+        assert_eq!(cfs_session.target, CfsSessionTarget::Dynamic);
+        assert_eq!(
+            cfs_session.get_target_xname(),
+            Some(vec![
+                "x1000c1s7b0n0".to_string(),
+                "x1000c1s7b0n1".to_string()
+            ])
+        );
+        assert!(cfs_session.is_complete());
+        assert!(cfs_session.is_success());
+    }
+
+    #[test]
+    fn test_verbosity_from_wire() {
+        assert_eq!(verbosity_from_wire(2), 2);
+        assert_eq!(verbosity_from_wire(4), 4);
+        assert_eq!(verbosity_from_wire(5), MAX_ANSIBLE_VERBOSITY);
+        assert_eq!(verbosity_from_wire(256), MAX_ANSIBLE_VERBOSITY);
+    }
+}
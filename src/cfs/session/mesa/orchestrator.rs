@@ -0,0 +1,607 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    future::Future,
+    time::Duration,
+};
+
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+use crate::{common::ownership::OwnershipTags, error::Error, ims};
+
+use super::{
+    model::{self, CfsSession, CfsSessionTarget},
+    watcher::{CfsSessionWatchResult, CfsSessionWatcher},
+};
+
+/// A CFS session in the orchestration graph
+#[derive(Debug, Clone)]
+pub struct SessionNode {
+    id: String,
+    cfs_session: CfsSession,
+    depends_on: Vec<String>,
+    base_image_from_opt: Option<String>,
+}
+
+impl SessionNode {
+    /// `id` identifies the CFS session in the graph, the CFS session name is taken from the
+    /// request. `cfs_session` can be a v2 or v3 request (eg built with `CfsSessionBuilder`), it
+    /// is created with the CFS API version supported by the site
+    pub fn new(id: &str, cfs_session: impl Into<CfsSession>) -> Self {
+        Self {
+            id: id.to_string(),
+            cfs_session: cfs_session.into(),
+            depends_on: Vec::new(),
+            base_image_from_opt: None,
+        }
+    }
+
+    /// This CFS session will only start after `id` finishes successfully
+    pub fn depends_on(mut self, id: &str) -> Self {
+        if !self.depends_on.iter().any(|dependency| dependency == id) {
+            self.depends_on.push(id.to_string());
+        }
+        self
+    }
+
+    /// The image built by CFS session `id` is used as base image of this CFS session (members of
+    /// the target groups and source of the image map, so result image names are kept). Implies
+    /// `depends_on(id)`
+    pub fn base_image_from(mut self, id: &str) -> Self {
+        self.base_image_from_opt = Some(id.to_string());
+        self.depends_on(id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionOutcome {
+    Succeeded {
+        cfs_session_name: String,
+        result_image_id_opt: Option<String>,
+    },
+    Failed {
+        cfs_session_name: Option<String>,
+        reason: String,
+    },
+    /// CFS session not created because a dependency did not succeed or the orchestration was
+    /// cancelled
+    Cancelled { reason: String },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OrchestrationReport {
+    /// Outcome per CFS session id, in the order CFS sessions finished
+    pub outcome_vec: Vec<(String, SessionOutcome)>,
+}
+
+impl OrchestrationReport {
+    pub fn is_success(&self) -> bool {
+        self.outcome_vec
+            .iter()
+            .all(|(_, outcome)| matches!(outcome, SessionOutcome::Succeeded { .. }))
+    }
+
+    pub fn get(&self, id: &str) -> Option<&SessionOutcome> {
+        self.outcome_vec
+            .iter()
+            .find(|(session_id, _)| session_id == id)
+            .map(|(_, outcome)| outcome)
+    }
+}
+
+/// Runs a DAG of CFS sessions. CFS sessions are created as soon as all their dependencies
+/// succeed, with at most `max_concurrency` CFS sessions running at the same time. If a CFS
+/// session fails, then all CFS sessions depending on it (directly or not) are cancelled
+pub struct CfsSessionOrchestrator {
+    session_node_vec: Vec<SessionNode>,
+    max_concurrency: usize,
+    session_timeout: Duration,
+    cancellation_token: CancellationToken,
+}
+
+impl Default for CfsSessionOrchestrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CfsSessionOrchestrator {
+    pub fn new() -> Self {
+        Self {
+            session_node_vec: Vec::new(),
+            max_concurrency: 5,
+            session_timeout: Duration::from_secs(6000),
+            cancellation_token: CancellationToken::new(),
+        }
+    }
+
+    pub fn add_session(mut self, session_node: SessionNode) -> Self {
+        self.session_node_vec.push(session_node);
+        self
+    }
+
+    /// Max number of CFS sessions running at the same time. Defaults to 5
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    /// Max time to wait for each CFS session. Defaults to 100 minutes
+    pub fn session_timeout(mut self, session_timeout: Duration) -> Self {
+        self.session_timeout = session_timeout;
+        self
+    }
+
+    /// Cancelling the token stops creating new CFS sessions and stops waiting for the running
+    /// ones. Running CFS sessions are not deleted
+    pub fn cancellation_token(mut self, cancellation_token: CancellationToken) -> Self {
+        self.cancellation_token = cancellation_token;
+        self
+    }
+
+    /// Checks ids are unique, dependencies exist and there are no cycles
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut id_set = HashSet::new();
+
+        for session_node in &self.session_node_vec {
+            if !id_set.insert(session_node.id.as_str()) {
+                return Err(Error::Message(format!(
+                    "CFS session id '{}' is duplicated",
+                    session_node.id
+                )));
+            }
+        }
+
+        for session_node in &self.session_node_vec {
+            if let Some(dependency) = session_node
+                .depends_on
+                .iter()
+                .find(|dependency| !id_set.contains(dependency.as_str()))
+            {
+                return Err(Error::Message(format!(
+                    "CFS session '{}' depends on '{}' which does not exists",
+                    session_node.id, dependency
+                )));
+            }
+        }
+
+        // Kahn's algorithm, if we can't visit all nodes then there is a cycle
+        let mut pending_dependency_count: HashMap<&str, usize> = self
+            .session_node_vec
+            .iter()
+            .map(|session_node| (session_node.id.as_str(), session_node.depends_on.len()))
+            .collect();
+
+        let mut ready_queue: VecDeque<&str> = pending_dependency_count
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(id, _)| *id)
+            .collect();
+
+        let mut visited_count = 0;
+
+        while let Some(id) = ready_queue.pop_front() {
+            visited_count += 1;
+
+            for session_node in &self.session_node_vec {
+                if session_node
+                    .depends_on
+                    .iter()
+                    .any(|dependency| dependency == id)
+                {
+                    let count = pending_dependency_count
+                        .get_mut(session_node.id.as_str())
+                        .unwrap();
+                    *count -= 1;
+                    if *count == 0 {
+                        ready_queue.push_back(&session_node.id);
+                    }
+                }
+            }
+        }
+
+        if visited_count != self.session_node_vec.len() {
+            return Err(Error::Message(
+                "CFS sessions dependencies have a cycle".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub async fn run(
+        self,
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+    ) -> Result<OrchestrationReport, Error> {
+        let shasta_token = shasta_token.to_string();
+        let shasta_base_url = shasta_base_url.to_string();
+        let shasta_root_cert = shasta_root_cert.to_vec();
+
+        self.run_with(move |session_node, session_timeout, cancellation_token| {
+            run_session(
+                shasta_token.clone(),
+                shasta_base_url.clone(),
+                shasta_root_cert.clone(),
+                session_node,
+                session_timeout,
+                cancellation_token,
+            )
+        })
+        .await
+    }
+
+    /// Schedules the CFS sessions, `run_session_fn` creates a CFS session and waits for it to
+    /// finish
+    async fn run_with<F, Fut>(self, run_session_fn: F) -> Result<OrchestrationReport, Error>
+    where
+        F: Fn(SessionNode, Duration, CancellationToken) -> Fut,
+        Fut: Future<Output = (String, SessionOutcome)> + Send + 'static,
+    {
+        self.validate()?;
+
+        let mut report = OrchestrationReport::default();
+
+        let mut pending_node_vec: Vec<SessionNode> = self.session_node_vec.clone();
+        let mut outcome_map: HashMap<String, SessionOutcome> = HashMap::new();
+
+        let mut tasks = JoinSet::new();
+
+        loop {
+            // Cancel CFS sessions with a dependency which did not succeed
+            loop {
+                let cancelled_node_vec: Vec<(String, String)> = pending_node_vec
+                    .iter()
+                    .filter_map(|session_node| {
+                        session_node
+                            .depends_on
+                            .iter()
+                            .find(|dependency| {
+                                outcome_map.get(dependency.as_str()).is_some_and(|outcome| {
+                                    !matches!(outcome, SessionOutcome::Succeeded { .. })
+                                })
+                            })
+                            .map(|dependency| (session_node.id.clone(), dependency.clone()))
+                    })
+                    .collect();
+
+                if cancelled_node_vec.is_empty() {
+                    break;
+                }
+
+                for (id, dependency) in cancelled_node_vec {
+                    log::warn!(
+                        "CFS session '{}' cancelled because '{}' did not succeed",
+                        id,
+                        dependency
+                    );
+
+                    let outcome = SessionOutcome::Cancelled {
+                        reason: format!("dependency '{}' did not succeed", dependency),
+                    };
+
+                    pending_node_vec.retain(|session_node| session_node.id != id);
+                    outcome_map.insert(id.clone(), outcome.clone());
+                    report.outcome_vec.push((id, outcome));
+                }
+            }
+
+            if self.cancellation_token.is_cancelled() {
+                for session_node in pending_node_vec.drain(..) {
+                    let outcome = SessionOutcome::Cancelled {
+                        reason: "orchestration cancelled".to_string(),
+                    };
+                    outcome_map.insert(session_node.id.clone(), outcome.clone());
+                    report.outcome_vec.push((session_node.id, outcome));
+                }
+            }
+
+            // CFS sessions cancelled when starting, their dependants need to be cancelled too
+            let mut is_cancelled_on_start = false;
+
+            // Create CFS sessions with all dependencies succeeded
+            while tasks.len() < self.max_concurrency {
+                let ready_idx_opt = pending_node_vec.iter().position(|session_node| {
+                    session_node.depends_on.iter().all(|dependency| {
+                        matches!(
+                            outcome_map.get(dependency.as_str()),
+                            Some(SessionOutcome::Succeeded { .. })
+                        )
+                    })
+                });
+
+                let Some(ready_idx) = ready_idx_opt else {
+                    break;
+                };
+
+                let mut session_node = pending_node_vec.remove(ready_idx);
+
+                // Pass image built by the dependency as base image
+                if let Some(base_image_from) = &session_node.base_image_from_opt {
+                    if let Some(SessionOutcome::Succeeded {
+                        result_image_id_opt: Some(result_image_id),
+                        ..
+                    }) = outcome_map.get(base_image_from)
+                    {
+                        set_base_image(&mut session_node.cfs_session, result_image_id);
+                    } else {
+                        let outcome = SessionOutcome::Cancelled {
+                            reason: format!(
+                                "dependency '{}' did not build an image",
+                                base_image_from
+                            ),
+                        };
+                        outcome_map.insert(session_node.id.clone(), outcome.clone());
+                        report.outcome_vec.push((session_node.id, outcome));
+                        is_cancelled_on_start = true;
+                        continue;
+                    }
+                }
+
+                log::info!("Starting CFS session '{}'", session_node.id);
+
+                tasks.spawn(run_session_fn(
+                    session_node,
+                    self.session_timeout,
+                    self.cancellation_token.child_token(),
+                ));
+            }
+
+            if is_cancelled_on_start {
+                continue;
+            }
+
+            // Wait for the next CFS session to finish
+            match tasks.join_next().await {
+                Some(Ok((id, outcome))) => {
+                    log::info!("CFS session '{}' finished: {:?}", id, outcome);
+                    outcome_map.insert(id.clone(), outcome.clone());
+                    report.outcome_vec.push((id, outcome));
+                }
+                Some(Err(error)) => {
+                    return Err(Error::Message(format!(
+                        "CFS session orchestration task failed. Reason: {}",
+                        error
+                    )));
+                }
+                None => {
+                    // Nothing running and nothing can start, dependencies are propagated above
+                    // so this should not happen. Report CFS sessions left instead of losing the
+                    // outcomes already collected
+                    for session_node in pending_node_vec.drain(..) {
+                        log::warn!("CFS session '{}' can't start", session_node.id);
+
+                        let outcome = SessionOutcome::Cancelled {
+                            reason: "dependencies can't be satisfied".to_string(),
+                        };
+                        outcome_map.insert(session_node.id.clone(), outcome.clone());
+                        report.outcome_vec.push((session_node.id, outcome));
+                    }
+
+                    break;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+async fn run_session(
+    shasta_token: String,
+    shasta_base_url: String,
+    shasta_root_cert: Vec<u8>,
+    session_node: SessionNode,
+    session_timeout: Duration,
+    cancellation_token: CancellationToken,
+) -> (String, SessionOutcome) {
+    let cfs_session = match model::post(
+        &shasta_token,
+        &shasta_base_url,
+        &shasta_root_cert,
+        session_node.cfs_session.clone(),
+    )
+    .await
+    {
+        Ok(cfs_session) => cfs_session,
+        Err(error) => {
+            return (
+                session_node.id,
+                SessionOutcome::Failed {
+                    cfs_session_name: None,
+                    reason: error.to_string(),
+                },
+            )
+        }
+    };
+
+    let cfs_session_name = cfs_session.name;
+
+    let watch_result = CfsSessionWatcher::new(
+        &shasta_token,
+        &shasta_base_url,
+        &shasta_root_cert,
+        &cfs_session_name,
+    )
+    .timeout(session_timeout)
+    .cancellation_token(cancellation_token)
+    .wait()
+    .await;
+
+    let outcome = match watch_result {
+        Ok(CfsSessionWatchResult::Succeeded(cfs_session)) => {
+            let result_image_id_opt = cfs_session.get_first_result_id();
+
+            // The image built inherits the ownership tags of the CFS session which built it
+            let ownership_tags = OwnershipTags::from_tags(&session_node.cfs_session.tags);
+
+            if let Some(result_image_id) = result_image_id_opt
+                .as_ref()
+                .filter(|_| !ownership_tags.is_empty())
+            {
+                if let Err(error) = ims::image::mesa::utils::set_ownership_tags(
+                    &shasta_token,
+                    &shasta_base_url,
+                    &shasta_root_cert,
+                    result_image_id,
+                    &ownership_tags,
+                )
+                .await
+                {
+                    log::warn!(
+                        "Could not add ownership tags to IMS image '{}'. Reason:\n{}",
+                        result_image_id,
+                        error
+                    );
+                }
+            }
+
+            SessionOutcome::Succeeded {
+                cfs_session_name,
+                result_image_id_opt,
+            }
+        }
+        Ok(CfsSessionWatchResult::Failed(_)) => SessionOutcome::Failed {
+            cfs_session_name: Some(cfs_session_name),
+            reason: "CFS session failed".to_string(),
+        },
+        Ok(CfsSessionWatchResult::TimedOut(status_opt)) => SessionOutcome::Failed {
+            cfs_session_name: Some(cfs_session_name),
+            reason: format!("timed out with status {:?}", status_opt),
+        },
+        Ok(CfsSessionWatchResult::Cancelled(_)) => SessionOutcome::Cancelled {
+            reason: "orchestration cancelled".to_string(),
+        },
+        Err(error) => SessionOutcome::Failed {
+            cfs_session_name: Some(cfs_session_name),
+            reason: error.to_string(),
+        },
+    };
+
+    (session_node.id, outcome)
+}
+
+/// Replaces the base image of an image CFS session. Result image names in the image map (CFS v3)
+/// are kept
+fn set_base_image(cfs_session: &mut CfsSession, base_image_id: &str) {
+    if let CfsSessionTarget::Image {
+        group_vec,
+        image_map_vec,
+    } = &mut cfs_session.target
+    {
+        for group in group_vec.iter_mut() {
+            group.members = vec![base_image_id.to_string()];
+        }
+
+        for image_map in image_map_vec.iter_mut() {
+            image_map.source_id = base_image_id.to_string();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cfs::session::mesa::builder::CfsSessionBuilder;
+
+    use super::*;
+
+    fn session_node(id: &str) -> SessionNode {
+        SessionNode::new(
+            id,
+            CfsSessionBuilder::dynamic(id, "config-a")
+                .build_unchecked()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_validate_dag() {
+        assert!(CfsSessionOrchestrator::new()
+            .add_session(session_node("image"))
+            .add_session(session_node("nodes").base_image_from("image"))
+            .validate()
+            .is_ok());
+
+        assert!(CfsSessionOrchestrator::new()
+            .add_session(session_node("a").depends_on("b"))
+            .add_session(session_node("b").depends_on("a"))
+            .validate()
+            .is_err());
+
+        assert!(CfsSessionOrchestrator::new()
+            .add_session(session_node("a").depends_on("missing"))
+            .validate()
+            .is_err());
+
+        assert!(CfsSessionOrchestrator::new()
+            .add_session(session_node("a"))
+            .add_session(session_node("a"))
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_set_base_image_keeps_result_image_name() {
+        let mut cfs_session = SessionNode::new(
+            "compute",
+            CfsSessionBuilder::image("compute", "config-a", "placeholder", "compute-image")
+                .group("Compute")
+                .build_unchecked()
+                .unwrap(),
+        )
+        .cfs_session;
+
+        set_base_image(&mut cfs_session, "base-image-id");
+
+        let CfsSessionTarget::Image {
+            group_vec,
+            image_map_vec,
+        } = cfs_session.target
+        else {
+            panic!("CFS session target is not an image");
+        };
+
+        assert_eq!(group_vec[0].members, vec!["base-image-id"]);
+        assert_eq!(image_map_vec[0].source_id, "base-image-id");
+        assert_eq!(image_map_vec[0].result_name, "compute-image");
+    }
+
+    #[tokio::test]
+    async fn test_run_cancels_dependants_of_base_image_without_image() {
+        let report = CfsSessionOrchestrator::new()
+            .add_session(session_node("a"))
+            .add_session(session_node("b").base_image_from("a"))
+            .add_session(session_node("c").depends_on("b"))
+            .run_with(|session_node, _, _| async move {
+                // 'a' succeeds without building an image
+                (
+                    session_node.id.clone(),
+                    SessionOutcome::Succeeded {
+                        cfs_session_name: session_node.id,
+                        result_image_id_opt: None,
+                    },
+                )
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(report.outcome_vec.len(), 3);
+        assert!(matches!(
+            report.get("a"),
+            Some(SessionOutcome::Succeeded { .. })
+        ));
+        assert_eq!(
+            report.get("b"),
+            Some(&SessionOutcome::Cancelled {
+                reason: "dependency 'a' did not build an image".to_string()
+            })
+        );
+        assert_eq!(
+            report.get("c"),
+            Some(&SessionOutcome::Cancelled {
+                reason: "dependency 'b' did not succeed".to_string()
+            })
+        );
+    }
+}
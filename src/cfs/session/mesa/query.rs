@@ -0,0 +1,280 @@
+use crate::{cfs, common::ownership::OwnershipTags, error::Error};
+
+use super::{
+    r#struct::v2::CfsSessionGetResponse,
+    utils::{self, CfsSessionSortOrder},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CfsSessionStatusFilter {
+    Pending,
+    Running,
+    Complete,
+}
+
+impl CfsSessionStatusFilter {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Running => "running",
+            Self::Complete => "complete",
+        }
+    }
+}
+
+/// Query CFS sessions. Filters supported by the CFS API (status, succeeded, age, tags and name)
+/// are sent to CSM so only the matching CFS sessions are downloaded, then CFS sessions are
+/// filtered by HSM group and xnames, sorted and limited
+///
+/// eg: last 5 failed CFS sessions of the last day targeting HSM group 'zinal'
+///
+/// CfsSessionQuery::new()
+///     .status(CfsSessionStatusFilter::Complete)
+///     .succeeded(false)
+///     .max_age("1d")
+///     .hsm_group_name_vec(&["zinal".to_string()])
+///     .limit(5)
+///     .get(shasta_token, shasta_base_url, shasta_root_cert)
+///     .await
+#[derive(Debug, Clone, Default)]
+pub struct CfsSessionQuery {
+    name_contains_opt: Option<String>,
+    status_opt: Option<CfsSessionStatusFilter>,
+    succeeded_opt: Option<bool>,
+    min_age_opt: Option<String>,
+    max_age_opt: Option<String>,
+    tag_vec: Vec<(String, String)>,
+    hsm_group_name_vec: Vec<String>,
+    xname_vec: Vec<String>,
+    keep_generic_sessions: bool,
+    sort_order: CfsSessionSortOrder,
+    limit_opt: Option<usize>,
+}
+
+impl CfsSessionQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn name_contains(mut self, name_contains: &str) -> Self {
+        self.name_contains_opt = Some(name_contains.to_string());
+        self
+    }
+
+    pub fn status(mut self, status: CfsSessionStatusFilter) -> Self {
+        self.status_opt = Some(status);
+        self
+    }
+
+    pub fn succeeded(mut self, succeeded: bool) -> Self {
+        self.succeeded_opt = Some(succeeded);
+        self
+    }
+
+    /// CFS sessions older than `min_age` (eg "1h", "3d")
+    pub fn min_age(mut self, min_age: &str) -> Self {
+        self.min_age_opt = Some(min_age.to_string());
+        self
+    }
+
+    /// CFS sessions newer than `max_age` (eg "1h", "3d")
+    pub fn max_age(mut self, max_age: &str) -> Self {
+        self.max_age_opt = Some(max_age.to_string());
+        self
+    }
+
+    pub fn tag(mut self, key: &str, value: &str) -> Self {
+        self.tag_vec.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// CFS sessions created with these mesa ownership tags (see `common::ownership`)
+    pub fn ownership_tags(mut self, ownership_tags: &OwnershipTags) -> Self {
+        for (key, value) in ownership_tags.to_tag_vec() {
+            self = self.tag(key, &value);
+        }
+        self
+    }
+
+    /// CFS sessions targeting any of these HSM groups or any of their members
+    pub fn hsm_group_name_vec(mut self, hsm_group_name_vec: &[String]) -> Self {
+        self.hsm_group_name_vec = hsm_group_name_vec.to_vec();
+        self
+    }
+
+    /// CFS sessions targeting any of these xnames or any HSM group they belong to
+    pub fn xname_vec(mut self, xname_vec: &[String]) -> Self {
+        self.xname_vec = xname_vec.to_vec();
+        self
+    }
+
+    /// Keep CFS sessions building "generic" images when filtering by HSM group or xnames (see
+    /// `utils::is_session_image_generic`)
+    pub fn keep_generic_sessions(mut self, keep_generic_sessions: bool) -> Self {
+        self.keep_generic_sessions = keep_generic_sessions;
+        self
+    }
+
+    pub fn sort_order(mut self, sort_order: CfsSessionSortOrder) -> Self {
+        self.sort_order = sort_order;
+        self
+    }
+
+    /// Max number of CFS sessions returned, the first ones in the sort order (eg the 5 newest
+    /// CFS sessions with `CfsSessionSortOrder::StartTimeDesc`)
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit_opt = Some(limit);
+        self
+    }
+
+    /// CFS tags filter format is comma separated list of key=value
+    fn get_tags_param(&self) -> Option<String> {
+        if self.tag_vec.is_empty() {
+            None
+        } else {
+            Some(
+                self.tag_vec
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect::<Vec<String>>()
+                    .join(","),
+            )
+        }
+    }
+
+    pub async fn get(
+        &self,
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+    ) -> Result<Vec<CfsSessionGetResponse>, Error> {
+        let mut cfs_session_vec = cfs::session::shasta::http_client::v2::get(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            None,
+            self.min_age_opt.clone(),
+            self.max_age_opt.clone(),
+            self.status_opt.map(|status| status.as_str().to_string()),
+            self.name_contains_opt.clone(),
+            self.succeeded_opt,
+            self.get_tags_param(),
+        )
+        .await?;
+
+        log::info!(
+            "{} CFS sessions returned by CSM matching filters",
+            cfs_session_vec.len()
+        );
+
+        if !self.hsm_group_name_vec.is_empty() {
+            utils::filter_by_hsm(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                &mut cfs_session_vec,
+                &self.hsm_group_name_vec,
+                None,
+                self.keep_generic_sessions,
+            )
+            .await;
+        }
+
+        if !self.xname_vec.is_empty() {
+            utils::filter_by_xname(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                &mut cfs_session_vec,
+                &self
+                    .xname_vec
+                    .iter()
+                    .map(|xname| xname.as_str())
+                    .collect::<Vec<&str>>(),
+                None,
+                self.keep_generic_sessions,
+            )
+            .await;
+        }
+
+        self.sort_and_limit(&mut cfs_session_vec);
+
+        Ok(cfs_session_vec)
+    }
+
+    fn sort_and_limit(&self, cfs_session_vec: &mut Vec<CfsSessionGetResponse>) {
+        utils::sort(cfs_session_vec, self.sort_order);
+
+        if let Some(limit) = self.limit_opt {
+            cfs_session_vec.truncate(limit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::test_fixtures;
+
+    use super::*;
+
+    fn cfs_session(name: &str, start_time_opt: Option<&str>) -> CfsSessionGetResponse {
+        test_fixtures::cfs_session(name, "running", "none", start_time_opt)
+    }
+
+    #[test]
+    fn test_sort_sessions_without_start_time() {
+        let mut cfs_session_vec = vec![
+            cfs_session("pending", None),
+            cfs_session("newer", Some("2024-02-01T00:00:00")),
+            cfs_session("older", Some("2024-01-01T00:00:00")),
+        ];
+
+        utils::sort(&mut cfs_session_vec, CfsSessionSortOrder::StartTimeAsc);
+
+        assert_eq!(
+            cfs_session_vec
+                .iter()
+                .map(|cfs_session| cfs_session.name.clone().unwrap())
+                .collect::<Vec<String>>(),
+            vec!["older", "newer", "pending"]
+        );
+
+        utils::sort(&mut cfs_session_vec, CfsSessionSortOrder::StartTimeDesc);
+
+        assert_eq!(cfs_session_vec[0].name.as_deref(), Some("pending"));
+    }
+
+    #[test]
+    fn test_tags_param() {
+        let cfs_session_query = CfsSessionQuery::new()
+            .tag("owner", "alice")
+            .tag("hsm_group", "zinal");
+
+        assert_eq!(
+            cfs_session_query.get_tags_param().as_deref(),
+            Some("owner=alice,hsm_group=zinal")
+        );
+    }
+
+    #[test]
+    fn test_limit_keeps_first_in_sort_order() {
+        let mut cfs_session_vec = vec![
+            cfs_session("oldest", Some("2024-01-01T00:00:00")),
+            cfs_session("newest", Some("2024-03-01T00:00:00")),
+            cfs_session("middle", Some("2024-02-01T00:00:00")),
+        ];
+
+        CfsSessionQuery::new()
+            .sort_order(CfsSessionSortOrder::StartTimeDesc)
+            .limit(2)
+            .sort_and_limit(&mut cfs_session_vec);
+
+        assert_eq!(
+            cfs_session_vec
+                .iter()
+                .map(|cfs_session| cfs_session.name.clone().unwrap())
+                .collect::<Vec<String>>(),
+            vec!["newest", "middle"]
+        );
+    }
+}
@@ -0,0 +1,439 @@
+use std::time::Duration;
+
+use futures::{stream::BoxStream, Stream, StreamExt};
+use k8s_openapi::api::batch::v1::Job;
+use kube::runtime::watcher;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::{cfs, error::Error};
+
+use super::r#struct::v2::CfsSessionGetResponse;
+
+/// Number of consecutive errors fetching the CFS session before giving up
+const MAX_CONSECUTIVE_ERRORS: u8 = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CfsSessionStatus {
+    Pending,
+    Running,
+    Complete { succeeded: bool },
+}
+
+impl CfsSessionStatus {
+    pub fn from_session(cfs_session: &CfsSessionGetResponse) -> Option<Self> {
+        let session = cfs_session.status.as_ref()?.session.as_ref()?;
+
+        match session.status.as_deref()? {
+            "pending" => Some(Self::Pending),
+            "running" => Some(Self::Running),
+            "complete" => Some(Self::Complete {
+                succeeded: session.succeeded.as_deref() == Some("true"),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        matches!(self, Self::Complete { .. })
+    }
+}
+
+/// How the CFS session watch ended
+#[derive(Debug, Clone)]
+pub enum CfsSessionWatchResult {
+    Succeeded(Box<CfsSessionGetResponse>),
+    Failed(Box<CfsSessionGetResponse>),
+    /// Last status seen before the timeout
+    TimedOut(Option<CfsSessionStatus>),
+    /// Last status seen before the watch was cancelled
+    Cancelled(Option<CfsSessionStatus>),
+}
+
+impl CfsSessionWatchResult {
+    pub fn is_success(&self) -> bool {
+        matches!(self, Self::Succeeded(_))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CfsSessionWatchEvent {
+    /// CFS session status changed
+    Transition(CfsSessionStatus),
+    /// Last event in the stream
+    Finished(CfsSessionWatchResult),
+}
+
+/// Watches a CFS session till it finishes. If a k8s client is provided, then changes in the CFS
+/// session k8s job trigger a check of the CFS session status, otherwise (or if the k8s watch
+/// fails) the CFS session status is polled
+pub struct CfsSessionWatcher {
+    shasta_token: String,
+    shasta_base_url: String,
+    shasta_root_cert: Vec<u8>,
+    cfs_session_name: String,
+    timeout: Duration,
+    poll_interval: Duration,
+    cancellation_token: CancellationToken,
+    k8s_client_opt: Option<kube::Client>,
+}
+
+impl CfsSessionWatcher {
+    pub fn new(
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+        cfs_session_name: &str,
+    ) -> Self {
+        Self {
+            shasta_token: shasta_token.to_string(),
+            shasta_base_url: shasta_base_url.to_string(),
+            shasta_root_cert: shasta_root_cert.to_vec(),
+            cfs_session_name: cfs_session_name.to_string(),
+            timeout: Duration::from_secs(6000),
+            poll_interval: Duration::from_secs(2),
+            cancellation_token: CancellationToken::new(),
+            k8s_client_opt: None,
+        }
+    }
+
+    /// Max time to wait for the CFS session to finish. Defaults to 100 minutes
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Time between checks when polling the CFS session. Defaults to 2 seconds. If watching the
+    /// k8s job, this is multiplied by 10 and used as a safety net in case we miss k8s events
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Cancel the watch by calling `cancel` in the token (or in any clone of it)
+    pub fn cancellation_token(mut self, cancellation_token: CancellationToken) -> Self {
+        self.cancellation_token = cancellation_token;
+        self
+    }
+
+    /// Watch the CFS session k8s job instead of polling
+    pub fn k8s_client(mut self, k8s_client: kube::Client) -> Self {
+        self.k8s_client_opt = Some(k8s_client);
+        self
+    }
+
+    /// Stream of CFS session status transitions. The last item is always either an error or a
+    /// `CfsSessionWatchEvent::Finished`
+    pub fn watch(self) -> impl Stream<Item = Result<CfsSessionWatchEvent, Error>> {
+        let job_event_stream_opt = self.k8s_client_opt.clone().map(|k8s_client| {
+            let job_api: kube::Api<Job> = kube::Api::namespaced(k8s_client, "services");
+
+            watcher(
+                job_api,
+                watcher::Config::default().labels(&format!("cfsession={}", self.cfs_session_name)),
+            )
+            .map(|job_event| job_event.is_ok())
+            .boxed()
+        });
+
+        let state = WatchState {
+            deadline: Instant::now() + self.timeout,
+            watcher: self,
+            job_event_stream_opt,
+            last_status_opt: None,
+            consecutive_error_count: 0,
+            is_finished: false,
+        };
+
+        futures::stream::unfold(state, |mut state| async move {
+            if state.is_finished {
+                return None;
+            }
+
+            let event_rslt = state.next_event().await;
+
+            if !matches!(event_rslt, Ok(CfsSessionWatchEvent::Transition(_))) {
+                state.is_finished = true;
+            }
+
+            Some((event_rslt, state))
+        })
+    }
+
+    /// Waits for the CFS session to finish and returns how it finished. Status transitions are
+    /// logged
+    pub async fn wait(self) -> Result<CfsSessionWatchResult, Error> {
+        let cfs_session_name = self.cfs_session_name.clone();
+
+        let mut event_stream = Box::pin(self.watch());
+
+        while let Some(event) = event_stream.next().await {
+            match event? {
+                CfsSessionWatchEvent::Transition(status) => {
+                    log::info!("CFS session '{}' status '{:?}'", cfs_session_name, status)
+                }
+                CfsSessionWatchEvent::Finished(result) => return Ok(result),
+            }
+        }
+
+        Err(Error::Message(format!(
+            "Watch of CFS session '{}' ended unexpectedly",
+            cfs_session_name
+        )))
+    }
+}
+
+struct WatchState {
+    watcher: CfsSessionWatcher,
+    deadline: Instant,
+    job_event_stream_opt: Option<BoxStream<'static, bool>>,
+    last_status_opt: Option<CfsSessionStatus>,
+    consecutive_error_count: u8,
+    is_finished: bool,
+}
+
+impl WatchState {
+    async fn next_event(&mut self) -> Result<CfsSessionWatchEvent, Error> {
+        loop {
+            if let Some(event) = self.get_stop_event() {
+                return Ok(event);
+            }
+
+            match fetch_session(&self.watcher).await {
+                Ok(cfs_session) => {
+                    self.consecutive_error_count = 0;
+
+                    if let Some(event) = self.process_session(cfs_session) {
+                        return Ok(event);
+                    }
+                }
+                Err(error) => {
+                    self.consecutive_error_count += 1;
+
+                    log::warn!(
+                        "Could not get CFS session '{}' (attempt {} of {}). Reason:\n{}",
+                        self.watcher.cfs_session_name,
+                        self.consecutive_error_count,
+                        MAX_CONSECUTIVE_ERRORS,
+                        error
+                    );
+
+                    if self.consecutive_error_count >= MAX_CONSECUTIVE_ERRORS {
+                        return Err(error);
+                    }
+                }
+            }
+
+            self.wait_for_change().await;
+        }
+    }
+
+    /// Returns the last event if the watch was cancelled or timed out
+    fn get_stop_event(&self) -> Option<CfsSessionWatchEvent> {
+        if self.watcher.cancellation_token.is_cancelled() {
+            Some(CfsSessionWatchEvent::Finished(
+                CfsSessionWatchResult::Cancelled(self.last_status_opt.clone()),
+            ))
+        } else if Instant::now() >= self.deadline {
+            Some(CfsSessionWatchEvent::Finished(
+                CfsSessionWatchResult::TimedOut(self.last_status_opt.clone()),
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the event to report for the CFS session fetched, if any
+    fn process_session(
+        &mut self,
+        cfs_session: CfsSessionGetResponse,
+    ) -> Option<CfsSessionWatchEvent> {
+        if let Some(status) = CfsSessionStatus::from_session(&cfs_session) {
+            if self.last_status_opt.as_ref() != Some(&status) {
+                self.last_status_opt = Some(status.clone());
+                return Some(CfsSessionWatchEvent::Transition(status));
+            }
+        }
+
+        // The 'complete' transition has already been reported
+        if let Some(CfsSessionStatus::Complete { succeeded }) = self.last_status_opt {
+            return Some(CfsSessionWatchEvent::Finished(if succeeded {
+                CfsSessionWatchResult::Succeeded(Box::new(cfs_session))
+            } else {
+                CfsSessionWatchResult::Failed(Box::new(cfs_session))
+            }));
+        }
+
+        None
+    }
+
+    /// Waits till the CFS session k8s job changes, the poll interval expires, the timeout is
+    /// reached or the watch is cancelled, whatever happens first
+    async fn wait_for_change(&mut self) {
+        let poll_interval = if self.job_event_stream_opt.is_some() {
+            self.watcher.poll_interval * 10
+        } else {
+            self.watcher.poll_interval
+        };
+
+        let sleep_until = std::cmp::min(Instant::now() + poll_interval, self.deadline);
+
+        let cancellation_token = self.watcher.cancellation_token.clone();
+
+        match self.job_event_stream_opt.as_mut() {
+            Some(job_event_stream) => {
+                tokio::select! {
+                    job_event_opt = job_event_stream.next() => {
+                        if job_event_opt != Some(true) {
+                            log::warn!("k8s watch on CFS session job failed, falling back to polling");
+                            self.job_event_stream_opt = None;
+                        }
+                    }
+                    _ = tokio::time::sleep_until(sleep_until) => {}
+                    _ = cancellation_token.cancelled() => {}
+                }
+            }
+            None => {
+                tokio::select! {
+                    _ = tokio::time::sleep_until(sleep_until) => {}
+                    _ = cancellation_token.cancelled() => {}
+                }
+            }
+        }
+    }
+}
+
+// Not a method of WatchState because the k8s watch stream is not Sync
+async fn fetch_session(watcher: &CfsSessionWatcher) -> Result<CfsSessionGetResponse, Error> {
+    cfs::session::mesa::http_client::get(
+        &watcher.shasta_token,
+        &watcher.shasta_base_url,
+        &watcher.shasta_root_cert,
+        None,
+        None,
+        None,
+        Some(&watcher.cfs_session_name),
+        None,
+    )
+    .await?
+    .first()
+    .cloned()
+    .ok_or_else(|| {
+        Error::Message(format!(
+            "CFS session '{}' not found",
+            watcher.cfs_session_name
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::test_fixtures::cfs_session;
+
+    use super::*;
+
+    fn watch_state(timeout: Duration) -> WatchState {
+        let watcher = CfsSessionWatcher::new("token", "https://api.local", &[], "my-session");
+
+        WatchState {
+            deadline: Instant::now() + timeout,
+            watcher,
+            job_event_stream_opt: None,
+            last_status_opt: None,
+            consecutive_error_count: 0,
+            is_finished: false,
+        }
+    }
+
+    #[test]
+    fn test_cfs_session_status_from_session() {
+        assert_eq!(
+            CfsSessionStatus::from_session(&cfs_session("my-session", "pending", "none", None)),
+            Some(CfsSessionStatus::Pending)
+        );
+        assert_eq!(
+            CfsSessionStatus::from_session(&cfs_session("my-session", "running", "none", None)),
+            Some(CfsSessionStatus::Running)
+        );
+        assert_eq!(
+            CfsSessionStatus::from_session(&cfs_session("my-session", "complete", "true", None)),
+            Some(CfsSessionStatus::Complete { succeeded: true })
+        );
+        assert_eq!(
+            CfsSessionStatus::from_session(&cfs_session("my-session", "complete", "false", None)),
+            Some(CfsSessionStatus::Complete { succeeded: false })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_timeout_and_cancel() {
+        // Deadline and cancellation are checked before calling CSM
+        assert!(matches!(
+            CfsSessionWatcher::new("token", "https://api.local", &[], "my-session")
+                .timeout(Duration::ZERO)
+                .wait()
+                .await,
+            Ok(CfsSessionWatchResult::TimedOut(None))
+        ));
+
+        let cancellation_token = CancellationToken::new();
+        cancellation_token.cancel();
+
+        assert!(matches!(
+            CfsSessionWatcher::new("token", "https://api.local", &[], "my-session")
+                .cancellation_token(cancellation_token)
+                .wait()
+                .await,
+            Ok(CfsSessionWatchResult::Cancelled(None))
+        ));
+
+        // Last status seen is reported on timeout
+        let mut state = watch_state(Duration::ZERO);
+        state.process_session(cfs_session("my-session", "running", "none", None));
+
+        assert!(matches!(
+            state.get_stop_event(),
+            Some(CfsSessionWatchEvent::Finished(
+                CfsSessionWatchResult::TimedOut(Some(CfsSessionStatus::Running))
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_process_session_terminal_state() {
+        let mut state = watch_state(Duration::from_secs(60));
+
+        assert!(state.get_stop_event().is_none());
+        assert!(matches!(
+            state.process_session(cfs_session("my-session", "pending", "none", None)),
+            Some(CfsSessionWatchEvent::Transition(CfsSessionStatus::Pending))
+        ));
+        // Same status is not reported twice
+        assert!(state
+            .process_session(cfs_session("my-session", "pending", "none", None))
+            .is_none());
+        assert!(matches!(
+            state.process_session(cfs_session("my-session", "complete", "false", None)),
+            Some(CfsSessionWatchEvent::Transition(
+                CfsSessionStatus::Complete { succeeded: false }
+            ))
+        ));
+        // Once the 'complete' transition is reported, the watch finishes
+        assert!(matches!(
+            state.process_session(cfs_session("my-session", "complete", "false", None)),
+            Some(CfsSessionWatchEvent::Finished(
+                CfsSessionWatchResult::Failed(_)
+            ))
+        ));
+
+        let mut state = watch_state(Duration::from_secs(60));
+        state.process_session(cfs_session("my-session", "complete", "true", None));
+
+        assert!(matches!(
+            state.process_session(cfs_session("my-session", "complete", "true", None)),
+            Some(CfsSessionWatchEvent::Finished(
+                CfsSessionWatchResult::Succeeded(_)
+            ))
+        ));
+    }
+}
@@ -0,0 +1,416 @@
+/// Typed representation of the `cray-product-catalog` k8s ConfigMap. The ConfigMap is a map of
+/// product name --> YAML string with the list of versions installed in the system for that product
+///
+/// Example of ConfigMap data entry:
+///
+/// cos: |
+///   2.4.104:
+///     configuration:
+///       clone_url: https://vcs.cmn.alps.cscs.ch/vcs/cray/cos-config-management.git
+///       commit: 1a2b3c4d5e
+///       import_branch: cray/cos/2.4.104
+///     images:
+///       cos-2.4.104-sles15sp4.x86_64:
+///         id: 0a1b2c3d-...
+pub mod r#struct {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+    pub struct ProductConfiguration {
+        pub clone_url: String,
+        pub commit: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub import_branch: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub import_date: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub ssh_url: Option<String>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+    pub struct ProductArtifact {
+        pub id: String,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+    pub struct ProductVersion {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub configuration: Option<ProductConfiguration>,
+        #[serde(default)]
+        pub images: BTreeMap<String, ProductArtifact>,
+        #[serde(default)]
+        pub recipes: BTreeMap<String, ProductArtifact>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default)]
+    pub struct ProductCatalog {
+        /// product name --> product version --> product details
+        pub products: BTreeMap<String, BTreeMap<String, ProductVersion>>,
+    }
+
+    /// Result of resolving a product name and version (or version requirement) against the
+    /// catalog
+    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+    pub struct ResolvedProduct {
+        pub name: String,
+        pub version: String,
+        pub configuration: ProductConfiguration,
+    }
+}
+
+pub mod utils {
+    use std::collections::BTreeMap;
+
+    use semver::{Version, VersionReq};
+
+    use crate::error::Error;
+
+    use super::{
+        k8s::K8sAccess,
+        r#struct::{ProductCatalog, ProductVersion, ResolvedProduct},
+    };
+
+    impl ProductCatalog {
+        /// Parses the data in the `cray-product-catalog` ConfigMap. Product entries which can't
+        /// be parsed are ignored and logged
+        pub fn from_configmap_data(configmap_data: &BTreeMap<String, String>) -> Self {
+            let mut products = BTreeMap::new();
+
+            for (product_name, product_versions_yaml) in configmap_data {
+                match serde_yaml::from_str::<BTreeMap<String, ProductVersion>>(
+                    product_versions_yaml,
+                ) {
+                    Ok(product_versions) => {
+                        products.insert(product_name.clone(), product_versions);
+                    }
+                    Err(error) => {
+                        log::warn!(
+                            "Could not parse product '{}' in cray product catalog. Reason:\n{}",
+                            product_name,
+                            error
+                        );
+                    }
+                }
+            }
+
+            Self { products }
+        }
+
+        pub fn get_product_version_vec(&self, product_name: &str) -> Vec<String> {
+            self.products
+                .get(product_name)
+                .map(|product_versions| product_versions.keys().cloned().collect())
+                .unwrap_or_default()
+        }
+
+        /// Resolves a product name and version to the CFS configuration (clone url and commit id)
+        /// registered in the catalog.
+        /// `product_version` can be:
+        ///  - an exact version as stored in the catalog (eg "2.4.104"), fails if missing
+        ///  - "latest" (or empty) which means the highest version with a configuration
+        ///  - a semver requirement (eg "~2.4", ">=2.3, <2.5") which resolves to the highest
+        ///    version matching the requirement
+        pub fn resolve(
+            &self,
+            product_name: &str,
+            product_version: &str,
+        ) -> Result<ResolvedProduct, Error> {
+            let product_versions = self.products.get(product_name).ok_or_else(|| {
+                Error::Message(format!(
+                    "Product '{}' not found in cray product catalog",
+                    product_name
+                ))
+            })?;
+
+            let product_version = product_version.trim();
+
+            // Only versions with a configuration are useful to build CFS configuration layers
+            let mut candidate_vec: Vec<(&String, &ProductVersion)> = product_versions
+                .iter()
+                .filter(|(_, details)| details.configuration.is_some())
+                .collect();
+
+            let (version, details) = if let Some((version, details)) = candidate_vec
+                .iter()
+                .find(|(version, _)| version.as_str() == product_version)
+            {
+                // Exact match
+                (*version, *details)
+            } else if let Ok(exact_version) = Version::parse(product_version) {
+                // A bare version is an exact version, not the caret requirement semver would
+                // read from it (eg '2.4.100' must not resolve to '2.4.104')
+                candidate_vec
+                    .iter()
+                    .find(|(version, _)| parse_version(version).as_ref() == Some(&exact_version))
+                    .copied()
+                    .ok_or_else(|| {
+                        Error::Message(format!(
+                            "Product '{}' version '{}' with a configuration not found in cray product catalog. Versions available: {:?}",
+                            product_name,
+                            product_version,
+                            product_versions.keys().collect::<Vec<_>>()
+                        ))
+                    })?
+            } else {
+                if !product_version.is_empty() && product_version != "latest" {
+                    let version_req = VersionReq::parse(product_version).map_err(|error| {
+                        Error::Message(format!(
+                            "Product '{}' version '{}' not found in cray product catalog and it is not a valid semver requirement. Reason: {}",
+                            product_name, product_version, error
+                        ))
+                    })?;
+
+                    candidate_vec.retain(|(version, _)| {
+                        parse_version(version).is_some_and(|version| version_req.matches(&version))
+                    });
+                }
+
+                candidate_vec.sort_by(|(version_a, _), (version_b, _)| {
+                    compare_versions(version_a, version_b)
+                });
+
+                candidate_vec.last().copied().ok_or_else(|| {
+                    Error::Message(format!(
+                        "No version matching '{}' with a configuration found for product '{}' in cray product catalog. Versions available: {:?}",
+                        product_version,
+                        product_name,
+                        product_versions.keys().collect::<Vec<_>>()
+                    ))
+                })?
+            };
+
+            log::debug!(
+                "Product '{}' version '{}' resolved to version '{}'",
+                product_name,
+                product_version,
+                version
+            );
+
+            Ok(ResolvedProduct {
+                name: product_name.to_string(),
+                version: version.clone(),
+                configuration: details.configuration.clone().unwrap(),
+            })
+        }
+    }
+
+    /// Resolves products against the cray product catalog. The catalog is either provided by the
+    /// caller or fetched from k8s the first time a product is resolved
+    pub struct ProductCatalogResolver<'a> {
+        cray_product_catalog_opt: Option<&'a ProductCatalog>,
+        k8s_access_opt: Option<&'a K8sAccess<'a>>,
+        fetched_cray_product_catalog_opt: Option<ProductCatalog>,
+    }
+
+    impl<'a> ProductCatalogResolver<'a> {
+        pub fn new(
+            cray_product_catalog_opt: Option<&'a ProductCatalog>,
+            k8s_access_opt: Option<&'a K8sAccess<'a>>,
+        ) -> Self {
+            Self {
+                cray_product_catalog_opt,
+                k8s_access_opt,
+                fetched_cray_product_catalog_opt: None,
+            }
+        }
+
+        pub async fn resolve(
+            &mut self,
+            product_name: &str,
+            product_version: &str,
+        ) -> Result<ResolvedProduct, Error> {
+            if let Some(cray_product_catalog) = self.cray_product_catalog_opt {
+                return cray_product_catalog.resolve(product_name, product_version);
+            }
+
+            if self.fetched_cray_product_catalog_opt.is_none() {
+                let k8s_access = self.k8s_access_opt.ok_or_else(|| {
+                    Error::Message(
+                        "Cray product catalog not provided and no k8s details to fetch it"
+                            .to_string(),
+                    )
+                })?;
+
+                let cray_product_catalog = super::k8s::get(
+                    k8s_access.vault_base_url,
+                    k8s_access.vault_secret_path,
+                    k8s_access.vault_role_id,
+                    k8s_access.k8s_api_url,
+                )
+                .await
+                .map_err(|error| {
+                    Error::Message(format!(
+                        "Could not fetch cray product catalog. Reason:\n{}",
+                        error
+                    ))
+                })?;
+
+                self.fetched_cray_product_catalog_opt = Some(cray_product_catalog);
+            }
+
+            self.fetched_cray_product_catalog_opt
+                .as_ref()
+                .unwrap()
+                .resolve(product_name, product_version)
+        }
+    }
+
+    /// Catalog versions are not always strict semver (eg "1.5" or "23.7.0-20230701"), pad
+    /// missing minor/patch numbers so they can still be compared
+    pub fn parse_version(version: &str) -> Option<Version> {
+        let version = version.trim().trim_start_matches('v');
+
+        if let Ok(version) = Version::parse(version) {
+            return Some(version);
+        }
+
+        let (core, suffix) = match version.find(['-', '+']) {
+            Some(idx) => version.split_at(idx),
+            None => (version, ""),
+        };
+
+        let mut numbers: Vec<&str> = core.split('.').collect();
+        if numbers.is_empty() || numbers.len() > 3 {
+            return None;
+        }
+        while numbers.len() < 3 {
+            numbers.push("0");
+        }
+
+        Version::parse(&format!("{}{}", numbers.join("."), suffix)).ok()
+    }
+
+    /// Versions which are not semver are considered lower than the ones which are and are
+    /// compared lexicographically among themselves
+    pub fn compare_versions(version_a: &str, version_b: &str) -> std::cmp::Ordering {
+        match (parse_version(version_a), parse_version(version_b)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Greater,
+            (None, Some(_)) => std::cmp::Ordering::Less,
+            (None, None) => version_a.cmp(version_b),
+        }
+    }
+}
+
+pub mod k8s {
+    use crate::{
+        common::{kubernetes, vault::http_client::fetch_shasta_k8s_secrets},
+        error::Error,
+    };
+
+    use super::r#struct::ProductCatalog;
+
+    pub const CRAY_PRODUCT_CATALOG_CONFIGMAP_NAME: &str = "cray-product-catalog";
+
+    /// Details needed to fetch the cray product catalog from the k8s cluster
+    #[derive(Debug, Clone)]
+    pub struct K8sAccess<'a> {
+        pub vault_base_url: &'a str,
+        pub vault_secret_path: &'a str,
+        pub vault_role_id: &'a str,
+        pub k8s_api_url: &'a str,
+    }
+
+    /// Fetch the `cray-product-catalog` ConfigMap from the k8s cluster and parse it
+    pub async fn get(
+        vault_base_url: &str,
+        vault_secret_path: &str,
+        vault_role_id: &str,
+        k8s_api_url: &str,
+    ) -> Result<ProductCatalog, Error> {
+        let shasta_k8s_secrets =
+            fetch_shasta_k8s_secrets(vault_base_url, vault_secret_path, vault_role_id).await;
+
+        let client = kubernetes::get_k8s_client_programmatically(k8s_api_url, shasta_k8s_secrets)
+            .await
+            .map_err(|error| Error::Message(error.to_string()))?;
+
+        let configmap_data = kubernetes::get_configmap(client, CRAY_PRODUCT_CATALOG_CONFIGMAP_NAME)
+            .await
+            .ok_or_else(|| {
+                Error::Message(format!(
+                    "ConfigMap '{}' has no data",
+                    CRAY_PRODUCT_CATALOG_CONFIGMAP_NAME
+                ))
+            })?;
+
+        Ok(ProductCatalog::from_configmap_data(&configmap_data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::r#struct::ProductCatalog;
+
+    fn catalog() -> ProductCatalog {
+        let mut configmap_data = BTreeMap::new();
+        configmap_data.insert(
+            "cos".to_string(),
+            r#"
+2.3.101:
+  configuration:
+    clone_url: https://vcs.cmn.alps.cscs.ch/vcs/cray/cos-config-management.git
+    commit: aaa
+    import_branch: cray/cos/2.3.101
+2.4.99:
+  configuration:
+    clone_url: https://vcs.cmn.alps.cscs.ch/vcs/cray/cos-config-management.git
+    commit: bbb
+2.4.104:
+  configuration:
+    clone_url: https://vcs.cmn.alps.cscs.ch/vcs/cray/cos-config-management.git
+    commit: ccc
+  images:
+    cos-2.4.104-sles15sp4.x86_64:
+      id: 0a1b2c3d
+2.5.0:
+  images:
+    cos-2.5.0-sles15sp5.x86_64:
+      id: 4e5f6a7b
+"#
+            .to_string(),
+        );
+
+        ProductCatalog::from_configmap_data(&configmap_data)
+    }
+
+    #[test]
+    fn test_resolve_exact_version() {
+        let resolved = catalog().resolve("cos", "2.4.99").unwrap();
+
+        assert_eq!(resolved.version, "2.4.99");
+        assert_eq!(resolved.configuration.commit, "bbb");
+    }
+
+    #[test]
+    fn test_resolve_latest_version_with_configuration() {
+        let resolved = catalog().resolve("cos", "latest").unwrap();
+
+        assert_eq!(resolved.version, "2.4.104");
+        assert_eq!(resolved.configuration.commit, "ccc");
+    }
+
+    #[test]
+    fn test_resolve_semver_requirement() {
+        let resolved = catalog().resolve("cos", "~2.3").unwrap();
+
+        assert_eq!(resolved.version, "2.3.101");
+
+        let resolved = catalog().resolve("cos", ">=2.4.0, <2.4.100").unwrap();
+
+        assert_eq!(resolved.version, "2.4.99");
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        assert!(catalog().resolve("sma", "latest").is_err());
+        assert!(catalog().resolve("cos", "^3").is_err());
+        assert!(catalog().resolve("cos", "not a version").is_err());
+        // Bare version missing in the catalog is not read as '^2.4.100'
+        assert!(catalog().resolve("cos", "2.4.100").is_err());
+    }
+}
@@ -76,6 +76,109 @@ pub mod http_client {
         } */
     }
 
+    /// Get repository details. Returns None if the repository does not exists
+    pub async fn get_repo(
+        gitea_base_url: &str,
+        gitea_token: &str,
+        shasta_root_cert: &[u8],
+        repo_name: &str,
+    ) -> Result<Option<Value>, Error> {
+        let api_url = format!("{}/api/v1/repos/cray/{}", gitea_base_url, repo_name);
+
+        get_opt(&api_url, &[], gitea_token, shasta_root_cert).await
+    }
+
+    /// Get commit details. Returns None if the commit does not exists in the repository
+    pub async fn get_commit(
+        gitea_base_url: &str,
+        gitea_token: &str,
+        shasta_root_cert: &[u8],
+        repo_name: &str,
+        commit_id: &str,
+    ) -> Result<Option<Value>, Error> {
+        let api_url = format!(
+            "{}/api/v1/repos/cray/{}/git/commits/{}",
+            gitea_base_url, repo_name, commit_id
+        );
+
+        get_opt(&api_url, &[], gitea_token, shasta_root_cert).await
+    }
+
+    /// Get file metadata for a file in a repository at a specific git ref (commit id, branch or
+    /// tag). Returns None if the file does not exists at that ref
+    pub async fn get_file_metadata(
+        gitea_base_url: &str,
+        gitea_token: &str,
+        shasta_root_cert: &[u8],
+        repo_name: &str,
+        file_path: &str,
+        git_ref: &str,
+    ) -> Result<Option<Value>, Error> {
+        let mut api_url = reqwest::Url::parse(&format!(
+            "{}/api/v1/repos/cray/{}/contents",
+            gitea_base_url, repo_name
+        ))
+        .map_err(|error| Error::Message(error.to_string()))?;
+
+        // Each path segment is percent-encoded, '/' separators are kept
+        api_url
+            .path_segments_mut()
+            .map_err(|_| Error::Message(format!("Invalid Gitea URL '{}'", gitea_base_url)))?
+            .extend(
+                file_path
+                    .split('/')
+                    .filter(|path_segment| !path_segment.is_empty()),
+            );
+
+        get_opt(
+            api_url.as_str(),
+            &[("ref", git_ref)],
+            gitea_token,
+            shasta_root_cert,
+        )
+        .await
+    }
+
+    /// GET request to Gitea where a 404 is not an error but a missing resource
+    async fn get_opt(
+        api_url: &str,
+        query: &[(&str, &str)],
+        gitea_token: &str,
+        shasta_root_cert: &[u8],
+    ) -> Result<Option<Value>, Error> {
+        let client_builder = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(shasta_root_cert)?);
+
+        // Build client
+        let client = if let Ok(socks5_env) = std::env::var("SOCKS5") {
+            // socks5 proxy
+            log::debug!("SOCKS5 enabled");
+            let socks5proxy = reqwest::Proxy::all(socks5_env)?;
+
+            // rest client to authenticate
+            client_builder.proxy(socks5proxy).build()?
+        } else {
+            client_builder.build()?
+        };
+
+        log::debug!("Request to {}", api_url);
+
+        let response = client
+            .get(api_url)
+            .query(query)
+            .header("Authorization", format!("token {}", gitea_token))
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            Ok(None)
+        } else if response.status().is_success() {
+            response.json().await.map(Some).map_err(Error::NetError)
+        } else {
+            Err(Error::Message(response.text().await?))
+        }
+    }
+
     /// Get most commit id (sha) pointed by a branch
     pub async fn get_commit_pointed_by_branch(
         gitea_base_url: &str,
@@ -345,3 +448,41 @@ pub mod http_client {
             .await
     }
 }
+
+pub mod utils {
+    /// Get the repo name (without the organization) from a clone url
+    /// eg https://api-gw-service-nmn.local/vcs/cray/cos-config-management.git -->
+    /// cos-config-management
+    pub fn get_repo_name_from_url(repo_url: &str) -> &str {
+        repo_url
+            .trim_end_matches('/')
+            .trim_end_matches(".git")
+            .rsplit('/')
+            .next()
+            .unwrap_or(repo_url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::utils::get_repo_name_from_url;
+
+    #[test]
+    fn test_get_repo_name_from_url() {
+        assert_eq!(
+            get_repo_name_from_url(
+                "https://api-gw-service-nmn.local/vcs/cray/cos-config-management.git"
+            ),
+            "cos-config-management"
+        );
+        assert_eq!(
+            get_repo_name_from_url("https://api.cmn.alps.cscs.ch/vcs/cray/csm-config-management/"),
+            "csm-config-management"
+        );
+        assert_eq!(
+            get_repo_name_from_url("https://api-gw-service-nmn.local/vcs/cray/uan-config"),
+            "uan-config"
+        );
+        assert_eq!(get_repo_name_from_url("uan-config.git"), "uan-config");
+    }
+}
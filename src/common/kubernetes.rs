@@ -757,6 +757,139 @@ pub async fn get_cfs_session_container_teardown_logs_stream(
     get_container_logs_stream(teardown_container, cfs_session_pod, &pods_api).await
 }
 
+/// Get logs stream for the init container running kiwi-ng in the pod of an IMS 'create' job
+pub async fn get_ims_job_container_build_image_logs_stream(
+    client: kube::Client,
+    ims_job_kubernetes_job: &str,
+    ims_job_kubernetes_namespace: &str,
+) -> Result<Lines<impl AsyncBufReadExt>, Box<dyn Error + std::marker::Send + Sync>> {
+    let container_name = "build-image";
+
+    let pods_api: kube::Api<Pod> = kube::Api::namespaced(client, ims_job_kubernetes_namespace);
+
+    let params = kube::api::ListParams::default()
+        .limit(1)
+        .labels(format!("job-name={}", ims_job_kubernetes_job).as_str());
+
+    let mut pods = pods_api.list(&params).await?;
+
+    let mut i = 0;
+    let max = 30;
+    let delay_secs = 2;
+
+    // Waiting for pod to start
+    while pods.items.is_empty() && i <= max {
+        println!(
+            "Waiting k8s to create pod/container for IMS job '{}'. Trying again in {} secs. Attempt {} of {}",
+            ims_job_kubernetes_job,
+            delay_secs,
+            i + 1,
+            max
+        );
+        i += 1;
+        tokio::time::sleep(time::Duration::from_secs(delay_secs)).await;
+        pods = pods_api.list(&params).await?;
+    }
+
+    if pods.items.is_empty() {
+        return Err(format!(
+            "Pod for IMS job '{}' not created. Aborting operation.",
+            ims_job_kubernetes_job
+        )
+        .into());
+    }
+
+    let ims_job_pod = &pods.items[0].clone();
+
+    let ims_job_pod_name = ims_job_pod.metadata.name.clone().unwrap();
+    log::info!("Pod name: {}", ims_job_pod_name);
+
+    let build_image_container = ims_job_pod
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.init_containers.as_ref())
+        .and_then(|init_container_vec| {
+            init_container_vec
+                .iter()
+                .find(|container| container.name.eq(container_name))
+        })
+        .cloned()
+        .ok_or_else(|| {
+            format!(
+                "Init container '{}' not found in pod '{}'. Aborting operation.",
+                container_name, ims_job_pod_name
+            )
+        })?;
+
+    let mut container_status = get_init_container_status(ims_job_pod, &build_image_container.name);
+
+    let mut i = 0;
+    let max = 300;
+
+    // Waiting for container build-image to start, init containers before it fetch the recipe
+    // and wait for the package repositories
+    while (container_status.is_none() || container_status.as_ref().unwrap().waiting.is_some())
+        && i <= max
+    {
+        println!(
+            "Container ({}) status missing or 'waiting'. Checking again in 2 secs. Attempt {} of {}",
+            build_image_container.name,
+            i + 1,
+            max
+        );
+        i += 1;
+        tokio::time::sleep(time::Duration::from_secs(2)).await;
+        let pods = pods_api.list(&params).await?;
+        container_status = get_init_container_status(&pods.items[0], &build_image_container.name);
+        log::debug!("Container status:\n{:#?}", container_status);
+    }
+
+    if container_status.is_none() || container_status.as_ref().unwrap().waiting.is_some() {
+        return Err(format!(
+            "Container ({}) status is waiting. Aborting operation.",
+            build_image_container.name
+        )
+        .into());
+    }
+
+    get_init_container_logs_stream(&build_image_container, ims_job_pod, &pods_api).await
+}
+
+/// Print kiwi-ng logs of an IMS 'create' job till the image build finishes
+pub async fn print_ims_job_logs(
+    client: kube::Client,
+    ims_job_kubernetes_job: &str,
+    ims_job_kubernetes_namespace: &str,
+) -> Result<(), Box<dyn Error + std::marker::Send + Sync>> {
+    let mut logs_stream = get_ims_job_container_build_image_logs_stream(
+        client,
+        ims_job_kubernetes_job,
+        ims_job_kubernetes_namespace,
+    )
+    .await?;
+
+    while let Some(line) = logs_stream.try_next().await? {
+        println!("{}", line);
+    }
+
+    Ok(())
+}
+
+fn get_init_container_status(
+    pod: &k8s_openapi::api::core::v1::Pod,
+    container_name: &String,
+) -> Option<k8s_openapi::api::core::v1::ContainerState> {
+    pod.status
+        .as_ref()
+        .and_then(|status| status.init_container_statuses.as_ref())
+        .and_then(|status_vec| {
+            status_vec
+                .iter()
+                .find(|container_status| container_status.name.eq(container_name))
+        })
+        .and_then(|container_status| container_status.state.clone())
+}
+
 fn get_container_status(
     pod: &k8s_openapi::api::core::v1::Pod,
     container_name: &String,
@@ -1,10 +1,15 @@
 pub mod authentication;
 pub mod cluster_ops;
+pub mod cray_product_catalog;
 pub mod gitea;
 pub mod jwt_ops;
 pub mod kubernetes;
 // pub mod local_git_repo;
 pub mod csm;
 pub mod log_ops;
+pub mod ownership;
+pub mod site_profile;
+#[cfg(test)]
+pub(crate) mod test_fixtures;
 pub mod utils;
 pub mod vault;
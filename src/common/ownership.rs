@@ -0,0 +1,146 @@
+//! Standard ownership tags added to the CFS sessions, CFS components and IMS images created by
+//! mesa so we can tell who created a resource and from what without parsing its name.
+//!
+//! CFS sessions and components store them as `tags`, IMS images as `metadata`. Both are plain
+//! key/value string maps, hence the same keys are used everywhere.
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{common::jwt_ops, error::Error};
+
+pub const OWNER_TAG_KEY: &str = "mesa.owner";
+pub const HSM_GROUP_TAG_KEY: &str = "mesa.hsm_group";
+pub const SAT_FILE_TAG_KEY: &str = "mesa.sat_file";
+pub const GIT_COMMIT_TAG_KEY: &str = "mesa.git_commit";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct OwnershipTags {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hsm_group: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sat_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_commit: Option<String>,
+}
+
+impl OwnershipTags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ownership tags with the owner set to the `preferred_username` in the JWT token
+    pub fn from_jwt(shasta_token: &str) -> Result<Self, Error> {
+        let owner = jwt_ops::get_preferred_username(shasta_token)
+            .map_err(|error| Error::Message(error.to_string()))?;
+
+        Ok(Self::new().owner(&owner))
+    }
+
+    pub fn owner(mut self, owner: &str) -> Self {
+        self.owner = Some(owner.to_string());
+        self
+    }
+
+    pub fn hsm_group(mut self, hsm_group: &str) -> Self {
+        self.hsm_group = Some(hsm_group.to_string());
+        self
+    }
+
+    /// Name or path of the SAT file the resource was created from
+    pub fn sat_file(mut self, sat_file: &str) -> Self {
+        self.sat_file = Some(sat_file.to_string());
+        self
+    }
+
+    pub fn git_commit(mut self, git_commit: &str) -> Self {
+        self.git_commit = Some(git_commit.to_string());
+        self
+    }
+
+    /// Ownership tags found in a map of CFS tags or IMS metadata. Other keys are ignored
+    pub fn from_tags(tags: &HashMap<String, String>) -> Self {
+        Self {
+            owner: tags.get(OWNER_TAG_KEY).cloned(),
+            hsm_group: tags.get(HSM_GROUP_TAG_KEY).cloned(),
+            sat_file: tags.get(SAT_FILE_TAG_KEY).cloned(),
+            git_commit: tags.get(GIT_COMMIT_TAG_KEY).cloned(),
+        }
+    }
+
+    /// List of (key, value) for the ownership tags set
+    pub fn to_tag_vec(&self) -> Vec<(&'static str, String)> {
+        [
+            (OWNER_TAG_KEY, &self.owner),
+            (HSM_GROUP_TAG_KEY, &self.hsm_group),
+            (SAT_FILE_TAG_KEY, &self.sat_file),
+            (GIT_COMMIT_TAG_KEY, &self.git_commit),
+        ]
+        .into_iter()
+        .filter_map(|(key, value_opt)| value_opt.clone().map(|value| (key, value)))
+        .collect()
+    }
+
+    pub fn to_tags(&self) -> HashMap<String, String> {
+        self.to_tag_vec()
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect()
+    }
+
+    /// Adds the ownership tags to an existing map of tags, overriding ownership keys already
+    /// there
+    pub fn merge_into(&self, tags: &mut HashMap<String, String>) {
+        tags.extend(self.to_tags());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.to_tag_vec().is_empty()
+    }
+
+    /// True if every ownership tag set in `self` has the same value in `tags`. Empty ownership
+    /// tags match everything
+    pub fn matches(&self, tags_opt: Option<&HashMap<String, String>>) -> bool {
+        self.to_tag_vec()
+            .iter()
+            .all(|(key, value)| tags_opt.and_then(|tags| tags.get(*key)) == Some(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ownership_tags_round_trip() {
+        let ownership_tags = OwnershipTags::new()
+            .owner("alice")
+            .hsm_group("zinal")
+            .git_commit("1a2b3c4d");
+
+        let mut tags = HashMap::from([("other".to_string(), "value".to_string())]);
+        ownership_tags.merge_into(&mut tags);
+
+        assert_eq!(tags.len(), 4);
+        assert_eq!(OwnershipTags::from_tags(&tags), ownership_tags);
+    }
+
+    #[test]
+    fn test_ownership_tags_matches() {
+        let tags = OwnershipTags::new()
+            .owner("alice")
+            .hsm_group("zinal")
+            .to_tags();
+
+        assert!(OwnershipTags::new().matches(None));
+        assert!(OwnershipTags::new().owner("alice").matches(Some(&tags)));
+        assert!(!OwnershipTags::new().owner("bob").matches(Some(&tags)));
+        assert!(!OwnershipTags::new()
+            .owner("alice")
+            .sat_file("sat.yaml")
+            .matches(Some(&tags)));
+        assert!(!OwnershipTags::new().owner("alice").matches(None));
+    }
+}
@@ -0,0 +1,338 @@
+//! API versions supported by a CSM site. Different CSM releases ship different sets of APIs (BOS
+//! v1/v2, CFS v2/v3, PCS/CAPMC), the site profile is probed once per CSM API base url and cached
+//! so higher level functions can pick the right backend without the caller getting involved
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{cfs::session::mesa::model::CfsApiVersion, error::Error};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum BosApiVersion {
+    V1,
+    V2,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PowerBackend {
+    /// Power Control Service (CSM >= 1.3)
+    Pcs,
+    /// Cray Advanced Platform Monitoring and Control (deprecated in CSM 1.3, removed in CSM 1.5)
+    Capmc,
+}
+
+/// Endpoints used to check whether an API is available. A 2xx response means the API is served,
+/// anything else (including server and network errors) means it is not. The probe only fails if
+/// all endpoints reject the token
+const CFS_V3_ENDPOINT: &str = "/cfs/v3/options";
+const CFS_V2_ENDPOINT: &str = "/cfs/v2/options";
+const BOS_V2_ENDPOINT: &str = "/bos/v2/healthz";
+const BOS_V1_ENDPOINT: &str = "/bos/v1/version";
+const IMS_V3_ENDPOINT: &str = "/ims/v3/public-keys";
+const PCS_ENDPOINT: &str = "/power-control/v1/readiness";
+const CAPMC_ENDPOINT: &str = "/capmc/capmc/v1/health";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SiteProfile {
+    pub shasta_base_url: String,
+    /// Newest CFS API version available
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cfs_api_version: Option<CfsApiVersion>,
+    /// Newest BOS API version available
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bos_api_version: Option<BosApiVersion>,
+    pub ims_v3: bool,
+    /// PCS is preferred over CAPMC if both are available
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power_backend: Option<PowerBackend>,
+    pub probe_time: String,
+}
+
+impl SiteProfile {
+    /// Queries the CSM APIs to find out which API versions are available
+    pub async fn probe(
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+    ) -> Result<Self, Error> {
+        let client_builder = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(shasta_root_cert)?);
+
+        // Build client
+        let client = if let Ok(socks5_env) = std::env::var("SOCKS5") {
+            // socks5 proxy
+            log::debug!("SOCKS5 enabled");
+            let socks5proxy = reqwest::Proxy::all(socks5_env)?;
+
+            // rest client to authenticate
+            client_builder.proxy(socks5proxy).build()?
+        } else {
+            client_builder.build()?
+        };
+
+        let is_available = |endpoint: &'static str| {
+            is_endpoint_available(&client, shasta_token, shasta_base_url, endpoint)
+        };
+
+        let (cfs_v3, cfs_v2, bos_v2, bos_v1, ims_v3, pcs, capmc) = tokio::join!(
+            is_available(CFS_V3_ENDPOINT),
+            is_available(CFS_V2_ENDPOINT),
+            is_available(BOS_V2_ENDPOINT),
+            is_available(BOS_V1_ENDPOINT),
+            is_available(IMS_V3_ENDPOINT),
+            is_available(PCS_ENDPOINT),
+            is_available(CAPMC_ENDPOINT),
+        );
+
+        check_authorized(&[cfs_v3, cfs_v2, bos_v2, bos_v1, ims_v3, pcs, capmc])?;
+
+        let [cfs_v3, cfs_v2, bos_v2, bos_v1, ims_v3, pcs, capmc] =
+            [cfs_v3, cfs_v2, bos_v2, bos_v1, ims_v3, pcs, capmc]
+                .map(|endpoint_status| endpoint_status == EndpointStatus::Available);
+
+        let site_profile = Self {
+            shasta_base_url: shasta_base_url.to_string(),
+            cfs_api_version: pick(cfs_v3, CfsApiVersion::V3, cfs_v2, CfsApiVersion::V2),
+            bos_api_version: pick(bos_v2, BosApiVersion::V2, bos_v1, BosApiVersion::V1),
+            ims_v3,
+            power_backend: pick(pcs, PowerBackend::Pcs, capmc, PowerBackend::Capmc),
+            probe_time: chrono::Utc::now().to_rfc3339(),
+        };
+
+        log::info!("CSM site profile: {:?}", site_profile);
+
+        Ok(site_profile)
+    }
+
+    pub fn get_cfs_api_version(&self) -> Result<CfsApiVersion, Error> {
+        self.cfs_api_version
+            .ok_or_else(|| self.not_available_error("CFS"))
+    }
+
+    pub fn get_bos_api_version(&self) -> Result<BosApiVersion, Error> {
+        self.bos_api_version
+            .ok_or_else(|| self.not_available_error("BOS"))
+    }
+
+    pub fn get_power_backend(&self) -> Result<PowerBackend, Error> {
+        self.power_backend
+            .ok_or_else(|| self.not_available_error("PCS or CAPMC"))
+    }
+
+    fn not_available_error(&self, api_name: &str) -> Error {
+        Error::Message(format!(
+            "No supported {} API version found in '{}'",
+            api_name, self.shasta_base_url
+        ))
+    }
+}
+
+fn pick<T>(is_preferred: bool, preferred: T, is_fallback: bool, fallback: T) -> Option<T> {
+    if is_preferred {
+        Some(preferred)
+    } else if is_fallback {
+        Some(fallback)
+    } else {
+        None
+    }
+}
+
+/// Result of probing a CSM endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EndpointStatus {
+    Available,
+    NotAvailable,
+    /// 401 or 403, the token can't tell whether the API is available
+    Unauthorized,
+}
+
+async fn is_endpoint_available(
+    client: &reqwest::Client,
+    shasta_token: &str,
+    shasta_base_url: &str,
+    endpoint: &str,
+) -> EndpointStatus {
+    match client
+        .get(shasta_base_url.to_owned() + endpoint)
+        .bearer_auth(shasta_token)
+        .send()
+        .await
+    {
+        Ok(response) => endpoint_status(endpoint, response.status()),
+        Err(error) => {
+            log::warn!(
+                "Could not probe CSM endpoint '{}', treating it as not available. Reason:\n{}",
+                endpoint,
+                error
+            );
+            EndpointStatus::NotAvailable
+        }
+    }
+}
+
+fn endpoint_status(endpoint: &str, status: reqwest::StatusCode) -> EndpointStatus {
+    if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+        log::warn!(
+            "CSM endpoint '{}' rejected the token, status {}",
+            endpoint,
+            status
+        );
+        EndpointStatus::Unauthorized
+    } else if status.is_server_error() {
+        log::warn!(
+            "CSM endpoint '{}' failed, treating it as not available, status {}",
+            endpoint,
+            status
+        );
+        EndpointStatus::NotAvailable
+    } else if status.is_success() {
+        EndpointStatus::Available
+    } else {
+        log::debug!(
+            "CSM endpoint '{}' not available, status {}",
+            endpoint,
+            status
+        );
+        EndpointStatus::NotAvailable
+    }
+}
+
+/// Fails if every endpoint rejected the token, the site profile would say no API is available
+/// when the problem is the token
+fn check_authorized(endpoint_status_vec: &[EndpointStatus]) -> Result<(), Error> {
+    if endpoint_status_vec
+        .iter()
+        .all(|endpoint_status| *endpoint_status == EndpointStatus::Unauthorized)
+    {
+        return Err(Error::Message(
+            "Could not probe CSM APIs, all endpoints rejected the token".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn cache() -> &'static Mutex<HashMap<String, SiteProfile>> {
+    static SITE_PROFILE_CACHE: OnceLock<Mutex<HashMap<String, SiteProfile>>> = OnceLock::new();
+    SITE_PROFILE_CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Returns the site profile of the CSM site, probing the CSM APIs the first time it is called
+/// for a CSM API base url. Failed probes are not cached, next call probes again
+pub async fn get(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+) -> Result<SiteProfile, Error> {
+    if let Some(site_profile) = cache().lock().unwrap().get(shasta_base_url) {
+        return Ok(site_profile.clone());
+    }
+
+    let site_profile = SiteProfile::probe(shasta_token, shasta_base_url, shasta_root_cert).await?;
+
+    set(site_profile.clone());
+
+    Ok(site_profile)
+}
+
+/// Sets the site profile for a CSM site, eg to restore a profile saved from a previous run
+/// instead of probing the CSM APIs again
+pub fn set(site_profile: SiteProfile) {
+    cache()
+        .lock()
+        .unwrap()
+        .insert(site_profile.shasta_base_url.clone(), site_profile);
+}
+
+/// Forgets the site profile of a CSM site so it is probed again next time (eg after a CSM
+/// upgrade)
+pub fn invalidate(shasta_base_url: &str) {
+    cache().lock().unwrap().remove(shasta_base_url);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_prefers_newer_api() {
+        assert_eq!(
+            pick(true, BosApiVersion::V2, true, BosApiVersion::V1),
+            Some(BosApiVersion::V2)
+        );
+        assert_eq!(
+            pick(false, BosApiVersion::V2, true, BosApiVersion::V1),
+            Some(BosApiVersion::V1)
+        );
+        assert_eq!(
+            pick(false, PowerBackend::Pcs, false, PowerBackend::Capmc),
+            None
+        );
+    }
+
+    #[test]
+    fn test_endpoint_status() {
+        use reqwest::StatusCode;
+
+        let status = |status_code| endpoint_status(BOS_V2_ENDPOINT, status_code);
+
+        assert_eq!(status(StatusCode::OK), EndpointStatus::Available);
+        assert_eq!(status(StatusCode::NOT_FOUND), EndpointStatus::NotAvailable);
+        assert_eq!(
+            status(StatusCode::METHOD_NOT_ALLOWED),
+            EndpointStatus::NotAvailable
+        );
+        assert_eq!(
+            status(StatusCode::SERVICE_UNAVAILABLE),
+            EndpointStatus::NotAvailable
+        );
+        assert_eq!(
+            status(StatusCode::UNAUTHORIZED),
+            EndpointStatus::Unauthorized
+        );
+        assert_eq!(status(StatusCode::FORBIDDEN), EndpointStatus::Unauthorized);
+    }
+
+    #[test]
+    fn test_check_authorized() {
+        use EndpointStatus::*;
+
+        assert!(check_authorized(&[Unauthorized, Unauthorized]).is_err());
+        assert!(check_authorized(&[Unauthorized, NotAvailable]).is_ok());
+        assert!(check_authorized(&[Unauthorized, Available]).is_ok());
+        assert!(check_authorized(&[NotAvailable, NotAvailable]).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_cached_site_profile() {
+        let site_profile = SiteProfile {
+            shasta_base_url: "https://api.cached.site".to_string(),
+            cfs_api_version: Some(CfsApiVersion::V2),
+            bos_api_version: None,
+            ims_v3: true,
+            power_backend: Some(PowerBackend::Capmc),
+            probe_time: "2024-01-01T00:00:00Z".to_string(),
+        };
+
+        set(site_profile.clone());
+
+        // Cached profile is returned without calling CSM
+        assert_eq!(
+            get("token", "https://api.cached.site", &[]).await.unwrap(),
+            site_profile
+        );
+        assert!(site_profile.get_bos_api_version().is_err());
+
+        invalidate("https://api.cached.site");
+
+        assert!(cache()
+            .lock()
+            .unwrap()
+            .get("https://api.cached.site")
+            .is_none());
+    }
+}
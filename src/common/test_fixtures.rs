@@ -0,0 +1,67 @@
+//! Fixtures shared by unit tests. CSM payloads are built from JSON so they look like what the
+//! CSM APIs return
+
+use serde_json::json;
+
+use crate::{
+    bos::component::shasta::http_client::v2::Component,
+    cfs::{
+        component::shasta::r#struct::v2::ComponentResponse,
+        session::mesa::r#struct::v2::CfsSessionGetResponse,
+    },
+};
+
+/// CFS session with a session status, `succeeded` is "none", "true" or "false"
+pub fn cfs_session(
+    name: &str,
+    status: &str,
+    succeeded: &str,
+    start_time_opt: Option<&str>,
+) -> CfsSessionGetResponse {
+    serde_json::from_value(json!({
+        "name": name,
+        "status": {
+            "session": { "status": status, "succeeded": succeeded, "startTime": start_time_opt }
+        }
+    }))
+    .unwrap()
+}
+
+/// CFS component with a `retry_policy` of 3
+pub fn cfs_component(
+    xname: &str,
+    desired_config: &str,
+    enabled: bool,
+    configuration_status: &str,
+    error_count: u64,
+) -> ComponentResponse {
+    serde_json::from_value(json!({
+        "id": xname,
+        "desiredConfig": desired_config,
+        "enabled": enabled,
+        "configurationStatus": configuration_status,
+        "errorCount": error_count,
+        "retryPolicy": 3
+    }))
+    .unwrap()
+}
+
+/// BOS component, `session` is the BOS session managing the node and `last_updated_opt` the
+/// time of the last BOS action on the node
+pub fn bos_component(
+    xname: &str,
+    phase: &str,
+    status: &str,
+    error: &str,
+    session: &str,
+    last_updated_opt: Option<&str>,
+) -> Component {
+    serde_json::from_value(json!({
+        "id": xname,
+        "error": error,
+        "session": session,
+        "status": { "phase": phase, "status": status, "status_override": "" },
+        "last_action": { "action": "power_on", "last_updated": last_updated_opt }
+    }))
+    .unwrap()
+}
@@ -15,3 +15,17 @@ pub enum Error {
     #[error("ERROR - CSM: {0}")]
     CsmError(Value),
 }
+
+impl Error {
+    /// CSM APIs return a problem details payload with the http status code
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Error::CsmError(payload) => payload["status"].as_u64() == Some(404),
+            Error::Message(payload) => serde_json::from_str::<Value>(payload)
+                .map(|payload| payload["status"].as_u64() == Some(404))
+                .unwrap_or(false),
+            Error::NetError(error) => error.status() == Some(reqwest::StatusCode::NOT_FOUND),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,535 @@
+//! Portable IMS image bundles, used to move images between CSM systems (eg test to production)
+//! and to keep offline archives. A bundle is a directory with:
+//!
+//! - `bundle.json`: IMS image record, image manifest and the CFS configuration and session that
+//!   produced the image
+//! - `manifest.json`: IMS image manifest as stored in S3
+//! - the artifacts referenced by the manifest, eg `rootfs`, `kernel` and `initrd`
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cfs::{
+        self,
+        configuration::mesa::r#struct::{
+            cfs_configuration_request::v2::CfsConfigurationRequest,
+            cfs_configuration_response::v2::CfsConfigurationResponse,
+        },
+        session::mesa::model::CfsSession,
+    },
+    error::Error,
+    ims::{
+        self,
+        image::{
+            r#struct::{Image, ImageManifest, ImageManifestArtifact, ImageMetadataPatch},
+            utils::get_manifest_bytes,
+        },
+        import::{self, ImageFiles, INITRD_TYPE, KERNEL_TYPE, ROOTFS_SQUASHFS_TYPE},
+        s3::parse_s3_path,
+    },
+};
+
+pub const BUNDLE_VERSION: &str = "1";
+pub const BUNDLE_FILE_NAME: &str = "bundle.json";
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImageBundle {
+    pub version: String,
+    pub created: String,
+    /// IMS image record in the system the image was exported from
+    pub image: Image,
+    pub manifest: ImageManifest,
+    /// CFS configuration used to build the image, None if the image was not built by CFS
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cfs_configuration: Option<CfsConfigurationResponse>,
+    /// CFS session that built the image, None if the image was not built by CFS
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cfs_session: Option<CfsSession>,
+}
+
+impl ImageBundle {
+    /// Reads `bundle.json` from a bundle directory
+    pub fn load(bundle_dir: &Path) -> Result<Self, Error> {
+        let bundle_file = std::fs::File::open(bundle_dir.join(BUNDLE_FILE_NAME))?;
+
+        let bundle: Self = serde_json::from_reader(bundle_file)?;
+
+        if bundle.version != BUNDLE_VERSION {
+            return Err(Error::Message(format!(
+                "Bundle version '{}' not supported, expected version '{}'",
+                bundle.version, BUNDLE_VERSION
+            )));
+        }
+
+        Ok(bundle)
+    }
+
+    /// Writes `bundle.json` to a bundle directory
+    pub fn save(&self, bundle_dir: &Path) -> Result<(), Error> {
+        std::fs::create_dir_all(bundle_dir)?;
+
+        let bundle_file = std::fs::File::create(bundle_dir.join(BUNDLE_FILE_NAME))?;
+
+        serde_json::to_writer_pretty(bundle_file, self)?;
+
+        Ok(())
+    }
+
+    /// Local path of an artifact in the bundle directory
+    pub fn get_artifact_file_path(
+        bundle_dir: &Path,
+        artifact: &ImageManifestArtifact,
+    ) -> Result<PathBuf, Error> {
+        get_artifact_file_name(artifact).map(|file_name| bundle_dir.join(file_name))
+    }
+
+    pub fn get_artifact_by_type(&self, artifact_type: &str) -> Option<&ImageManifestArtifact> {
+        self.manifest
+            .artifacts
+            .iter()
+            .find(|artifact| artifact.r#type == artifact_type)
+    }
+
+    /// Checks the md5 checksum of each artifact in the bundle directory matches the manifest
+    pub async fn verify(&self, bundle_dir: &Path) -> Result<(), Error> {
+        for artifact in &self.manifest.artifacts {
+            let file_path = Self::get_artifact_file_path(bundle_dir, artifact)?;
+
+            verify_artifact_file(&file_path, artifact).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Artifacts are stored in the bundle with the name of their S3 object, eg
+/// 's3://boot-images/<image id>/rootfs' is stored as 'rootfs'
+fn get_artifact_file_name(artifact: &ImageManifestArtifact) -> Result<String, Error> {
+    parse_s3_path(&artifact.link.path)
+        .and_then(|(_, key)| key.rsplit('/').next())
+        .filter(|file_name| {
+            !file_name.is_empty()
+                && *file_name != BUNDLE_FILE_NAME
+                && *file_name != MANIFEST_FILE_NAME
+        })
+        .map(str::to_string)
+        .ok_or_else(|| {
+            Error::Message(format!(
+                "Artifact link '{}' is not a valid S3 path",
+                artifact.link.path
+            ))
+        })
+}
+
+async fn verify_artifact_file(
+    file_path: &Path,
+    artifact: &ImageManifestArtifact,
+) -> Result<(), Error> {
+    let file_path_aux = file_path.to_path_buf();
+
+    log::info!("Verify md5 checksum of '{}'", file_path.display());
+
+    let md5 = tokio::task::spawn_blocking(move || import::md5_file(&file_path_aux))
+        .await
+        .map_err(|error| Error::Message(error.to_string()))??;
+
+    if md5 != artifact.md5 {
+        return Err(Error::Message(format!(
+            "Artifact '{}' corrupted, md5 checksum '{}' does not match manifest md5 checksum '{}'",
+            file_path.display(),
+            md5,
+            artifact.md5
+        )));
+    }
+
+    if let Some(etag) = &artifact.link.etag {
+        // Part size used to upload the artifact is unknown, IMS and other clients use their own
+        import::check_etag(&artifact.link.path, etag, &md5, None)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the CFS session that built the image and the CFS configuration it used. Image
+/// provenance is informative, errors are logged and ignored
+async fn get_cfs_provenance(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    image_id: &str,
+) -> (Option<CfsSession>, Option<CfsConfigurationResponse>) {
+    let cfs_session_opt =
+        match cfs::session::mesa::model::get(shasta_token, shasta_base_url, shasta_root_cert, None)
+            .await
+        {
+            Ok(cfs_session_vec) => cfs_session_vec.into_iter().find(|cfs_session| {
+                cfs_session
+                    .get_result_id_vec()
+                    .iter()
+                    .any(|result_id| result_id == image_id)
+            }),
+            Err(error) => {
+                log::warn!(
+                    "Could not get CFS sessions to find the one that built image '{}'. Reason:\n{}",
+                    image_id,
+                    error
+                );
+                None
+            }
+        };
+
+    let Some(configuration_name) = cfs_session_opt
+        .as_ref()
+        .and_then(|cfs_session| cfs_session.configuration_name.clone())
+    else {
+        log::info!("Image '{}' was not built by a CFS session", image_id);
+        return (cfs_session_opt, None);
+    };
+
+    let cfs_configuration_opt = match cfs::configuration::mesa::http_client::get(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        Some(&configuration_name),
+    )
+    .await
+    {
+        Ok(cfs_configuration_vec) => cfs_configuration_vec.into_iter().next(),
+        Err(error) => {
+            log::warn!(
+                "Could not get CFS configuration '{}'. Reason:\n{}",
+                configuration_name,
+                error
+            );
+            None
+        }
+    };
+
+    (cfs_session_opt, cfs_configuration_opt)
+}
+
+/// Exports an IMS image to a bundle directory. Downloads the image manifest and every artifact
+/// it references, checks the etag in S3 and the md5 checksum of each artifact against the
+/// manifest
+pub async fn export(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    image_id: &str,
+    bundle_dir: &Path,
+) -> Result<ImageBundle, Error> {
+    let image = ims::image::mesa::http_client::get(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        Some(image_id),
+    )
+    .await?
+    .into_iter()
+    .next()
+    .ok_or_else(|| Error::Message(format!("IMS image '{}' not found", image_id)))?;
+
+    let sts_value = ims::s3::s3_auth(shasta_token, shasta_base_url, shasta_root_cert).await?;
+
+    let manifest_bytes = get_manifest_bytes(&sts_value, &image).await?;
+
+    let manifest: ImageManifest = serde_json::from_slice(&manifest_bytes)?;
+
+    // Artifacts are stored by name in the bundle directory, names must be unique
+    let mut artifact_file_name_set = HashSet::new();
+    for artifact in &manifest.artifacts {
+        let artifact_file_name = get_artifact_file_name(artifact)?;
+
+        if !artifact_file_name_set.insert(artifact_file_name.clone()) {
+            return Err(Error::Message(format!(
+                "More than one artifact named '{}' in manifest of IMS image '{}'",
+                artifact_file_name, image_id
+            )));
+        }
+    }
+
+    std::fs::create_dir_all(bundle_dir)?;
+    std::fs::write(bundle_dir.join(MANIFEST_FILE_NAME), &manifest_bytes)?;
+
+    for artifact in &manifest.artifacts {
+        let (bucket, key) = parse_s3_path(&artifact.link.path).unwrap_or_default();
+
+        let etag = ims::s3::s3_get_object_etag(&sts_value, key, bucket)
+            .await
+            .map_err(|error| {
+                Error::Message(format!(
+                    "Could not get etag of '{}'. Reason:\n{}",
+                    artifact.link.path, error
+                ))
+            })?;
+
+        if artifact
+            .link
+            .etag
+            .as_ref()
+            .is_some_and(|manifest_etag| *manifest_etag != etag)
+        {
+            return Err(Error::Message(format!(
+                "Artifact '{}' changed in S3, etag '{}' does not match manifest etag '{}'",
+                artifact.link.path,
+                etag,
+                artifact.link.etag.clone().unwrap_or_default()
+            )));
+        }
+
+        log::info!("Download '{}'", artifact.link.path);
+
+        ims::s3::s3_download_object(&sts_value, key, bucket, &bundle_dir.to_string_lossy())
+            .await
+            .map_err(|error| {
+                Error::Message(format!(
+                    "Could not download '{}'. Reason:\n{}",
+                    artifact.link.path, error
+                ))
+            })?;
+
+        verify_artifact_file(
+            &ImageBundle::get_artifact_file_path(bundle_dir, artifact)?,
+            artifact,
+        )
+        .await?;
+    }
+
+    let (cfs_session, cfs_configuration) =
+        get_cfs_provenance(shasta_token, shasta_base_url, shasta_root_cert, image_id).await;
+
+    let bundle = ImageBundle {
+        version: BUNDLE_VERSION.to_string(),
+        created: chrono::Utc::now().to_rfc3339(),
+        image,
+        manifest,
+        cfs_configuration,
+        cfs_session,
+    };
+
+    bundle.save(bundle_dir)?;
+
+    Ok(bundle)
+}
+
+/// Recreates an image from a bundle directory. Checks the artifacts checksums, imports the image
+/// with its metadata and optionally creates the CFS configuration that built it if it does not
+/// exist in the target system. The CFS session is only kept in the bundle as a record, running
+/// it again would build a new image. Image name defaults to the name in the bundle
+pub async fn import(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    bundle_dir: &Path,
+    image_name_opt: Option<&str>,
+    create_cfs_configuration: bool,
+) -> Result<Image, Error> {
+    let bundle = ImageBundle::load(bundle_dir)?;
+
+    bundle.verify(bundle_dir).await?;
+
+    let get_artifact_file_path = |artifact_type: &str| {
+        bundle
+            .get_artifact_by_type(artifact_type)
+            .ok_or_else(|| {
+                Error::Message(format!(
+                    "Artifact of type '{}' missing in bundle '{}'",
+                    artifact_type,
+                    bundle_dir.display()
+                ))
+            })
+            .and_then(|artifact| ImageBundle::get_artifact_file_path(bundle_dir, artifact))
+    };
+
+    let image_files = ImageFiles {
+        rootfs: get_artifact_file_path(ROOTFS_SQUASHFS_TYPE)?,
+        kernel: get_artifact_file_path(KERNEL_TYPE)?,
+        initrd: get_artifact_file_path(INITRD_TYPE)?,
+    };
+
+    for artifact in &bundle.manifest.artifacts {
+        if ![ROOTFS_SQUASHFS_TYPE, KERNEL_TYPE, INITRD_TYPE].contains(&artifact.r#type.as_str()) {
+            log::warn!(
+                "Artifact '{}' of type '{}' not imported",
+                artifact.link.path,
+                artifact.r#type
+            );
+        }
+    }
+
+    let mut image = import::import_image(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        image_name_opt.unwrap_or(&bundle.image.name),
+        &image_files,
+        bundle.image.arch.as_deref(),
+    )
+    .await?;
+
+    let image_id = image.id.clone().unwrap_or_default();
+
+    // Don't leave a half configured image behind, importing the bundle again would create a
+    // second image with the same name
+    if let Err(error) = set_up_imported_image(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        &bundle,
+        &image_id,
+        create_cfs_configuration,
+    )
+    .await
+    {
+        log::error!(
+            "Could not set up IMS image '{}' imported from bundle '{}', deleting it. Reason:\n{}",
+            image_id,
+            bundle_dir.display(),
+            error
+        );
+
+        if let Err(delete_error) = ims::image::shasta::http_client::delete(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &image_id,
+        )
+        .await
+        {
+            log::warn!(
+                "Could not delete IMS image '{}', delete it manually. Reason:\n{}",
+                image_id,
+                delete_error
+            );
+        }
+
+        return Err(error);
+    }
+
+    if let Some(metadata) = &bundle.image.metadata {
+        image.metadata = Some(metadata.clone());
+    }
+
+    Ok(image)
+}
+
+/// Sets the bundle metadata to the imported image and creates the CFS configuration that built
+/// it if requested
+async fn set_up_imported_image(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    bundle: &ImageBundle,
+    image_id: &str,
+    create_cfs_configuration: bool,
+) -> Result<(), Error> {
+    for (key, value) in bundle.image.metadata.iter().flatten() {
+        ims::image::shasta::http_client::patch_metadata(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            image_id,
+            &ImageMetadataPatch::Set {
+                key: key.clone(),
+                value: value.clone(),
+            },
+        )
+        .await?;
+    }
+
+    if let (true, Some(cfs_configuration)) = (create_cfs_configuration, &bundle.cfs_configuration) {
+        let cfs_configuration_exists = cfs::configuration::mesa::http_client::get(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            Some(&cfs_configuration.name),
+        )
+        .await
+        .is_ok_and(|cfs_configuration_vec| !cfs_configuration_vec.is_empty());
+
+        if cfs_configuration_exists {
+            log::info!(
+                "CFS configuration '{}' already exists, not created",
+                cfs_configuration.name
+            );
+        } else {
+            // Response and request payloads share the fields CFS needs to create a configuration
+            let cfs_configuration_request: CfsConfigurationRequest =
+                serde_json::from_value(serde_json::to_value(cfs_configuration)?)?;
+
+            cfs::configuration::mesa::http_client::put(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                &cfs_configuration_request,
+                &cfs_configuration.name,
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::ims::image::r#struct::Link;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bundle_save_load_and_verify() {
+        let bundle_dir = tempfile::tempdir().unwrap();
+
+        std::fs::File::create(bundle_dir.path().join("kernel"))
+            .unwrap()
+            .write_all(b"hello")
+            .unwrap();
+
+        let bundle = ImageBundle {
+            version: BUNDLE_VERSION.to_string(),
+            created: "2024-03-29T17:57:21+00:00".to_string(),
+            image: Image {
+                id: Some("6c644208-104a-473d-802c-410219026335".to_string()),
+                name: "compute".to_string(),
+                ..Default::default()
+            },
+            manifest: ImageManifest {
+                version: "1.0".to_string(),
+                created: "2024-03-29 17:57:21.183021".to_string(),
+                artifacts: vec![ImageManifestArtifact {
+                    link: Link {
+                        path: "s3://boot-images/6c644208-104a-473d-802c-410219026335/kernel"
+                            .to_string(),
+                        etag: Some("5d41402abc4b2a76b9719d911017c592".to_string()),
+                        r#type: "s3".to_string(),
+                    },
+                    md5: "5d41402abc4b2a76b9719d911017c592".to_string(),
+                    r#type: KERNEL_TYPE.to_string(),
+                }],
+            },
+            cfs_configuration: None,
+            cfs_session: None,
+        };
+
+        bundle.save(bundle_dir.path()).unwrap();
+
+        let bundle = ImageBundle::load(bundle_dir.path()).unwrap();
+
+        assert!(bundle.get_artifact_by_type(KERNEL_TYPE).is_some());
+        assert!(bundle.verify(bundle_dir.path()).await.is_ok());
+
+        // Corrupted artifact
+        std::fs::write(bundle_dir.path().join("kernel"), b"hellO").unwrap();
+
+        assert!(bundle.verify(bundle_dir.path()).await.is_err());
+    }
+}
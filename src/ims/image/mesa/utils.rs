@@ -1,6 +1,13 @@
 use serde_json::Value;
 
-use crate::ims::image::r#struct::{Image, ImsImageRecord2Update};
+use crate::{
+    common::ownership::OwnershipTags,
+    error::Error,
+    ims::{
+        self,
+        image::r#struct::{Image, ImageMetadataPatch, ImsImageRecord2Update},
+    },
+};
 
 /// Just sorts images by creation time in ascendent order
 pub async fn filter(image_vec: &mut [Image]) {
@@ -12,6 +19,60 @@ pub fn filter_by_image_id(image_vec: &mut Vec<Image>, image_id_vec: &[&str]) {
     image_vec.retain(|image| image_id_vec.contains(&image.id.as_ref().unwrap().as_str()));
 }
 
+/// Keeps the images whose metadata contains all the ownership tags provided
+pub fn filter_by_ownership_tags(image_vec: &mut Vec<Image>, ownership_tags: &OwnershipTags) {
+    image_vec.retain(|image| ownership_tags.matches(image.metadata.as_ref()));
+}
+
+/// Adds the mesa ownership tags to an IMS image metadata
+pub async fn set_ownership_tags(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    image_id: &str,
+    ownership_tags: &OwnershipTags,
+) -> Result<(), Error> {
+    for (key, value) in ownership_tags.to_tag_vec() {
+        ims::image::shasta::http_client::patch_metadata(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            image_id,
+            &ImageMetadataPatch::Set {
+                key: key.to_string(),
+                value,
+            },
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Returns the mesa ownership tags of an IMS image (who built it and from what)
+pub async fn get_ownership_tags(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    image_id: &str,
+) -> Result<OwnershipTags, Error> {
+    let image = ims::image::mesa::http_client::get(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        Some(image_id),
+    )
+    .await?
+    .pop()
+    .ok_or_else(|| Error::Message(format!("IMS image '{}' not found", image_id)))?;
+
+    Ok(image
+        .metadata
+        .as_ref()
+        .map(OwnershipTags::from_tags)
+        .unwrap_or_default())
+}
+
 /// update an IMS image record --> https://github.com/Cray-HPE/docs-csm/blob/release/1.5/api/ims.md#post_v2_image
 pub async fn update_image(
     shasta_token: &str,
@@ -1,5 +1,7 @@
 use serde_json::Value;
 
+use crate::{error::Error, ims::image::r#struct::ImageMetadataPatch};
+
 pub async fn get_raw(
     shasta_token: &str,
     shasta_base_url: &str,
@@ -80,6 +82,47 @@ pub async fn get_all(
     get(shasta_token, shasta_base_url, shasta_root_cert, None).await
 }
 
+/// Set or remove an IMS image metadata key. IMS only accepts one metadata operation per request
+pub async fn patch_metadata(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    image_id: &str,
+    metadata_patch: &ImageMetadataPatch,
+) -> Result<Value, Error> {
+    let client_builder = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(shasta_root_cert)?);
+
+    // Build client
+    let client = if let Ok(socks5_env) = std::env::var("SOCKS5") {
+        // socks5 proxy
+        log::debug!("SOCKS5 enabled");
+        let socks5proxy = reqwest::Proxy::all(socks5_env)?;
+
+        // rest client to authenticate
+        client_builder.proxy(socks5proxy).build()?
+    } else {
+        client_builder.build()?
+    };
+
+    let api_url = shasta_base_url.to_owned() + "/ims/v3/images/" + image_id;
+
+    let response = client
+        .patch(api_url)
+        .bearer_auth(shasta_token)
+        .json(&serde_json::json!({ "metadata": metadata_patch }))
+        .send()
+        .await
+        .map_err(Error::NetError)?;
+
+    if response.status().is_success() {
+        response.json().await.map_err(Error::NetError)
+    } else {
+        let payload = response.json::<Value>().await.map_err(Error::NetError)?;
+        Err(Error::CsmError(payload))
+    }
+}
+
 // Delete IMS image using CSM API. First does a "soft delete", then a "permanent deletion"
 // soft delete --> https://csm12-apidocs.svc.cscs.ch/paas/ims/operation/delete_v3_image/
 // permanent deletion --> https://csm12-apidocs.svc.cscs.ch/paas/ims/operation/delete_v3_deleted_image/
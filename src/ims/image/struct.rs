@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -26,4 +28,30 @@ pub struct Image {
     pub link: Option<Link>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub arch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+/// Operation on a single IMS image metadata key
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "operation", rename_all = "lowercase")]
+pub enum ImageMetadataPatch {
+    Set { key: String, value: String },
+    Remove { key: String },
+}
+
+/// Content of the `manifest.json` file IMS links images to, lists the image artifacts in S3
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ImageManifest {
+    pub version: String,
+    pub created: String,
+    pub artifacts: Vec<ImageManifestArtifact>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ImageManifestArtifact {
+    pub link: Link,
+    pub md5: String,
+    /// Artifact mime type, eg `application/vnd.cray.image.rootfs.squashfs`
+    pub r#type: String,
 }
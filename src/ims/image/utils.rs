@@ -3,8 +3,9 @@ use serde_json::Value;
 use crate::{
     bos,
     bss::bootparameters::http_client::get_raw,
+    error::Error,
     hsm::group::utils::get_member_vec_from_hsm_name_vec,
-    ims::{self, image::r#struct::Image, public_keys::http_client::v3::get},
+    ims::{self, image::r#struct::Image, public_keys::http_client::v3::get, s3::parse_s3_path},
 };
 
 // Get Image using fuzzy finder, meaning returns any image which name contains a specific
@@ -427,3 +428,32 @@ pub async fn get_single(
 
     None
 }
+
+/// Reads the S3 manifest an IMS image links to, as stored in S3
+pub(crate) async fn get_manifest_bytes(
+    sts_value: &serde_json::Value,
+    image: &Image,
+) -> Result<Vec<u8>, Error> {
+    let image_id = image.id.as_deref().unwrap_or_default();
+
+    let manifest_link = image
+        .link
+        .as_ref()
+        .ok_or_else(|| Error::Message(format!("IMS image '{}' has no S3 artifacts", image_id)))?;
+
+    let (manifest_bucket, manifest_key) = parse_s3_path(&manifest_link.path).ok_or_else(|| {
+        Error::Message(format!(
+            "IMS image '{}' link '{}' is not a valid S3 path",
+            image_id, manifest_link.path
+        ))
+    })?;
+
+    ims::s3::s3_get_object_bytes(sts_value, manifest_key, manifest_bucket)
+        .await
+        .map_err(|error| {
+            Error::Message(format!(
+                "Could not get manifest of IMS image '{}'. Reason:\n{}",
+                image_id, error
+            ))
+        })
+}
@@ -0,0 +1,562 @@
+//! Import images and recipes built outside CSM into IMS. Artifacts are uploaded to the same
+//! buckets and paths IMS jobs use so the result can't be told apart from an image or recipe
+//! built by IMS
+
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+use crate::{
+    error::Error,
+    ims::{
+        self,
+        image::r#struct::{
+            Image, ImageManifest, ImageManifestArtifact, ImsImageRecord2Update, Link,
+        },
+        recipe::r#struct::RecipeGetResponse,
+    },
+};
+
+pub const BOOT_IMAGES_BUCKET: &str = "boot-images";
+pub const IMS_BUCKET: &str = "ims";
+
+pub const IMAGE_MANIFEST_VERSION: &str = "1.0";
+
+pub const ROOTFS_SQUASHFS_TYPE: &str = "application/vnd.cray.image.rootfs.squashfs";
+pub const KERNEL_TYPE: &str = "application/vnd.cray.image.kernel";
+pub const INITRD_TYPE: &str = "application/vnd.cray.image.initrd";
+
+/// Files smaller than this are uploaded in a single request, S3 multipart uploads need parts of
+/// 5MB min
+const MULTIPART_UPLOAD_THRESHOLD: u64 = ims::s3::MULTIPART_CHUNK_SIZE;
+
+/// Local boot artifacts of an image
+#[derive(Debug, Clone)]
+pub struct ImageFiles {
+    /// squashfs root filesystem
+    pub rootfs: PathBuf,
+    pub kernel: PathBuf,
+    pub initrd: PathBuf,
+}
+
+/// md5 checksum of a local file in hex format. File is read in chunks, images are several GB
+pub fn md5_file(path: &Path) -> Result<String, Error> {
+    let mut file = File::open(path)?;
+
+    let mut context = md5::Context::new();
+    let mut buffer = vec![0; 1024 * 1024 * 8];
+
+    loop {
+        let bytes_read = file.read(&mut buffer)?;
+
+        if bytes_read == 0 {
+            break;
+        }
+
+        context.consume(&buffer[..bytes_read]);
+    }
+
+    Ok(format!("{:x}", context.compute()))
+}
+
+/// Etag S3 gives a local file uploaded in parts of `part_size` bytes: md5 checksum of the
+/// concatenated md5 checksums of each part, followed by the number of parts
+/// (eg '<hash>-<number of parts>')
+pub fn multipart_etag_file(path: &Path, part_size: u64) -> Result<String, Error> {
+    let mut file = File::open(path)?;
+
+    let mut part_md5_vec: Vec<u8> = Vec::new();
+    let mut part_count = 0;
+    let mut buffer = vec![0; 1024 * 1024];
+
+    loop {
+        let mut context = md5::Context::new();
+        let mut part_len = 0;
+
+        while part_len < part_size {
+            let read_len = (part_size - part_len).min(buffer.len() as u64) as usize;
+            let bytes_read = file.read(&mut buffer[..read_len])?;
+
+            if bytes_read == 0 {
+                break;
+            }
+
+            context.consume(&buffer[..bytes_read]);
+            part_len += bytes_read as u64;
+        }
+
+        if part_len == 0 {
+            break;
+        }
+
+        part_md5_vec.extend_from_slice(&context.compute().0);
+        part_count += 1;
+
+        if part_len < part_size {
+            break;
+        }
+    }
+
+    Ok(format!("{:x}-{}", md5::compute(&part_md5_vec), part_count))
+}
+
+/// Checks the file exists and is not empty, returns its size
+fn check_file(path: &Path) -> Result<u64, Error> {
+    let metadata = std::fs::metadata(path).map_err(|error| {
+        Error::Message(format!(
+            "Could not read '{}'. Reason:\n{}",
+            path.display(),
+            error
+        ))
+    })?;
+
+    if !metadata.is_file() || metadata.len() == 0 {
+        return Err(Error::Message(format!(
+            "'{}' is not a file or is empty",
+            path.display()
+        )));
+    }
+
+    Ok(metadata.len())
+}
+
+/// Checks the first bytes of a file identify its format
+fn check_magic_number(path: &Path, magic_number: &[u8], file_format: &str) -> Result<(), Error> {
+    let mut file_magic_number = vec![0; magic_number.len()];
+    File::open(path)?.read_exact(&mut file_magic_number)?;
+
+    if file_magic_number != magic_number {
+        return Err(Error::Message(format!(
+            "'{}' is not a {}",
+            path.display(),
+            file_format
+        )));
+    }
+
+    Ok(())
+}
+
+/// md5 calculation blocks, run it out of the async runtime
+async fn md5_file_async(path: &Path) -> Result<String, Error> {
+    let path = path.to_path_buf();
+
+    log::info!("Calculate md5 checksum of '{}'", path.display());
+
+    tokio::task::spawn_blocking(move || md5_file(&path))
+        .await
+        .map_err(|error| Error::Message(error.to_string()))?
+}
+
+/// S3 returns etags surrounded by double quotes, IMS stores them without
+fn trim_etag(etag: &str) -> String {
+    etag.trim_matches('"').to_string()
+}
+
+/// The etag of an object uploaded in a single request is its md5 checksum. Etags of multipart
+/// uploads (eg '<hash>-<number of parts>') are compared with `multipart_etag_opt`, the etag
+/// expected for the file (see `multipart_etag_file`). Multipart etags are not checked if the
+/// part size used to upload the file is unknown and `multipart_etag_opt` is None
+pub fn check_etag(
+    object_path: &str,
+    etag: &str,
+    md5: &str,
+    multipart_etag_opt: Option<&str>,
+) -> Result<(), Error> {
+    match (etag.contains('-'), multipart_etag_opt) {
+        (false, _) if etag != md5 => Err(Error::Message(format!(
+            "Object '{}' corrupted during upload, etag '{}' does not match md5 checksum '{}'",
+            object_path, etag, md5
+        ))),
+        (true, Some(multipart_etag)) if etag != multipart_etag => Err(Error::Message(format!(
+            "Object '{}' corrupted during upload, etag '{}' does not match multipart etag '{}'",
+            object_path, etag, multipart_etag
+        ))),
+        (true, None) => {
+            log::debug!(
+                "Object '{}' uploaded in parts of unknown size, etag '{}' not checked",
+                object_path,
+                etag
+            );
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Uploads a local file to S3, returns the etag
+async fn upload_file(
+    sts_value: &Value,
+    object_path: &str,
+    bucket: &str,
+    file_path: &Path,
+    md5: &str,
+) -> Result<String, Error> {
+    let file_size = check_file(file_path)?;
+
+    log::info!(
+        "Upload '{}' to 's3://{}/{}'",
+        file_path.display(),
+        bucket,
+        object_path
+    );
+
+    let (etag_rslt, multipart_etag_opt) = if file_size < MULTIPART_UPLOAD_THRESHOLD {
+        let content = std::fs::read(file_path)?;
+        (
+            ims::s3::s3_put_object_bytes(sts_value, object_path, bucket, content).await,
+            None,
+        )
+    } else {
+        // multipart etag calculation blocks, run it out of the async runtime
+        let file_path_aux = file_path.to_path_buf();
+        let multipart_etag = tokio::task::spawn_blocking(move || {
+            multipart_etag_file(&file_path_aux, ims::s3::MULTIPART_CHUNK_SIZE)
+        })
+        .await
+        .map_err(|error| Error::Message(error.to_string()))??;
+
+        (
+            ims::s3::s3_multipart_upload_object(
+                sts_value,
+                object_path,
+                bucket,
+                &file_path.to_string_lossy(),
+            )
+            .await,
+            Some(multipart_etag),
+        )
+    };
+
+    let etag = trim_etag(&etag_rslt.map_err(|error| {
+        Error::Message(format!(
+            "Could not upload '{}' to 's3://{}/{}'. Reason:\n{}",
+            file_path.display(),
+            bucket,
+            object_path,
+            error
+        ))
+    })?);
+
+    check_etag(object_path, &etag, md5, multipart_etag_opt.as_deref())?;
+
+    Ok(etag)
+}
+
+/// Uploads image artifacts and manifest.json to S3, returns the link to the manifest
+async fn upload_image_artifacts(
+    sts_value: &Value,
+    image_id: &str,
+    artifact_vec: &[(&Path, &str, &str, String)],
+) -> Result<Link, Error> {
+    let mut manifest_artifact_vec = Vec::new();
+
+    for (file_path, artifact_name, artifact_type, md5) in artifact_vec {
+        let object_path = format!("{}/{}", image_id, artifact_name);
+
+        let etag = upload_file(sts_value, &object_path, BOOT_IMAGES_BUCKET, file_path, md5).await?;
+
+        manifest_artifact_vec.push(ImageManifestArtifact {
+            link: Link {
+                path: format!("s3://{}/{}", BOOT_IMAGES_BUCKET, object_path),
+                etag: Some(etag),
+                r#type: "s3".to_string(),
+            },
+            md5: md5.clone(),
+            r#type: artifact_type.to_string(),
+        });
+    }
+
+    let manifest = ImageManifest {
+        version: IMAGE_MANIFEST_VERSION.to_string(),
+        created: chrono::Utc::now()
+            .format("%Y-%m-%d %H:%M:%S%.6f")
+            .to_string(),
+        artifacts: manifest_artifact_vec,
+    };
+
+    let manifest_path = format!("{}/manifest.json", image_id);
+
+    let manifest_etag = ims::s3::s3_put_object_bytes(
+        sts_value,
+        &manifest_path,
+        BOOT_IMAGES_BUCKET,
+        serde_json::to_vec_pretty(&manifest)?,
+    )
+    .await
+    .map_err(|error| {
+        Error::Message(format!(
+            "Could not upload image manifest to 's3://{}/{}'. Reason:\n{}",
+            BOOT_IMAGES_BUCKET, manifest_path, error
+        ))
+    })?;
+
+    Ok(Link {
+        path: format!("s3://{}/{}", BOOT_IMAGES_BUCKET, manifest_path),
+        etag: Some(trim_etag(&manifest_etag)),
+        r#type: "s3".to_string(),
+    })
+}
+
+/// Import an image from its local boot artifacts. Creates the IMS image record, uploads the
+/// artifacts and manifest.json to S3 and links the image to the manifest. The IMS image record
+/// is deleted if the artifacts could not be uploaded
+pub async fn import_image(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    image_name: &str,
+    image_files: &ImageFiles,
+    arch_opt: Option<&str>,
+) -> Result<Image, Error> {
+    // Validate all files before creating anything in CSM
+    let mut artifact_vec = Vec::new();
+
+    for (file_path, artifact_name, artifact_type) in [
+        (image_files.rootfs.as_path(), "rootfs", ROOTFS_SQUASHFS_TYPE),
+        (image_files.kernel.as_path(), "kernel", KERNEL_TYPE),
+        (image_files.initrd.as_path(), "initrd", INITRD_TYPE),
+    ] {
+        check_file(file_path)?;
+
+        if artifact_name == "rootfs" {
+            check_magic_number(file_path, b"hsqs", "squashfs filesystem")?;
+        }
+
+        let md5 = md5_file_async(file_path).await?;
+
+        artifact_vec.push((file_path, artifact_name, artifact_type, md5));
+    }
+
+    let sts_value = ims::s3::s3_auth(shasta_token, shasta_base_url, shasta_root_cert).await?;
+
+    let image_value = ims::image::utils::register_new_image(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        &Image {
+            name: image_name.to_string(),
+            arch: arch_opt.map(str::to_string),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    let image_id = image_value["id"]
+        .as_str()
+        .ok_or_else(|| {
+            Error::Message("IMS did not return the id of the image created".to_string())
+        })?
+        .to_string();
+
+    log::info!("IMS image '{}' ({}) created", image_name, image_id);
+
+    let link_rslt = match upload_image_artifacts(&sts_value, &image_id, &artifact_vec).await {
+        Ok(link) => ims::image::mesa::utils::update_image(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &image_id,
+            &ImsImageRecord2Update {
+                link,
+                arch: arch_opt.map(str::to_string),
+            },
+        )
+        .await
+        .map_err(Error::NetError),
+        Err(error) => Err(error),
+    };
+
+    if let Err(error) = link_rslt {
+        log::warn!(
+            "Import of image '{}' failed, deleting IMS image '{}'. Artifacts already uploaded to 's3://{}/{}/' need to be removed manually",
+            image_name,
+            image_id,
+            BOOT_IMAGES_BUCKET,
+            image_id
+        );
+
+        if let Err(delete_error) = ims::image::shasta::http_client::delete(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &image_id,
+        )
+        .await
+        {
+            log::error!(
+                "Could not delete IMS image '{}'. Reason:\n{}",
+                image_id,
+                delete_error
+            );
+        }
+
+        return Err(error);
+    }
+
+    ims::image::mesa::http_client::get(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        Some(&image_id),
+    )
+    .await?
+    .into_iter()
+    .next()
+    .ok_or_else(|| Error::Message(format!("IMS image '{}' not found", image_id)))
+}
+
+/// Import a recipe from a local tarball (eg a kiwi-ng description packed as recipe.tar.gz).
+/// Creates the IMS recipe record, uploads the tarball to S3 and links the recipe to it. Unlike
+/// images, IMS recipes link straight to the archive, there is no manifest
+pub async fn import_recipe(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    recipe_name: &str,
+    recipe_type: &str,
+    linux_distribution: &str,
+    recipe_file_path: &Path,
+) -> Result<RecipeGetResponse, Error> {
+    check_file(recipe_file_path)?;
+
+    // IMS expects a gzip compressed tarball
+    check_magic_number(recipe_file_path, &[0x1f, 0x8b], "gzip compressed tarball")?;
+
+    let md5 = md5_file_async(recipe_file_path).await?;
+
+    let sts_value = ims::s3::s3_auth(shasta_token, shasta_base_url, shasta_root_cert).await?;
+
+    let recipe = ims::recipe::http_client::post(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        &RecipeGetResponse {
+            recipe_type: recipe_type.to_string(),
+            linux_distribution: linux_distribution.to_string(),
+            name: recipe_name.to_string(),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    let recipe_id = recipe.id.clone().ok_or_else(|| {
+        Error::Message("IMS did not return the id of the recipe created".to_string())
+    })?;
+
+    log::info!("IMS recipe '{}' ({}) created", recipe_name, recipe_id);
+
+    let object_path = format!("recipes/{}/recipe.tar.gz", recipe_id);
+
+    let recipe_rslt =
+        match upload_file(&sts_value, &object_path, IMS_BUCKET, recipe_file_path, &md5).await {
+            Ok(etag) => ims::recipe::http_client::patch_link(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                &recipe_id,
+                &ims::recipe::r#struct::Link {
+                    path: format!("s3://{}/{}", IMS_BUCKET, object_path),
+                    etag: Some(etag),
+                    r#type: "s3".to_string(),
+                },
+            )
+            .await
+            .map_err(Error::NetError),
+            Err(error) => Err(error),
+        };
+
+    if let Err(error) = recipe_rslt {
+        log::warn!(
+            "Import of recipe '{}' failed, deleting IMS recipe '{}'. Archive already uploaded to 's3://{}/{}' needs to be removed manually",
+            recipe_name,
+            recipe_id,
+            IMS_BUCKET,
+            object_path
+        );
+
+        if let Err(delete_error) = ims::recipe::http_client::delete(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &recipe_id,
+        )
+        .await
+        {
+            log::error!(
+                "Could not delete IMS recipe '{}'. Reason:\n{}",
+                recipe_id,
+                delete_error
+            );
+        }
+
+        return Err(error);
+    }
+
+    recipe_rslt
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_md5_file_and_check_etag() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"hello").unwrap();
+
+        let md5 = md5_file(file.path()).unwrap();
+
+        assert_eq!(md5, "5d41402abc4b2a76b9719d911017c592");
+        assert_eq!(
+            trim_etag("\"5d41402abc4b2a76b9719d911017c592\""),
+            "5d41402abc4b2a76b9719d911017c592"
+        );
+
+        assert!(check_etag("id/kernel", "5d41402abc4b2a76b9719d911017c592", &md5, None).is_ok());
+        assert!(check_etag("id/kernel", "d41d8cd98f00b204e9800998ecf8427e", &md5, None).is_err());
+        // Multipart upload etags are not md5 checksums, they can't be checked without the part
+        // size
+        assert!(check_etag(
+            "id/rootfs",
+            "9b2cf535f27731c974343645a3985328-3",
+            &md5,
+            None
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_multipart_etag_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"hello").unwrap();
+
+        // Parts 'he', 'll' and 'o'
+        let multipart_etag = multipart_etag_file(file.path(), 2).unwrap();
+
+        assert_eq!(multipart_etag, "75994d598838ab475c86e3140adc14c7-3");
+
+        assert!(check_etag(
+            "id/rootfs",
+            "75994d598838ab475c86e3140adc14c7-3",
+            "5d41402abc4b2a76b9719d911017c592",
+            Some(&multipart_etag)
+        )
+        .is_ok());
+        assert!(check_etag(
+            "id/rootfs",
+            "9b2cf535f27731c974343645a3985328-3",
+            "5d41402abc4b2a76b9719d911017c592",
+            Some(&multipart_etag)
+        )
+        .is_err());
+
+        // Part size multiple of the file size, no empty last part
+        assert!(multipart_etag_file(file.path(), 5).unwrap().ends_with("-1"));
+    }
+}
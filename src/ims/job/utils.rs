@@ -1,8 +1,18 @@
-use std::io::{self, Write};
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
 
 use serde_json::Value;
+use tokio::time::Instant;
 
-use crate::ims::{self};
+use crate::{
+    error::Error,
+    ims::{self},
+};
+
+/// IMS job statuses after which IMS does nothing else with the job
+const IMS_JOB_FINISHED_STATUS_VEC: [&str; 2] = ["success", "error"];
 
 /// Wait an IMS job to finish
 pub async fn wait_ims_job_to_finish(
@@ -51,3 +61,69 @@ pub async fn wait_ims_job_to_finish(
         }
     }
 }
+
+/// Waits an IMS job to finish and returns it. Unlike `wait_ims_job_to_finish`, fails if IMS can't
+/// be queried or if the IMS job did not finish before `timeout`
+pub async fn wait_ims_job_to_complete(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    ims_job_id: &str,
+    timeout: Duration,
+) -> Result<Value, Error> {
+    let start = Instant::now();
+
+    loop {
+        let ims_job: Value = ims::job::http_client::get(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            Some(ims_job_id),
+        )
+        .await?;
+
+        let ims_job_status = ims_job["status"].as_str().unwrap_or_default();
+
+        if is_finished(ims_job_status) {
+            log::info!(
+                "IMS job '{}' finished with job status '{}'",
+                ims_job_id,
+                ims_job_status
+            );
+
+            return Ok(ims_job);
+        }
+
+        if start.elapsed() >= timeout {
+            return Err(Error::Message(format!(
+                "Timed out waiting IMS job '{}' to finish after {:?}, last job status '{}'",
+                ims_job_id, timeout, ims_job_status
+            )));
+        }
+
+        log::info!(
+            "Waiting IMS job '{}' with job status '{}'. Checking again in 2 secs",
+            ims_job_id,
+            ims_job_status
+        );
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+    }
+}
+
+fn is_finished(ims_job_status: &str) -> bool {
+    IMS_JOB_FINISHED_STATUS_VEC.contains(&ims_job_status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_finished() {
+        assert!(is_finished("success"));
+        assert!(is_finished("error"));
+        assert!(!is_finished("waiting_image"));
+        assert!(!is_finished(""));
+    }
+}
@@ -1,4 +1,6 @@
+pub mod bundle;
 pub mod image;
+pub mod import;
 pub mod job;
 pub mod public_keys;
 pub mod recipe;
@@ -50,5 +50,46 @@ pub mod http_client {
 
             Ok(public_key_value_list.to_vec())
         }
+
+        /// Create IMS public key ref --> https://apidocs.svc.cscs.ch/paas/ims/operation/post_v3_public_key/
+        pub async fn post(
+            shasta_token: &str,
+            shasta_base_url: &str,
+            shasta_root_cert: &[u8],
+            name: &str,
+            public_key: &str,
+        ) -> Result<Value, reqwest::Error> {
+            let client;
+
+            let client_builder = reqwest::Client::builder()
+                .add_root_certificate(reqwest::Certificate::from_pem(shasta_root_cert)?);
+
+            // Build client
+            if std::env::var("SOCKS5").is_ok() {
+                // socks5 proxy
+                log::debug!("SOCKS5 enabled");
+                let socks5proxy = reqwest::Proxy::all(std::env::var("SOCKS5").unwrap())?;
+
+                // rest client to authenticate
+                client = client_builder.proxy(socks5proxy).build()?;
+            } else {
+                client = client_builder.build()?;
+            }
+
+            let api_url = shasta_base_url.to_owned() + "/ims/v3/public-keys";
+
+            client
+                .post(api_url)
+                .bearer_auth(shasta_token)
+                .json(&serde_json::json!({
+                    "name": name,
+                    "public_key": public_key,
+                }))
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await
+        }
     }
 }
@@ -1,4 +1,4 @@
-use super::r#struct::RecipeGetResponse;
+use super::r#struct::{Link, RecipeGetResponse};
 
 /// Create IMS job ref --> https://csm12-apidocs.svc.cscs.ch/paas/ims/operation/post_v3_job/
 pub async fn get(
@@ -41,3 +41,124 @@ pub async fn get(
 
     Ok(response)
 }
+
+/// Register a new recipe in IMS ref --> https://csm12-apidocs.svc.cscs.ch/paas/ims/operation/post_v2_recipe/
+pub async fn post(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    recipe: &RecipeGetResponse,
+) -> Result<RecipeGetResponse, reqwest::Error> {
+    let client;
+
+    let client_builder = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(shasta_root_cert)?);
+
+    // Build client
+    if std::env::var("SOCKS5").is_ok() {
+        // socks5 proxy
+        log::debug!("SOCKS5 enabled");
+        let socks5proxy = reqwest::Proxy::all(std::env::var("SOCKS5").unwrap())?;
+
+        // rest client to authenticate
+        client = client_builder.proxy(socks5proxy).build()?;
+    } else {
+        client = client_builder.build()?;
+    }
+
+    let api_url = shasta_base_url.to_owned() + "/ims/v2/recipes";
+
+    client
+        .post(api_url)
+        .bearer_auth(shasta_token)
+        .json(recipe)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<RecipeGetResponse>()
+        .await
+}
+
+/// Update the link of an IMS recipe to its archive in S3 ref --> https://csm12-apidocs.svc.cscs.ch/paas/ims/operation/patch_v2_recipe/
+pub async fn patch_link(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    recipe_id: &str,
+    link: &Link,
+) -> Result<RecipeGetResponse, reqwest::Error> {
+    let client;
+
+    let client_builder = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(shasta_root_cert)?);
+
+    // Build client
+    if std::env::var("SOCKS5").is_ok() {
+        // socks5 proxy
+        log::debug!("SOCKS5 enabled");
+        let socks5proxy = reqwest::Proxy::all(std::env::var("SOCKS5").unwrap())?;
+
+        // rest client to authenticate
+        client = client_builder.proxy(socks5proxy).build()?;
+    } else {
+        client = client_builder.build()?;
+    }
+
+    let api_url = shasta_base_url.to_owned() + "/ims/v2/recipes/" + recipe_id;
+
+    client
+        .patch(api_url)
+        .bearer_auth(shasta_token)
+        .json(&serde_json::json!({ "link": link }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<RecipeGetResponse>()
+        .await
+}
+
+/// Delete an IMS recipe, the recipe is soft deleted first and then permanently deleted ref --> https://csm12-apidocs.svc.cscs.ch/paas/ims/operation/delete_v3_recipe/
+pub async fn delete(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    recipe_id: &str,
+) -> Result<(), reqwest::Error> {
+    let client;
+
+    let client_builder = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(shasta_root_cert)?);
+
+    // Build client
+    if std::env::var("SOCKS5").is_ok() {
+        // socks5 proxy
+        log::debug!("SOCKS5 enabled");
+        let socks5proxy = reqwest::Proxy::all(std::env::var("SOCKS5").unwrap())?;
+
+        // rest client to authenticate
+        client = client_builder.proxy(socks5proxy).build()?;
+    } else {
+        client = client_builder.build()?;
+    }
+
+    // SOFT DELETION
+    let api_url = shasta_base_url.to_owned() + "/ims/v3/recipes/" + recipe_id;
+
+    client
+        .delete(api_url)
+        .bearer_auth(shasta_token)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    // PERMANENT DELETION
+    let api_url = shasta_base_url.to_owned() + "/ims/v3/deleted/recipes/" + recipe_id;
+
+    client
+        .delete(api_url)
+        .bearer_auth(shasta_token)
+        .send()
+        .await?
+        .error_for_status()
+        .map(|_| ())
+}
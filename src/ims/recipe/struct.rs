@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Link {
     pub path: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -8,7 +8,7 @@ pub struct Link {
     pub r#type: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct RecipeGetResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<String>,
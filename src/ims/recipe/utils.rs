@@ -0,0 +1,318 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    common::kubernetes,
+    error::Error,
+    ims::{
+        self,
+        image::{
+            r#struct::{Image, ImageManifest},
+            utils::get_manifest_bytes,
+        },
+        job::{r#struct::JobPostRequest, utils::wait_ims_job_to_complete},
+    },
+};
+
+use super::r#struct::RecipeGetResponse;
+
+/// Max time to wait for the IMS job building an image from a recipe
+const IMS_JOB_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// IMS public key injected in the image build environment
+pub enum PublicKeySelector<'a> {
+    /// Use existing public key by id
+    Id(&'a str),
+    /// Use existing public key by name
+    Name(&'a str),
+    /// Use existing public key with same name and content, create it if missing
+    Create { name: &'a str, public_key: &'a str },
+}
+
+/// IMS image built from a recipe with its S3 manifest, the manifest lists the image artifacts
+/// (rootfs, kernel, initrd) with their S3 path and md5 checksum
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecipeBuild {
+    pub image: Image,
+    pub manifest: ImageManifest,
+}
+
+/// Get an IMS recipe by id or name. Fails if the name matches more than one recipe
+pub async fn get_by_name_or_id(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    recipe_name_or_id: &str,
+) -> Result<RecipeGetResponse, Error> {
+    let recipe_vec =
+        super::http_client::get(shasta_token, shasta_base_url, shasta_root_cert, None).await?;
+
+    select_by_name_or_id(recipe_vec, recipe_name_or_id)
+}
+
+/// Picks the recipe with id `recipe_name_or_id`, otherwise the only recipe with that name
+fn select_by_name_or_id(
+    recipe_vec: Vec<RecipeGetResponse>,
+    recipe_name_or_id: &str,
+) -> Result<RecipeGetResponse, Error> {
+    let (recipe_id_vec, recipe_name_vec): (Vec<RecipeGetResponse>, Vec<RecipeGetResponse>) =
+        recipe_vec
+            .into_iter()
+            .filter(|recipe| {
+                recipe.id.as_deref() == Some(recipe_name_or_id) || recipe.name == recipe_name_or_id
+            })
+            .partition(|recipe| recipe.id.as_deref() == Some(recipe_name_or_id));
+
+    if let Some(recipe) = recipe_id_vec.into_iter().next() {
+        return Ok(recipe);
+    }
+
+    match recipe_name_vec.len() {
+        0 => Err(Error::Message(format!(
+            "IMS recipe '{}' not found",
+            recipe_name_or_id
+        ))),
+        1 => Ok(recipe_name_vec.into_iter().next().unwrap()),
+        _ => Err(Error::Message(format!(
+            "More than one IMS recipe named '{}', use the recipe id instead. Recipes found:\n{}",
+            recipe_name_or_id,
+            recipe_name_vec
+                .iter()
+                .map(|recipe| recipe.id.clone().unwrap_or_default())
+                .collect::<Vec<String>>()
+                .join("\n")
+        ))),
+    }
+}
+
+/// Returns the id of the IMS public key, creating it if requested
+pub async fn get_or_create_public_key_id(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    public_key_selector: &PublicKeySelector<'_>,
+) -> Result<String, Error> {
+    let public_key_value_vec = ims::public_keys::http_client::v3::get(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        None,
+    )
+    .await?;
+
+    let public_key_value_opt =
+        public_key_value_vec
+            .iter()
+            .find(|public_key_value| match public_key_selector {
+                PublicKeySelector::Id(id) => public_key_value["id"].as_str() == Some(id),
+                PublicKeySelector::Name(name) => public_key_value["name"].as_str() == Some(name),
+                PublicKeySelector::Create { name, public_key } => {
+                    public_key_value["name"].as_str() == Some(name)
+                        && public_key_value["public_key"].as_str().map(str::trim)
+                            == Some(public_key.trim())
+                }
+            });
+
+    if let Some(public_key_id) = public_key_value_opt.and_then(|value| value["id"].as_str()) {
+        return Ok(public_key_id.to_string());
+    }
+
+    match public_key_selector {
+        PublicKeySelector::Id(value) | PublicKeySelector::Name(value) => Err(Error::Message(
+            format!("IMS public key '{}' not found", value),
+        )),
+        PublicKeySelector::Create { name, public_key } => {
+            log::info!("Create IMS public key '{}'", name);
+
+            let public_key_value = ims::public_keys::http_client::v3::post(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                name,
+                public_key,
+            )
+            .await?;
+
+            public_key_value["id"]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| {
+                    Error::Message(format!(
+                        "IMS did not return the id of the public key '{}' created",
+                        name
+                    ))
+                })
+        }
+    }
+}
+
+/// Builds an image from an IMS recipe. Creates an IMS job of type 'create', streams kiwi-ng
+/// build logs if a k8s client is provided and waits for the job to finish.
+/// Returns the IMS image created with its S3 manifest
+pub async fn build_image_from_recipe(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    k8s_client_opt: Option<kube::Client>,
+    recipe_name_or_id: &str,
+    image_name: &str,
+    public_key_selector: &PublicKeySelector<'_>,
+) -> Result<RecipeBuild, Error> {
+    let recipe = get_by_name_or_id(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        recipe_name_or_id,
+    )
+    .await?;
+
+    let recipe_id = recipe.id.clone().unwrap_or_default();
+
+    log::info!("IMS recipe '{}' ({}) selected", recipe.name, recipe_id);
+
+    let public_key_id = get_or_create_public_key_id(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        public_key_selector,
+    )
+    .await?;
+
+    let ims_job = JobPostRequest {
+        job_type: "create".to_string(),
+        image_root_archive_name: image_name.to_string(),
+        kernel_file_name: None,
+        initrd_file_name: None,
+        kernel_parameters_file_name: None,
+        artifact_id: recipe_id,
+        public_key_id,
+        ssh_containers: None,
+        enable_debug: Some(false),
+        build_env_size: None,
+    };
+
+    log::debug!("Create IMS job request payload:\n{:#?}", ims_job);
+
+    let ims_job_value: Value =
+        ims::job::http_client::post(shasta_token, shasta_base_url, shasta_root_cert, &ims_job)
+            .await?;
+
+    let ims_job_id = ims_job_value["id"]
+        .as_str()
+        .ok_or_else(|| Error::Message("IMS did not return the id of the job created".to_string()))?
+        .to_string();
+
+    log::info!("IMS job '{}' created", ims_job_id);
+
+    if let Some(k8s_client) = k8s_client_opt {
+        let kubernetes_job = ims_job_value["kubernetes_job"].as_str().unwrap_or_default();
+        let kubernetes_namespace = ims_job_value["kubernetes_namespace"]
+            .as_str()
+            .unwrap_or("ims");
+
+        // Logs are informative, the job outcome is what counts
+        if let Err(error) =
+            kubernetes::print_ims_job_logs(k8s_client, kubernetes_job, kubernetes_namespace).await
+        {
+            log::warn!(
+                "Could not stream logs for IMS job '{}'. Reason:\n{}",
+                ims_job_id,
+                error
+            );
+        }
+    }
+
+    let ims_job_value = wait_ims_job_to_complete(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        &ims_job_id,
+        IMS_JOB_TIMEOUT,
+    )
+    .await?;
+
+    if ims_job_value["status"].as_str() != Some("success") {
+        return Err(Error::Message(format!(
+            "IMS job '{}' finished with status '{}'",
+            ims_job_id,
+            ims_job_value["status"].as_str().unwrap_or_default()
+        )));
+    }
+
+    let image_id = ims_job_value["resultant_image_id"]
+        .as_str()
+        .ok_or_else(|| {
+            Error::Message(format!("IMS job '{}' did not produce an image", ims_job_id))
+        })?;
+
+    let image = ims::image::mesa::http_client::get(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        Some(image_id),
+    )
+    .await?
+    .into_iter()
+    .next()
+    .ok_or_else(|| Error::Message(format!("IMS image '{}' not found", image_id)))?;
+
+    let sts_value = ims::s3::s3_auth(shasta_token, shasta_base_url, shasta_root_cert).await?;
+
+    let manifest: ImageManifest =
+        serde_json::from_slice(&get_manifest_bytes(&sts_value, &image).await?)?;
+
+    Ok(RecipeBuild { image, manifest })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipe(id: &str, name: &str) -> RecipeGetResponse {
+        RecipeGetResponse {
+            id: Some(id.to_string()),
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_select_by_name_or_id() {
+        let recipe_vec = vec![
+            recipe("6c644208-104a-473d-802c-410219026335", "cos-2.5"),
+            recipe("59e0180a-3fdd-4936-bba7-14ba914ffd34", "cos-2.5"),
+            // Recipe named after the id of another recipe
+            recipe("1f6cd9b1-0f2e-4a4b-9e3d-6e2b5f8a7c10", "uan-2.6"),
+            recipe(
+                "8d2f5a43-7c1e-4b9a-a0d6-3e4f5a6b7c8d",
+                "1f6cd9b1-0f2e-4a4b-9e3d-6e2b5f8a7c10",
+            ),
+        ];
+
+        // Id wins over name
+        assert_eq!(
+            select_by_name_or_id(recipe_vec.clone(), "1f6cd9b1-0f2e-4a4b-9e3d-6e2b5f8a7c10")
+                .unwrap()
+                .name,
+            "uan-2.6"
+        );
+
+        assert_eq!(
+            select_by_name_or_id(recipe_vec.clone(), "uan-2.6")
+                .unwrap()
+                .id
+                .as_deref(),
+            Some("1f6cd9b1-0f2e-4a4b-9e3d-6e2b5f8a7c10")
+        );
+
+        // Ambiguous name
+        assert!(matches!(
+            select_by_name_or_id(recipe_vec.clone(), "cos-2.5"),
+            Err(Error::Message(message)) if message.contains("59e0180a-3fdd-4936-bba7-14ba914ffd34")
+        ));
+
+        assert!(select_by_name_or_id(recipe_vec, "sles15").is_err());
+    }
+}
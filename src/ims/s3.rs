@@ -11,6 +11,16 @@ use anyhow::Result;
 use aws_sdk_s3::{primitives::ByteStream, Client};
 use indicatif::{ProgressBar, ProgressStyle};
 
+/// Size of the parts of S3 multipart uploads in bytes, S3 needs parts of 5MB min
+pub const MULTIPART_CHUNK_SIZE: u64 = 1024 * 1024 * 5;
+
+/// Returns bucket and key from a S3 path like 's3://boot-images/<image id>/manifest.json'
+pub fn parse_s3_path(path: &str) -> Option<(&str, &str)> {
+    path.strip_prefix("s3://")?
+        .split_once('/')
+        .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty())
+}
+
 pub const BAR_FORMAT: &str = "[{elapsed_precise}] {bar:40.cyan/blue} ({bytes_per_sec}) {bytes:>7}/{total_bytes:7} {msg} [ETA {eta}]";
 // Get a token for S3 and return the result
 // If something breaks, return an error
@@ -132,12 +142,56 @@ pub async fn s3_get_object_size(
     bucket: &str,
 ) -> Result<i64, Box<dyn Error>> {
     let client = setup_client(sts_value).await;
-    match client.get_object().bucket(bucket).key(key).send().await {
-        Ok(object) => Ok(object.content_length().unwrap()),
-        Err(e) => panic!("Error, unable to get object size from s3. Error msg: {}", e),
+    let object = client.get_object().bucket(bucket).key(key).send().await?;
+
+    object.content_length().ok_or_else(|| {
+        format!(
+            "Error, unable to get object size from s3. Object 's3://{}/{}' has no content length",
+            bucket, key
+        )
+        .into()
+    })
+}
+
+/// Checks if an object exists in S3
+/// path of the object: s3://bucket/key
+/// returns bool or error if S3 could not be queried
+pub async fn s3_object_exists(
+    sts_value: &Value,
+    key: &str,
+    bucket: &str,
+) -> Result<bool, Box<dyn Error>> {
+    let client = setup_client(sts_value).await;
+    match client.head_object().bucket(bucket).key(key).send().await {
+        Ok(_) => Ok(true),
+        Err(error)
+            if error
+                .as_service_error()
+                .is_some_and(|service_error| service_error.is_not_found()) =>
+        {
+            Ok(false)
+        }
+        Err(error) => Err(Box::new(error)),
     }
 }
 
+/// Gets the etag of a given object in S3
+/// path of the object: s3://bucket/key
+/// returns the etag without surrounding double quotes or error
+pub async fn s3_get_object_etag(
+    sts_value: &Value,
+    key: &str,
+    bucket: &str,
+) -> Result<String, Box<dyn Error>> {
+    let client = setup_client(sts_value).await;
+    let object = client.head_object().bucket(bucket).key(key).send().await?;
+
+    object
+        .e_tag()
+        .map(|etag| etag.trim_matches('"').to_string())
+        .ok_or_else(|| format!("Object 's3://{}/{}' has no etag", bucket, key).into())
+}
+
 /// Gets an object from S3
 ///
 /// # Needs
@@ -200,6 +254,63 @@ pub async fn s3_download_object(
     Ok(file_path.to_string_lossy().to_string())
 }
 
+/// Gets the content of an object in S3
+///
+/// # Needs
+/// - `sts_value` the temporary S3 token obtained from STS via `s3_auth()`
+/// - `object_path` path within the bucket in S3 of the object e.g. `392o1h-1-234-w1/manifest.json`
+/// - `bucket` bucket where the object is contained.
+/// # Returns
+///   * Vec<u8>: content of the object OR
+///   * Box<dyn Error>: descriptive error if not possible to get the object
+pub async fn s3_get_object_bytes(
+    sts_value: &Value,
+    object_path: &str,
+    bucket: &str,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let client = setup_client(sts_value).await;
+
+    let object = client
+        .get_object()
+        .bucket(bucket)
+        .key(object_path)
+        .send()
+        .await?;
+
+    Ok(object.body.collect().await?.into_bytes().to_vec())
+}
+
+/// Uploads content to an object in S3
+///
+/// # Needs
+/// - `sts_value` the temporary S3 token obtained from STS via `s3_auth()`
+/// - `object_path` path within the bucket in S3 of the object e.g. `392o1h-1-234-w1/manifest.json`
+/// - `bucket` bucket where the object will be stored
+/// - `content` content of the object
+/// # Returns
+///   * String: etag of the object uploaded OR
+///   * Box<dyn Error>: descriptive error if not possible to upload the object
+pub async fn s3_put_object_bytes(
+    sts_value: &Value,
+    object_path: &str,
+    bucket: &str,
+    content: Vec<u8>,
+) -> Result<String, Box<dyn Error>> {
+    let client = setup_client(sts_value).await;
+
+    let put_object_output = client
+        .put_object()
+        .bucket(bucket)
+        .key(object_path)
+        .body(ByteStream::from(content))
+        .send()
+        .await?;
+
+    log::debug!("Uploaded object '{}' successfully", object_path);
+
+    Ok(put_object_output.e_tag.unwrap_or_default())
+}
+
 /// Uploads an object to S3
 ///
 /// # Needs
@@ -289,25 +400,14 @@ pub async fn s3_multipart_upload_object(
 
     let client = setup_client(sts_value).await;
 
-    //In bytes, minimum chunk size of 5MB. Increase CHUNK_SIZE to send larger chunks.
-    const CHUNK_SIZE: u64 = 1024 * 1024 * 5;
+    //In bytes, minimum chunk size of 5MB. Increase MULTIPART_CHUNK_SIZE to send larger chunks.
+    const CHUNK_SIZE: u64 = MULTIPART_CHUNK_SIZE;
     const MAX_CHUNKS: u64 = 10000;
 
-    // create multipart upload
-    let multipart_upload_res: CreateMultipartUploadOutput = client
-        .create_multipart_upload()
-        .bucket(bucket)
-        .key(object_path)
-        .send()
-        .await
-        .unwrap();
-
-    let upload_id = multipart_upload_res.upload_id().unwrap();
-
     // Get details of the upload, this is needed because multipart uploads
     // are tricky and have a minimum chunk size of 5MB
     let path = Path::new(&file_path);
-    let file_size = std::fs::metadata(path).expect("it exists I swear").len();
+    let file_size = std::fs::metadata(path)?.len();
 
     let mut chunk_count = (file_size / CHUNK_SIZE) + 1;
     let mut size_of_last_chunk = file_size % CHUNK_SIZE;
@@ -316,66 +416,124 @@ pub async fn s3_multipart_upload_object(
         chunk_count -= 1;
     }
 
-    let bar = ProgressBar::new(file_size);
-    bar.set_style(ProgressStyle::with_template(BAR_FORMAT).unwrap());
-
     if file_size == 0 {
-        panic!("Bad file size.");
+        return Err(format!("File '{}' is empty", file_path).into());
     }
     if chunk_count > MAX_CHUNKS {
-        panic!("Too many chunks! Try increasing your chunk size.")
+        return Err(format!(
+            "File '{}' needs more than {} chunks of {} bytes",
+            file_path, MAX_CHUNKS, CHUNK_SIZE
+        )
+        .into());
     }
 
-    let mut upload_parts: Vec<CompletedPart> = Vec::new();
+    // create multipart upload
+    let multipart_upload_res: CreateMultipartUploadOutput = client
+        .create_multipart_upload()
+        .bucket(bucket)
+        .key(object_path)
+        .send()
+        .await?;
 
-    for chunk_index in 0..chunk_count {
-        let this_chunk = if chunk_count - 1 == chunk_index {
-            size_of_last_chunk
-        } else {
-            CHUNK_SIZE
-        };
-        let stream = ByteStream::read_from()
-            .path(path)
-            .offset(chunk_index * CHUNK_SIZE)
-            .length(Length::Exact(this_chunk))
-            .build()
-            .await
-            .unwrap();
-        //Chunk index needs to start at 0, but part numbers start at 1.
-        let part_number = (chunk_index as i32) + 1;
-        let upload_part_res = client
-            .upload_part()
-            .key(object_path)
+    let upload_id = multipart_upload_res
+        .upload_id()
+        .ok_or("S3 did not return the id of the multipart upload")?;
+
+    // Parts already uploaded are kept (and billed) by S3 till the upload is aborted
+    let upload_result: Result<String, Box<dyn Error>> = async {
+        let bar = ProgressBar::new(file_size);
+        bar.set_style(ProgressStyle::with_template(BAR_FORMAT)?);
+
+        let mut upload_parts: Vec<CompletedPart> = Vec::new();
+
+        for chunk_index in 0..chunk_count {
+            let this_chunk = if chunk_count - 1 == chunk_index {
+                size_of_last_chunk
+            } else {
+                CHUNK_SIZE
+            };
+            let stream = ByteStream::read_from()
+                .path(path)
+                .offset(chunk_index * CHUNK_SIZE)
+                .length(Length::Exact(this_chunk))
+                .build()
+                .await?;
+            //Chunk index needs to start at 0, but part numbers start at 1.
+            let part_number = (chunk_index as i32) + 1;
+            let upload_part_res = client
+                .upload_part()
+                .key(object_path)
+                .bucket(bucket)
+                .upload_id(upload_id)
+                .body(stream)
+                .part_number(part_number)
+                .send()
+                .await?;
+            upload_parts.push(
+                CompletedPart::builder()
+                    .e_tag(upload_part_res.e_tag.unwrap_or_default())
+                    .part_number(part_number)
+                    .build(),
+            );
+            bar.inc(this_chunk);
+        }
+        // complete the multipart upload
+        let completed_multipart_upload: CompletedMultipartUpload =
+            CompletedMultipartUpload::builder()
+                .set_parts(Some(upload_parts))
+                .build();
+
+        let complete_multipart_upload_res = client
+            .complete_multipart_upload()
             .bucket(bucket)
+            .key(object_path)
+            .multipart_upload(completed_multipart_upload)
             .upload_id(upload_id)
-            .body(stream)
-            .part_number(part_number)
             .send()
             .await?;
-        upload_parts.push(
-            CompletedPart::builder()
-                .e_tag(upload_part_res.e_tag.unwrap_or_default())
-                .part_number(part_number)
-                .build(),
-        );
-        bar.inc(this_chunk);
+
+        bar.finish();
+
+        Ok(complete_multipart_upload_res
+            .e_tag
+            .ok_or("S3 did not return the etag of the object uploaded")?)
     }
-    // complete the multipart upload
-    let completed_multipart_upload: CompletedMultipartUpload = CompletedMultipartUpload::builder()
-        .set_parts(Some(upload_parts))
-        .build();
+    .await;
 
-    let _complete_multipart_upload_res = client
-        .complete_multipart_upload()
-        .bucket(bucket)
-        .key(object_path)
-        .multipart_upload(completed_multipart_upload)
-        .upload_id(upload_id)
-        .send()
-        .await
-        .unwrap();
+    if upload_result.is_err() {
+        if let Err(error) = client
+            .abort_multipart_upload()
+            .bucket(bucket)
+            .key(object_path)
+            .upload_id(upload_id)
+            .send()
+            .await
+        {
+            log::warn!(
+                "Could not abort multipart upload '{}' of 's3://{}/{}'. Reason:\n{}",
+                upload_id,
+                bucket,
+                object_path,
+                error
+            );
+        }
+    }
 
-    bar.finish();
+    upload_result
+}
 
-    Ok(_complete_multipart_upload_res.e_tag.clone().unwrap())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_s3_path() {
+        assert_eq!(
+            parse_s3_path("s3://boot-images/1234/manifest.json"),
+            Some(("boot-images", "1234/manifest.json"))
+        );
+        assert_eq!(parse_s3_path("boot-images/1234/manifest.json"), None);
+        assert_eq!(parse_s3_path("s3://boot-images/"), None);
+        assert_eq!(parse_s3_path("s3:///manifest.json"), None);
+    }
 }
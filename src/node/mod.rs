@@ -1,4 +1,5 @@
 pub mod console;
+pub mod power;
 pub mod r#struct;
 pub mod traits;
 pub mod utils;
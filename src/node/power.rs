@@ -0,0 +1,118 @@
+//! Power management of nodes using the power API available in the CSM site (PCS or CAPMC, see
+//! `common::site_profile`). Calls block till the power operation finishes
+
+use serde_json::Value;
+
+use crate::{
+    capmc,
+    common::site_profile::{self, PowerBackend},
+    error::Error,
+    pcs,
+};
+
+pub async fn power_on(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: Vec<String>,
+    reason_opt: Option<String>,
+) -> Result<Value, Error> {
+    match site_profile::get(shasta_token, shasta_base_url, shasta_root_cert)
+        .await?
+        .get_power_backend()?
+    {
+        PowerBackend::Pcs => {
+            pcs::transitions::http_client::post_block(
+                shasta_base_url,
+                shasta_token,
+                shasta_root_cert,
+                "on",
+                &xname_vec,
+            )
+            .await
+        }
+        PowerBackend::Capmc => capmc::http_client::node_power_on::post_sync(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            xname_vec,
+            reason_opt,
+        )
+        .await
+        .map_err(Error::NetError),
+    }
+}
+
+pub async fn power_off(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: Vec<String>,
+    reason_opt: Option<String>,
+    force: bool,
+) -> Result<Value, Error> {
+    match site_profile::get(shasta_token, shasta_base_url, shasta_root_cert)
+        .await?
+        .get_power_backend()?
+    {
+        PowerBackend::Pcs => {
+            pcs::transitions::http_client::post_block(
+                shasta_base_url,
+                shasta_token,
+                shasta_root_cert,
+                if force { "force-off" } else { "soft-off" },
+                &xname_vec,
+            )
+            .await
+        }
+        PowerBackend::Capmc => capmc::http_client::node_power_off::post_sync(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            xname_vec,
+            reason_opt,
+            force,
+        )
+        .await
+        .map_err(Error::NetError),
+    }
+}
+
+pub async fn power_reset(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: Vec<String>,
+    reason_opt: Option<String>,
+    force: bool,
+) -> Result<Value, Error> {
+    match site_profile::get(shasta_token, shasta_base_url, shasta_root_cert)
+        .await?
+        .get_power_backend()?
+    {
+        PowerBackend::Pcs => {
+            pcs::transitions::http_client::post_block(
+                shasta_base_url,
+                shasta_token,
+                shasta_root_cert,
+                if force {
+                    "hard-restart"
+                } else {
+                    "soft-restart"
+                },
+                &xname_vec,
+            )
+            .await
+        }
+        PowerBackend::Capmc => capmc::http_client::node_power_reset::post_sync_vec(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            xname_vec,
+            reason_opt,
+            force,
+        )
+        .await
+        .map_err(Error::NetError),
+    }
+}